@@ -0,0 +1,114 @@
+use std::{error::Error, fmt};
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use surgeflow_types::Project;
+use uuid::Uuid;
+
+/// The queues a message can be dead-lettered from. Each one has its own dead-letter queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Queue {
+    NextStep,
+    ActiveStep,
+    CompletedStep,
+    FailedStep,
+    Event,
+    NewInstance,
+    CompletedInstance,
+    FailedInstance,
+}
+
+impl fmt::Display for Queue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Queue::NextStep => "next-step",
+            Queue::ActiveStep => "active-step",
+            Queue::CompletedStep => "completed-step",
+            Queue::FailedStep => "failed-step",
+            Queue::Event => "event",
+            Queue::NewInstance => "new-instance",
+            Queue::CompletedInstance => "completed-instance",
+            Queue::FailedInstance => "failed-instance",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub struct DeadLetterId(Uuid);
+
+impl fmt::Display for DeadLetterId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.hyphenated())
+    }
+}
+
+impl DeadLetterId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+impl Default for DeadLetterId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A message that was pulled out of its queue because it could not be processed.
+/// The payload is kept as JSON so that messages which no longer deserialize (e.g. after a
+/// deploy changed a step's shape) can still be inspected and redriven.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DeadLetter {
+    pub id: DeadLetterId,
+    pub queue: Queue,
+    pub payload: serde_json::Value,
+    pub reason: String,
+    pub delivery_count: u32,
+    pub dead_lettered_at: DateTime<Utc>,
+}
+
+/// Adapters that fail to deserialize a received message should dead-letter its raw payload
+/// through this manager themselves, since the worker never gets to see it.
+pub trait DeadLetterManager<P: Project>: Sized + Send + Sync + 'static + Clone {
+    type Error: Error + Send + Sync + 'static;
+
+    fn dead_letter(
+        &self,
+        queue: Queue,
+        payload: serde_json::Value,
+        reason: String,
+        delivery_count: u32,
+    ) -> impl Future<Output = Result<DeadLetterId, Self::Error>> + Send;
+
+    /// Lists the dead letters of `queue`, oldest first, starting after `after` if given.
+    fn list(
+        &self,
+        queue: Queue,
+        after: Option<DeadLetterId>,
+        limit: u32,
+    ) -> impl Future<Output = Result<Vec<DeadLetter>, Self::Error>> + Send;
+
+    fn get(
+        &self,
+        queue: Queue,
+        id: DeadLetterId,
+    ) -> impl Future<Output = Result<Option<DeadLetter>, Self::Error>> + Send;
+
+    /// Returns `false` if there was no such dead letter.
+    fn delete(
+        &self,
+        queue: Queue,
+        id: DeadLetterId,
+    ) -> impl Future<Output = Result<bool, Self::Error>> + Send;
+
+    /// Publishes the payload back to its original queue and removes the dead letter.
+    /// Returns `false` if there was no such dead letter.
+    fn redrive(
+        &self,
+        queue: Queue,
+        id: DeadLetterId,
+    ) -> impl Future<Output = Result<bool, Self::Error>> + Send;
+}
@@ -3,7 +3,9 @@ use std::marker::PhantomData;
 use surgeflow_types::Project;
 
 use crate::{
+    dead_letters::DeadLetterManager,
     managers::PersistenceManager,
+    notifications::NotificationSender,
     receivers::ActiveStepReceiver,
     senders::{ActiveStepSender, CompletedStepSender, FailedStepSender},
 };
@@ -15,6 +17,8 @@ pub struct ActiveStepWorkerDependencies<
     FailedStepSenderT,
     CompletedStepSenderT,
     PersistenceManagerT,
+    DeadLetterManagerT,
+    NotificationSenderT,
 > where
     P: Project,
     ActiveStepReceiverT: ActiveStepReceiver<P>,
@@ -22,12 +26,17 @@ pub struct ActiveStepWorkerDependencies<
     FailedStepSenderT: FailedStepSender<P>,
     CompletedStepSenderT: CompletedStepSender<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     pub active_step_receiver: ActiveStepReceiverT,
     pub active_step_sender: ActiveStepSenderT,
     pub failed_step_sender: FailedStepSenderT,
     pub completed_step_sender: CompletedStepSenderT,
     pub persistence_manager: PersistenceManagerT,
+    pub dead_letter_manager: DeadLetterManagerT,
+    /// `None` unless the adapter supports notifications.
+    pub notification_sender: Option<NotificationSenderT>,
     _marker: PhantomData<P>,
 }
 
@@ -38,6 +47,8 @@ impl<
     FailedStepSenderT,
     CompletedStepSenderT,
     PersistenceManagerT,
+    DeadLetterManagerT,
+    NotificationSenderT,
 >
     ActiveStepWorkerDependencies<
         P,
@@ -46,6 +57,8 @@ impl<
         FailedStepSenderT,
         CompletedStepSenderT,
         PersistenceManagerT,
+        DeadLetterManagerT,
+        NotificationSenderT,
     >
 where
     P: Project,
@@ -54,6 +67,8 @@ where
     FailedStepSenderT: FailedStepSender<P>,
     CompletedStepSenderT: CompletedStepSender<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     pub fn new(
         active_step_receiver: ActiveStepReceiverT,
@@ -61,6 +76,8 @@ where
         failed_step_sender: FailedStepSenderT,
         completed_step_sender: CompletedStepSenderT,
         persistence_manager: PersistenceManagerT,
+        dead_letter_manager: DeadLetterManagerT,
+        notification_sender: Option<NotificationSenderT>,
     ) -> Self {
         Self {
             active_step_receiver,
@@ -68,6 +85,8 @@ where
             failed_step_sender,
             completed_step_sender,
             persistence_manager,
+            dead_letter_manager,
+            notification_sender,
             _marker: PhantomData,
         }
     }
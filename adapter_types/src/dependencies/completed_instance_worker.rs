@@ -2,26 +2,58 @@ use std::marker::PhantomData;
 
 use surgeflow_types::Project;
 
-use crate::receivers::CompletedInstanceReceiver;
+use crate::{
+    dead_letters::DeadLetterManager, managers::PersistenceManager,
+    notifications::NotificationSender, receivers::CompletedInstanceReceiver,
+};
 
-pub struct CompletedInstanceWorkerDependencies<P, CompletedInstanceReceiverT>
-where
+pub struct CompletedInstanceWorkerDependencies<
+    P,
+    CompletedInstanceReceiverT,
+    PersistenceManagerT,
+    DeadLetterManagerT,
+    NotificationSenderT,
+> where
     P: Project,
     CompletedInstanceReceiverT: CompletedInstanceReceiver<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     pub completed_instance_receiver: CompletedInstanceReceiverT,
+    pub persistence_manager: PersistenceManagerT,
+    pub dead_letter_manager: DeadLetterManagerT,
+    /// `None` unless the adapter supports notifications.
+    pub notification_sender: Option<NotificationSenderT>,
     marker: PhantomData<P>,
 }
 
-impl<P, CompletedInstanceReceiverT>
-    CompletedInstanceWorkerDependencies<P, CompletedInstanceReceiverT>
+impl<P, CompletedInstanceReceiverT, PersistenceManagerT, DeadLetterManagerT, NotificationSenderT>
+    CompletedInstanceWorkerDependencies<
+        P,
+        CompletedInstanceReceiverT,
+        PersistenceManagerT,
+        DeadLetterManagerT,
+        NotificationSenderT,
+    >
 where
     P: Project,
     CompletedInstanceReceiverT: CompletedInstanceReceiver<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
-    pub fn new(completed_instance_receiver: CompletedInstanceReceiverT) -> Self {
+    pub fn new(
+        completed_instance_receiver: CompletedInstanceReceiverT,
+        persistence_manager: PersistenceManagerT,
+        dead_letter_manager: DeadLetterManagerT,
+        notification_sender: Option<NotificationSenderT>,
+    ) -> Self {
         Self {
             completed_instance_receiver,
+            persistence_manager,
+            dead_letter_manager,
+            notification_sender,
             marker: PhantomData,
         }
     }
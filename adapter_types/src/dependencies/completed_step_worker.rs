@@ -3,42 +3,91 @@ use std::marker::PhantomData;
 use surgeflow_types::Project;
 
 use crate::{
-    managers::PersistenceManager, receivers::CompletedStepReceiver, senders::NextStepSender,
+    dead_letters::DeadLetterManager,
+    managers::PersistenceManager,
+    notifications::NotificationSender,
+    outbox::Outbox,
+    receivers::CompletedStepReceiver,
+    senders::{CompletedInstanceSender, NextStepSender},
 };
 
 pub struct CompletedStepWorkerDependencies<
     P,
     CompletedStepReceiverT,
     NextStepSenderT,
+    CompletedInstanceSenderT,
     PersistenceManagerT,
+    DeadLetterManagerT,
+    OutboxT,
+    NotificationSenderT,
 > where
     P: Project,
     CompletedStepReceiverT: CompletedStepReceiver<P>,
     NextStepSenderT: NextStepSender<P>,
+    CompletedInstanceSenderT: CompletedInstanceSender<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    OutboxT: Outbox<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     pub completed_step_receiver: CompletedStepReceiverT,
     pub next_step_sender: NextStepSenderT,
+    pub completed_instance_sender: CompletedInstanceSenderT,
     pub persistence_manager: PersistenceManagerT,
+    pub dead_letter_manager: DeadLetterManagerT,
+    /// `None` unless the adapter opted in to the outbox.
+    pub outbox: Option<OutboxT>,
+    /// `None` unless the adapter supports notifications.
+    pub notification_sender: Option<NotificationSenderT>,
     marker: PhantomData<P>,
 }
 
-impl<P: Project, CompletedStepReceiverT, NextStepSenderT, PersistenceManagerT>
-    CompletedStepWorkerDependencies<P, CompletedStepReceiverT, NextStepSenderT, PersistenceManagerT>
+impl<
+    P: Project,
+    CompletedStepReceiverT,
+    NextStepSenderT,
+    CompletedInstanceSenderT,
+    PersistenceManagerT,
+    DeadLetterManagerT,
+    OutboxT,
+    NotificationSenderT,
+>
+    CompletedStepWorkerDependencies<
+        P,
+        CompletedStepReceiverT,
+        NextStepSenderT,
+        CompletedInstanceSenderT,
+        PersistenceManagerT,
+        DeadLetterManagerT,
+        OutboxT,
+        NotificationSenderT,
+    >
 where
     CompletedStepReceiverT: CompletedStepReceiver<P>,
     NextStepSenderT: NextStepSender<P>,
+    CompletedInstanceSenderT: CompletedInstanceSender<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    OutboxT: Outbox<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     pub fn new(
         completed_step_receiver: CompletedStepReceiverT,
         next_step_sender: NextStepSenderT,
+        completed_instance_sender: CompletedInstanceSenderT,
         persistence_manager: PersistenceManagerT,
+        dead_letter_manager: DeadLetterManagerT,
+        outbox: Option<OutboxT>,
+        notification_sender: Option<NotificationSenderT>,
     ) -> Self {
         Self {
             completed_step_receiver,
             next_step_sender,
+            completed_instance_sender,
             persistence_manager,
+            dead_letter_manager,
+            outbox,
+            notification_sender,
             marker: PhantomData,
         }
     }
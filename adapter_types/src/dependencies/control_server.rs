@@ -2,29 +2,101 @@ use std::marker::PhantomData;
 
 use surgeflow_types::Project;
 
-use crate::senders::{EventSender, NewInstanceSender};
+use crate::{
+    dead_letters::DeadLetterManager,
+    idempotency::IdempotencyStore,
+    managers::{PersistenceManager, StepsAwaitingEventManager},
+    notifications::NotificationReceiver,
+    registry::WorkerRegistry,
+    senders::{EventSender, NewInstanceSender},
+};
 
-pub struct ControlServerDependencies<P, EventSenderT, NewInstanceSenderT>
-where
+pub struct ControlServerDependencies<
+    P,
+    EventSenderT,
+    NewInstanceSenderT,
+    DeadLetterManagerT,
+    PersistenceManagerT,
+    StepsAwaitingEventManagerT,
+    NotificationReceiverT,
+    IdempotencyStoreT,
+    WorkerRegistryT,
+> where
     P: Project,
     EventSenderT: EventSender<P>,
     NewInstanceSenderT: NewInstanceSender<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+    StepsAwaitingEventManagerT: StepsAwaitingEventManager<P>,
+    NotificationReceiverT: NotificationReceiver<P>,
+    IdempotencyStoreT: IdempotencyStore<P>,
+    WorkerRegistryT: WorkerRegistry<P>,
 {
     pub event_sender: EventSenderT,
     pub new_instance_sender: NewInstanceSenderT,
+    pub dead_letter_manager: DeadLetterManagerT,
+    pub persistence_manager: PersistenceManagerT,
+    pub steps_awaiting_event_manager: StepsAwaitingEventManagerT,
+    /// `None` unless the adapter supports notifications.
+    pub notification_receiver: Option<NotificationReceiverT>,
+    pub idempotency_store: IdempotencyStoreT,
+    /// `None` unless the adapter has a worker registry.
+    pub worker_registry: Option<WorkerRegistryT>,
     _marker: PhantomData<P>,
 }
-impl<P, EventSenderT, NewInstanceSenderT>
-    ControlServerDependencies<P, EventSenderT, NewInstanceSenderT>
+impl<
+    P,
+    EventSenderT,
+    NewInstanceSenderT,
+    DeadLetterManagerT,
+    PersistenceManagerT,
+    StepsAwaitingEventManagerT,
+    NotificationReceiverT,
+    IdempotencyStoreT,
+    WorkerRegistryT,
+>
+    ControlServerDependencies<
+        P,
+        EventSenderT,
+        NewInstanceSenderT,
+        DeadLetterManagerT,
+        PersistenceManagerT,
+        StepsAwaitingEventManagerT,
+        NotificationReceiverT,
+        IdempotencyStoreT,
+        WorkerRegistryT,
+    >
 where
     P: Project,
     EventSenderT: EventSender<P>,
     NewInstanceSenderT: NewInstanceSender<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+    StepsAwaitingEventManagerT: StepsAwaitingEventManager<P>,
+    NotificationReceiverT: NotificationReceiver<P>,
+    IdempotencyStoreT: IdempotencyStore<P>,
+    WorkerRegistryT: WorkerRegistry<P>,
 {
-    pub fn new(event_sender: EventSenderT, new_instance_sender: NewInstanceSenderT) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        event_sender: EventSenderT,
+        new_instance_sender: NewInstanceSenderT,
+        dead_letter_manager: DeadLetterManagerT,
+        persistence_manager: PersistenceManagerT,
+        steps_awaiting_event_manager: StepsAwaitingEventManagerT,
+        notification_receiver: Option<NotificationReceiverT>,
+        idempotency_store: IdempotencyStoreT,
+        worker_registry: Option<WorkerRegistryT>,
+    ) -> Self {
         Self {
             event_sender,
             new_instance_sender,
+            dead_letter_manager,
+            persistence_manager,
+            steps_awaiting_event_manager,
+            notification_receiver,
+            idempotency_store,
+            worker_registry,
             _marker: PhantomData,
         }
     }
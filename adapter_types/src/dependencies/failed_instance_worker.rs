@@ -2,25 +2,58 @@ use std::marker::PhantomData;
 
 use surgeflow_types::Project;
 
-use crate::receivers::FailedInstanceReceiver;
+use crate::{
+    dead_letters::DeadLetterManager, managers::PersistenceManager,
+    notifications::NotificationSender, receivers::FailedInstanceReceiver,
+};
 
-pub struct FailedInstanceWorkerDependencies<P, FailedInstanceReceiverT>
-where
+pub struct FailedInstanceWorkerDependencies<
+    P,
+    FailedInstanceReceiverT,
+    PersistenceManagerT,
+    DeadLetterManagerT,
+    NotificationSenderT,
+> where
     P: Project,
     FailedInstanceReceiverT: FailedInstanceReceiver<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     pub failed_instance_receiver: FailedInstanceReceiverT,
+    pub persistence_manager: PersistenceManagerT,
+    pub dead_letter_manager: DeadLetterManagerT,
+    /// `None` unless the adapter supports notifications.
+    pub notification_sender: Option<NotificationSenderT>,
     marker: PhantomData<P>,
 }
 
-impl<P, FailedInstanceReceiverT> FailedInstanceWorkerDependencies<P, FailedInstanceReceiverT>
+impl<P, FailedInstanceReceiverT, PersistenceManagerT, DeadLetterManagerT, NotificationSenderT>
+    FailedInstanceWorkerDependencies<
+        P,
+        FailedInstanceReceiverT,
+        PersistenceManagerT,
+        DeadLetterManagerT,
+        NotificationSenderT,
+    >
 where
     P: Project,
     FailedInstanceReceiverT: FailedInstanceReceiver<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
-    pub fn new(failed_instance_receiver: FailedInstanceReceiverT) -> Self {
+    pub fn new(
+        failed_instance_receiver: FailedInstanceReceiverT,
+        persistence_manager: PersistenceManagerT,
+        dead_letter_manager: DeadLetterManagerT,
+        notification_sender: Option<NotificationSenderT>,
+    ) -> Self {
         Self {
             failed_instance_receiver,
+            persistence_manager,
+            dead_letter_manager,
+            notification_sender,
             marker: PhantomData,
         }
     }
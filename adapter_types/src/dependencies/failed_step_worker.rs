@@ -3,7 +3,9 @@ use std::marker::PhantomData;
 use surgeflow_types::Project;
 
 use crate::{
-    managers::PersistenceManager, receivers::FailedStepReceiver, senders::FailedInstanceSender,
+    dead_letters::DeadLetterManager, managers::PersistenceManager,
+    notifications::NotificationSender, outbox::Outbox, receivers::FailedStepReceiver,
+    senders::FailedInstanceSender,
 };
 
 pub struct FailedStepWorkerDependencies<
@@ -11,35 +13,71 @@ pub struct FailedStepWorkerDependencies<
     FailedStepReceiverT,
     FailedInstanceSenderT,
     PersistenceManagerT,
+    DeadLetterManagerT,
+    OutboxT,
+    NotificationSenderT,
 > where
     P: Project,
     FailedStepReceiverT: FailedStepReceiver<P>,
     FailedInstanceSenderT: FailedInstanceSender<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    OutboxT: Outbox<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     pub failed_step_receiver: FailedStepReceiverT,
     pub failed_instance_sender: FailedInstanceSenderT,
     pub persistence_manager: PersistenceManagerT,
+    pub dead_letter_manager: DeadLetterManagerT,
+    /// `None` unless the adapter opted in to the outbox.
+    pub outbox: Option<OutboxT>,
+    /// `None` unless the adapter supports notifications.
+    pub notification_sender: Option<NotificationSenderT>,
     marker: PhantomData<P>,
 }
 
-impl<P, FailedStepReceiverT, FailedInstanceSenderT, PersistenceManagerT>
-    FailedStepWorkerDependencies<P, FailedStepReceiverT, FailedInstanceSenderT, PersistenceManagerT>
+impl<
+    P,
+    FailedStepReceiverT,
+    FailedInstanceSenderT,
+    PersistenceManagerT,
+    DeadLetterManagerT,
+    OutboxT,
+    NotificationSenderT,
+>
+    FailedStepWorkerDependencies<
+        P,
+        FailedStepReceiverT,
+        FailedInstanceSenderT,
+        PersistenceManagerT,
+        DeadLetterManagerT,
+        OutboxT,
+        NotificationSenderT,
+    >
 where
     P: Project,
     FailedStepReceiverT: FailedStepReceiver<P>,
     FailedInstanceSenderT: FailedInstanceSender<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    OutboxT: Outbox<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     pub fn new(
         failed_step_receiver: FailedStepReceiverT,
         failed_instance_sender: FailedInstanceSenderT,
         persistence_manager: PersistenceManagerT,
+        dead_letter_manager: DeadLetterManagerT,
+        outbox: Option<OutboxT>,
+        notification_sender: Option<NotificationSenderT>,
     ) -> Self {
         Self {
             failed_step_receiver,
             failed_instance_sender,
             persistence_manager,
+            dead_letter_manager,
+            outbox,
+            notification_sender,
             marker: PhantomData,
         }
     }
@@ -1,13 +1,18 @@
 use std::error::Error;
 
+use super::dead_letters::DeadLetterManager;
+use super::idempotency::IdempotencyStore;
 use super::managers::{PersistenceManager, StepsAwaitingEventManager};
+use super::notifications::{NotificationReceiver, NotificationSender};
+use super::outbox::Outbox;
 use super::receivers::{
     ActiveStepReceiver, CompletedInstanceReceiver, CompletedStepReceiver, EventReceiver,
     FailedInstanceReceiver, FailedStepReceiver, NewInstanceReceiver, NextStepReceiver,
 };
+use super::registry::WorkerRegistry;
 use super::senders::{
-    ActiveStepSender, CompletedStepSender, EventSender, FailedInstanceSender, FailedStepSender,
-    NewInstanceSender, NextStepSender,
+    ActiveStepSender, CompletedInstanceSender, CompletedStepSender, EventSender,
+    FailedInstanceSender, FailedStepSender, NewInstanceSender, NextStepSender,
 };
 
 use active_step_worker::ActiveStepWorkerDependencies;
@@ -19,7 +24,9 @@ use failed_step_worker::FailedStepWorkerDependencies;
 use new_event_worker::NewEventWorkerDependencies;
 use new_instance_worker::NewInstanceWorkerDependencies;
 use next_step_worker::NextStepWorkerDependencies;
-use surgeflow_types::Project;
+use outbox_relay_worker::OutboxRelayWorkerDependencies;
+use reaper_worker::ReaperWorkerDependencies;
+use surgeflow_types::{Project, TaskQueue};
 
 pub mod control_server;
 
@@ -31,6 +38,20 @@ pub mod failed_step_worker;
 pub mod new_event_worker;
 pub mod new_instance_worker;
 pub mod next_step_worker;
+pub mod outbox_relay_worker;
+pub mod reaper_worker;
+
+/// The active step worker's dependencies, as provided by `D`.
+pub type ProvidedActiveStepWorkerDependencies<P, D> = ActiveStepWorkerDependencies<
+    P,
+    <D as ActiveStepWorkerDependencyProvider<P>>::ActiveStepReceiver,
+    <D as ActiveStepWorkerDependencyProvider<P>>::ActiveStepSender,
+    <D as ActiveStepWorkerDependencyProvider<P>>::FailedStepSender,
+    <D as ActiveStepWorkerDependencyProvider<P>>::CompletedStepSender,
+    <D as ActiveStepWorkerDependencyProvider<P>>::PersistenceManager,
+    <D as ActiveStepWorkerDependencyProvider<P>>::DeadLetterManager,
+    <D as ActiveStepWorkerDependencyProvider<P>>::NotificationSender,
+>;
 
 pub trait ActiveStepWorkerDependencyProvider<P: Project> {
     type ActiveStepReceiver: ActiveStepReceiver<P>;
@@ -38,176 +59,288 @@ pub trait ActiveStepWorkerDependencyProvider<P: Project> {
     type FailedStepSender: FailedStepSender<P>;
     type CompletedStepSender: CompletedStepSender<P>;
     type PersistenceManager: PersistenceManager<P>;
+    type DeadLetterManager: DeadLetterManager<P>;
+    type NotificationSender: NotificationSender<P>;
     type Error: Error + Send + Sync + 'static;
 
+    /// The receiver only takes steps from `task_queues`, which is never empty.
     fn active_step_worker_dependencies(
         &mut self,
+        task_queues: &[TaskQueue],
     ) -> impl std::future::Future<
-        Output = Result<
-            ActiveStepWorkerDependencies<
-                P,
-                Self::ActiveStepReceiver,
-                Self::ActiveStepSender,
-                Self::FailedStepSender,
-                Self::CompletedStepSender,
-                Self::PersistenceManager,
-            >,
-            Self::Error,
-        >,
+        Output = Result<ProvidedActiveStepWorkerDependencies<P, Self>, Self::Error>,
     > + Send;
 }
 
+/// The control server's dependencies, as provided by `D`.
+pub type ProvidedControlServerDependencies<P, D> = ControlServerDependencies<
+    P,
+    <D as ControlServerDependencyProvider<P>>::EventSender,
+    <D as ControlServerDependencyProvider<P>>::NewInstanceSender,
+    <D as ControlServerDependencyProvider<P>>::DeadLetterManager,
+    <D as ControlServerDependencyProvider<P>>::PersistenceManager,
+    <D as ControlServerDependencyProvider<P>>::StepsAwaitingEventManager,
+    <D as ControlServerDependencyProvider<P>>::NotificationReceiver,
+    <D as ControlServerDependencyProvider<P>>::IdempotencyStore,
+    <D as ControlServerDependencyProvider<P>>::WorkerRegistry,
+>;
+
 pub trait ControlServerDependencyProvider<P: Project> {
     type Error: Error + Send + Sync + 'static;
 
     type EventSender: EventSender<P>;
     type NewInstanceSender: NewInstanceSender<P>;
+    type DeadLetterManager: DeadLetterManager<P>;
+    /// `Sync` because the control server shares it between requests.
+    type PersistenceManager: PersistenceManager<P> + Sync;
+    /// `Sync` because the control server shares it between requests.
+    type StepsAwaitingEventManager: StepsAwaitingEventManager<P> + Sync;
+    /// `Sync` because it is kept in the dependencies the control server shares between requests.
+    type NotificationReceiver: NotificationReceiver<P> + Sync;
+    /// `Sync` because the control server shares it between requests.
+    type IdempotencyStore: IdempotencyStore<P> + Sync;
+    type WorkerRegistry: WorkerRegistry<P>;
 
     fn control_server_dependencies(
         &mut self,
     ) -> impl std::future::Future<
-        Output = Result<
-            ControlServerDependencies<P, Self::EventSender, Self::NewInstanceSender>,
-            Self::Error,
-        >,
+        Output = Result<ProvidedControlServerDependencies<P, Self>, Self::Error>,
     > + Send;
 }
 
+/// The completed instance worker's dependencies, as provided by `D`.
+pub type ProvidedCompletedInstanceWorkerDependencies<P, D> = CompletedInstanceWorkerDependencies<
+    P,
+    <D as CompletedInstanceWorkerDependencyProvider<P>>::CompletedInstanceReceiver,
+    <D as CompletedInstanceWorkerDependencyProvider<P>>::PersistenceManager,
+    <D as CompletedInstanceWorkerDependencyProvider<P>>::DeadLetterManager,
+    <D as CompletedInstanceWorkerDependencyProvider<P>>::NotificationSender,
+>;
+
 pub trait CompletedInstanceWorkerDependencyProvider<P: Project> {
     type CompletedInstanceReceiver: CompletedInstanceReceiver<P>;
+    type PersistenceManager: PersistenceManager<P>;
+    type DeadLetterManager: DeadLetterManager<P>;
+    type NotificationSender: NotificationSender<P>;
     type Error: Error + Send + Sync + 'static;
 
     fn completed_instance_worker_dependencies(
         &mut self,
     ) -> impl std::future::Future<
-        Output = Result<
-            CompletedInstanceWorkerDependencies<P, Self::CompletedInstanceReceiver>,
-            Self::Error,
-        >,
+        Output = Result<ProvidedCompletedInstanceWorkerDependencies<P, Self>, Self::Error>,
     > + Send;
 }
 
+/// The completed step worker's dependencies, as provided by `D`.
+pub type ProvidedCompletedStepWorkerDependencies<P, D> = CompletedStepWorkerDependencies<
+    P,
+    <D as CompletedStepWorkerDependencyProvider<P>>::CompletedStepReceiver,
+    <D as CompletedStepWorkerDependencyProvider<P>>::NextStepSender,
+    <D as CompletedStepWorkerDependencyProvider<P>>::CompletedInstanceSender,
+    <D as CompletedStepWorkerDependencyProvider<P>>::PersistenceManager,
+    <D as CompletedStepWorkerDependencyProvider<P>>::DeadLetterManager,
+    <D as CompletedStepWorkerDependencyProvider<P>>::Outbox,
+    <D as CompletedStepWorkerDependencyProvider<P>>::NotificationSender,
+>;
+
 pub trait CompletedStepWorkerDependencyProvider<P: Project> {
     type CompletedStepReceiver: CompletedStepReceiver<P>;
     type NextStepSender: NextStepSender<P>;
+    type CompletedInstanceSender: CompletedInstanceSender<P>;
     type PersistenceManager: PersistenceManager<P>;
+    type DeadLetterManager: DeadLetterManager<P>;
+    type Outbox: Outbox<P>;
+    type NotificationSender: NotificationSender<P>;
     type Error: Error + Send + Sync + 'static;
 
     fn completed_step_worker_dependencies(
         &mut self,
     ) -> impl std::future::Future<
-        Output = Result<
-            CompletedStepWorkerDependencies<
-                P,
-                Self::CompletedStepReceiver,
-                Self::NextStepSender,
-                Self::PersistenceManager,
-            >,
-            Self::Error,
-        >,
+        Output = Result<ProvidedCompletedStepWorkerDependencies<P, Self>, Self::Error>,
     > + Send;
 }
 
+/// The failed instance worker's dependencies, as provided by `D`.
+pub type ProvidedFailedInstanceWorkerDependencies<P, D> = FailedInstanceWorkerDependencies<
+    P,
+    <D as FailedInstanceWorkerDependencyProvider<P>>::FailedInstanceReceiver,
+    <D as FailedInstanceWorkerDependencyProvider<P>>::PersistenceManager,
+    <D as FailedInstanceWorkerDependencyProvider<P>>::DeadLetterManager,
+    <D as FailedInstanceWorkerDependencyProvider<P>>::NotificationSender,
+>;
+
 pub trait FailedInstanceWorkerDependencyProvider<P: Project> {
     type FailedInstanceReceiver: FailedInstanceReceiver<P>;
+    type PersistenceManager: PersistenceManager<P>;
+    type DeadLetterManager: DeadLetterManager<P>;
+    type NotificationSender: NotificationSender<P>;
     type Error: Error + Send + Sync + 'static;
 
     fn failed_instance_worker_dependencies(
         &mut self,
     ) -> impl std::future::Future<
-        Output = Result<
-            FailedInstanceWorkerDependencies<P, Self::FailedInstanceReceiver>,
-            Self::Error,
-        >,
+        Output = Result<ProvidedFailedInstanceWorkerDependencies<P, Self>, Self::Error>,
     > + Send;
 }
 
+/// The failed step worker's dependencies, as provided by `D`.
+pub type ProvidedFailedStepWorkerDependencies<P, D> = FailedStepWorkerDependencies<
+    P,
+    <D as FailedStepWorkerDependencyProvider<P>>::FailedStepReceiver,
+    <D as FailedStepWorkerDependencyProvider<P>>::FailedInstanceSender,
+    <D as FailedStepWorkerDependencyProvider<P>>::PersistenceManager,
+    <D as FailedStepWorkerDependencyProvider<P>>::DeadLetterManager,
+    <D as FailedStepWorkerDependencyProvider<P>>::Outbox,
+    <D as FailedStepWorkerDependencyProvider<P>>::NotificationSender,
+>;
+
 pub trait FailedStepWorkerDependencyProvider<P: Project> {
     type FailedStepReceiver: FailedStepReceiver<P>;
     type FailedInstanceSender: FailedInstanceSender<P>;
     type PersistenceManager: PersistenceManager<P>;
+    type DeadLetterManager: DeadLetterManager<P>;
+    type Outbox: Outbox<P>;
+    type NotificationSender: NotificationSender<P>;
     type Error: Error + Send + Sync + 'static;
 
     fn failed_step_worker_dependencies(
         &mut self,
     ) -> impl std::future::Future<
-        Output = Result<
-            FailedStepWorkerDependencies<
-                P,
-                Self::FailedStepReceiver,
-                Self::FailedInstanceSender,
-                Self::PersistenceManager,
-            >,
-            Self::Error,
-        >,
+        Output = Result<ProvidedFailedStepWorkerDependencies<P, Self>, Self::Error>,
     > + Send;
 }
 
+/// The new event worker's dependencies, as provided by `D`.
+pub type ProvidedNewEventWorkerDependencies<P, D> = NewEventWorkerDependencies<
+    P,
+    <D as NewEventWorkerDependencyProvider<P>>::ActiveStepSender,
+    <D as NewEventWorkerDependencyProvider<P>>::EventReceiver,
+    <D as NewEventWorkerDependencyProvider<P>>::StepsAwaitingEventManager,
+    <D as NewEventWorkerDependencyProvider<P>>::DeadLetterManager,
+>;
+
 pub trait NewEventWorkerDependencyProvider<P: Project> {
     type ActiveStepSender: ActiveStepSender<P>;
     type EventReceiver: EventReceiver<P>;
     type StepsAwaitingEventManager: StepsAwaitingEventManager<P>;
+    type DeadLetterManager: DeadLetterManager<P>;
     type Error: Error + Send + Sync + 'static;
 
     fn new_event_worker_dependencies(
         &mut self,
     ) -> impl std::future::Future<
-        Output = Result<
-            NewEventWorkerDependencies<
-                P,
-                Self::ActiveStepSender,
-                Self::EventReceiver,
-                Self::StepsAwaitingEventManager,
-            >,
-            Self::Error,
-        >,
+        Output = Result<ProvidedNewEventWorkerDependencies<P, Self>, Self::Error>,
     > + Send;
 }
 
+/// The new instance worker's dependencies, as provided by `D`.
+pub type ProvidedNewInstanceWorkerDependencies<P, D> = NewInstanceWorkerDependencies<
+    P,
+    <D as NewInstanceWorkerDependencyProvider<P>>::NextStepSender,
+    <D as NewInstanceWorkerDependencyProvider<P>>::NewInstanceReceiver,
+    <D as NewInstanceWorkerDependencyProvider<P>>::PersistenceManager,
+    <D as NewInstanceWorkerDependencyProvider<P>>::DeadLetterManager,
+    <D as NewInstanceWorkerDependencyProvider<P>>::Outbox,
+>;
+
 pub trait NewInstanceWorkerDependencyProvider<P: Project> {
     type NextStepSender: NextStepSender<P>;
     type NewInstanceReceiver: NewInstanceReceiver<P>;
     type PersistenceManager: PersistenceManager<P>;
+    type DeadLetterManager: DeadLetterManager<P>;
+    type Outbox: Outbox<P>;
     type Error: Error + Send + Sync + 'static;
 
     fn new_instance_worker_dependencies(
         &mut self,
     ) -> impl std::future::Future<
-        Output = Result<
-            NewInstanceWorkerDependencies<
-                P,
-                Self::NextStepSender,
-                Self::NewInstanceReceiver,
-                Self::PersistenceManager,
-            >,
-            Self::Error,
-        >,
+        Output = Result<ProvidedNewInstanceWorkerDependencies<P, Self>, Self::Error>,
     > + Send;
 }
 
+/// The next step worker's dependencies, as provided by `D`.
+pub type ProvidedNextStepWorkerDependencies<P, D> = NextStepWorkerDependencies<
+    P,
+    <D as NextStepWorkerDependencyProvider<P>>::NextStepReceiver,
+    <D as NextStepWorkerDependencyProvider<P>>::ActiveStepSender,
+    <D as NextStepWorkerDependencyProvider<P>>::StepsAwaitingEventManager,
+    <D as NextStepWorkerDependencyProvider<P>>::PersistenceManager,
+    <D as NextStepWorkerDependencyProvider<P>>::DeadLetterManager,
+    <D as NextStepWorkerDependencyProvider<P>>::NotificationSender,
+>;
+
 pub trait NextStepWorkerDependencyProvider<P: Project> {
     type NextStepReceiver: NextStepReceiver<P>;
     type ActiveStepSender: ActiveStepSender<P>;
     type StepsAwaitingEventManager: StepsAwaitingEventManager<P>;
     type PersistenceManager: PersistenceManager<P>;
+    type DeadLetterManager: DeadLetterManager<P>;
+    type NotificationSender: NotificationSender<P>;
     type Error: Error + Send + Sync + 'static;
 
     fn next_step_worker_dependencies(
         &mut self,
     ) -> impl std::future::Future<
-        Output = Result<
-            NextStepWorkerDependencies<
-                P,
-                Self::NextStepReceiver,
-                Self::ActiveStepSender,
-                Self::StepsAwaitingEventManager,
-                Self::PersistenceManager,
-            >,
-            Self::Error,
-        >,
+        Output = Result<ProvidedNextStepWorkerDependencies<P, Self>, Self::Error>,
+    > + Send;
+}
+
+/// The outbox relay worker's dependencies, as provided by `D`.
+pub type ProvidedOutboxRelayWorkerDependencies<P, D> = OutboxRelayWorkerDependencies<
+    P,
+    <D as OutboxRelayWorkerDependencyProvider<P>>::Outbox,
+    <D as OutboxRelayWorkerDependencyProvider<P>>::NextStepSender,
+    <D as OutboxRelayWorkerDependencyProvider<P>>::CompletedInstanceSender,
+    <D as OutboxRelayWorkerDependencyProvider<P>>::FailedInstanceSender,
+>;
+
+pub trait OutboxRelayWorkerDependencyProvider<P: Project> {
+    type Outbox: Outbox<P>;
+    type NextStepSender: NextStepSender<P>;
+    type CompletedInstanceSender: CompletedInstanceSender<P>;
+    type FailedInstanceSender: FailedInstanceSender<P>;
+    type Error: Error + Send + Sync + 'static;
+
+    fn outbox_relay_worker_dependencies(
+        &mut self,
+    ) -> impl std::future::Future<
+        Output = Result<ProvidedOutboxRelayWorkerDependencies<P, Self>, Self::Error>,
     > + Send;
 }
 
+/// The reaper worker's dependencies, as provided by `D`.
+pub type ProvidedReaperWorkerDependencies<P, D> = ReaperWorkerDependencies<
+    P,
+    <D as ReaperWorkerDependencyProvider<P>>::NextStepSender,
+    <D as ReaperWorkerDependencyProvider<P>>::ActiveStepSender,
+    <D as ReaperWorkerDependencyProvider<P>>::FailedStepSender,
+    <D as ReaperWorkerDependencyProvider<P>>::PersistenceManager,
+>;
+
+pub trait ReaperWorkerDependencyProvider<P: Project> {
+    type NextStepSender: NextStepSender<P>;
+    type ActiveStepSender: ActiveStepSender<P>;
+    type FailedStepSender: FailedStepSender<P>;
+    type PersistenceManager: PersistenceManager<P>;
+    type Error: Error + Send + Sync + 'static;
+
+    fn reaper_worker_dependencies(
+        &mut self,
+    ) -> impl std::future::Future<
+        Output = Result<ProvidedReaperWorkerDependencies<P, Self>, Self::Error>,
+    > + Send;
+}
+
+pub trait WorkerRegistryDependencyProvider<P: Project> {
+    type WorkerRegistry: WorkerRegistry<P>;
+    type Error: Error + Send + Sync + 'static;
+
+    /// `None` unless the adapter has a worker registry.
+    fn worker_registry(
+        &mut self,
+    ) -> impl std::future::Future<Output = Result<Option<Self::WorkerRegistry>, Self::Error>> + Send;
+}
+
 pub trait DependencyManager<P: Project>:
     Sized
     + ActiveStepWorkerDependencyProvider<P>
@@ -218,6 +351,9 @@ pub trait DependencyManager<P: Project>:
     + NewEventWorkerDependencyProvider<P>
     + NewInstanceWorkerDependencyProvider<P>
     + NextStepWorkerDependencyProvider<P>
+    + OutboxRelayWorkerDependencyProvider<P>
+    + ReaperWorkerDependencyProvider<P>
+    + WorkerRegistryDependencyProvider<P>
     + ControlServerDependencyProvider<P>
 {
     type Error: Error + Send + Sync + 'static;
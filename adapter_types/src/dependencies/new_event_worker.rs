@@ -3,7 +3,8 @@ use std::marker::PhantomData;
 use surgeflow_types::Project;
 
 use crate::{
-    managers::StepsAwaitingEventManager, receivers::EventReceiver, senders::ActiveStepSender,
+    dead_letters::DeadLetterManager, managers::StepsAwaitingEventManager, receivers::EventReceiver,
+    senders::ActiveStepSender,
 };
 
 pub struct NewEventWorkerDependencies<
@@ -11,35 +12,47 @@ pub struct NewEventWorkerDependencies<
     ActiveStepSenderT,
     EventReceiverT,
     StepsAwaitingEventManagerT,
+    DeadLetterManagerT,
 > where
     P: Project,
     ActiveStepSenderT: ActiveStepSender<P>,
     EventReceiverT: EventReceiver<P>,
     StepsAwaitingEventManagerT: StepsAwaitingEventManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
 {
     pub active_step_sender: ActiveStepSenderT,
     pub event_receiver: EventReceiverT,
     pub steps_awaiting_event_manager: StepsAwaitingEventManagerT,
+    pub dead_letter_manager: DeadLetterManagerT,
     marker: PhantomData<P>,
 }
 
-impl<P, ActiveStepSenderT, EventReceiverT, StepsAwaitingEventManagerT>
-    NewEventWorkerDependencies<P, ActiveStepSenderT, EventReceiverT, StepsAwaitingEventManagerT>
+impl<P, ActiveStepSenderT, EventReceiverT, StepsAwaitingEventManagerT, DeadLetterManagerT>
+    NewEventWorkerDependencies<
+        P,
+        ActiveStepSenderT,
+        EventReceiverT,
+        StepsAwaitingEventManagerT,
+        DeadLetterManagerT,
+    >
 where
     P: Project,
     ActiveStepSenderT: ActiveStepSender<P>,
     EventReceiverT: EventReceiver<P>,
     StepsAwaitingEventManagerT: StepsAwaitingEventManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
 {
     pub fn new(
         active_step_sender: ActiveStepSenderT,
         event_receiver: EventReceiverT,
         steps_awaiting_event_manager: StepsAwaitingEventManagerT,
+        dead_letter_manager: DeadLetterManagerT,
     ) -> Self {
         Self {
             active_step_sender,
             event_receiver,
             steps_awaiting_event_manager,
+            dead_letter_manager,
             marker: PhantomData,
         }
     }
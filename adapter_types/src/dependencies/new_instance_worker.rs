@@ -3,7 +3,8 @@ use std::marker::PhantomData;
 use surgeflow_types::Project;
 
 use crate::{
-    managers::PersistenceManager, receivers::NewInstanceReceiver, senders::NextStepSender,
+    dead_letters::DeadLetterManager, managers::PersistenceManager, outbox::Outbox,
+    receivers::NewInstanceReceiver, senders::NextStepSender,
 };
 
 pub struct NewInstanceWorkerDependencies<
@@ -11,35 +12,55 @@ pub struct NewInstanceWorkerDependencies<
     NextStepSenderT,
     NewInstanceReceiverT,
     PersistenceManagerT,
+    DeadLetterManagerT,
+    OutboxT,
 > where
     P: Project,
     NextStepSenderT: NextStepSender<P>,
     NewInstanceReceiverT: NewInstanceReceiver<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    OutboxT: Outbox<P>,
 {
     pub next_step_sender: NextStepSenderT,
     pub new_instance_receiver: NewInstanceReceiverT,
     pub persistence_manager: PersistenceManagerT,
+    pub dead_letter_manager: DeadLetterManagerT,
+    /// `None` unless the adapter opted in to the outbox.
+    pub outbox: Option<OutboxT>,
     marker: PhantomData<P>,
 }
 
-impl<P, NextStepSenderT, NewInstanceReceiverT, PersistenceManagerT>
-    NewInstanceWorkerDependencies<P, NextStepSenderT, NewInstanceReceiverT, PersistenceManagerT>
+impl<P, NextStepSenderT, NewInstanceReceiverT, PersistenceManagerT, DeadLetterManagerT, OutboxT>
+    NewInstanceWorkerDependencies<
+        P,
+        NextStepSenderT,
+        NewInstanceReceiverT,
+        PersistenceManagerT,
+        DeadLetterManagerT,
+        OutboxT,
+    >
 where
     P: Project,
     NextStepSenderT: NextStepSender<P>,
     NewInstanceReceiverT: NewInstanceReceiver<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    OutboxT: Outbox<P>,
 {
     pub fn new(
         next_step_sender: NextStepSenderT,
         new_instance_receiver: NewInstanceReceiverT,
         persistence_manager: PersistenceManagerT,
+        dead_letter_manager: DeadLetterManagerT,
+        outbox: Option<OutboxT>,
     ) -> Self {
         Self {
             next_step_sender,
             new_instance_receiver,
             persistence_manager,
+            dead_letter_manager,
+            outbox,
             marker: PhantomData,
         }
     }
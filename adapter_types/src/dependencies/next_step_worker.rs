@@ -3,7 +3,9 @@ use std::marker::PhantomData;
 use surgeflow_types::Project;
 
 use crate::{
+    dead_letters::DeadLetterManager,
     managers::{PersistenceManager, StepsAwaitingEventManager},
+    notifications::NotificationSender,
     receivers::NextStepReceiver,
     senders::ActiveStepSender,
 };
@@ -14,17 +16,24 @@ pub struct NextStepWorkerDependencies<
     ActiveStepSenderT,
     StepsAwaitingEventManagerT,
     PersistenceManagerT,
+    DeadLetterManagerT,
+    NotificationSenderT,
 > where
     P: Project,
     NextStepReceiverT: NextStepReceiver<P>,
     ActiveStepSenderT: ActiveStepSender<P>,
     StepsAwaitingEventManagerT: StepsAwaitingEventManager<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     pub next_step_receiver: NextStepReceiverT,
     pub active_step_sender: ActiveStepSenderT,
     pub steps_awaiting_event_manager: StepsAwaitingEventManagerT,
     pub persistence_manager: PersistenceManagerT,
+    pub dead_letter_manager: DeadLetterManagerT,
+    /// `None` unless the adapter supports notifications.
+    pub notification_sender: Option<NotificationSenderT>,
     marker: PhantomData<P>,
 }
 
@@ -34,6 +43,8 @@ impl<
     ActiveStepSenderT,
     StepsAwaitingEventManagerT,
     PersistenceManagerT,
+    DeadLetterManagerT,
+    NotificationSenderT,
 >
     NextStepWorkerDependencies<
         P,
@@ -41,24 +52,32 @@ impl<
         ActiveStepSenderT,
         StepsAwaitingEventManagerT,
         PersistenceManagerT,
+        DeadLetterManagerT,
+        NotificationSenderT,
     >
 where
     NextStepReceiverT: NextStepReceiver<P>,
     ActiveStepSenderT: ActiveStepSender<P>,
     StepsAwaitingEventManagerT: StepsAwaitingEventManager<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     pub fn new(
         next_step_receiver: NextStepReceiverT,
         active_step_sender: ActiveStepSenderT,
         steps_awaiting_event_manager: StepsAwaitingEventManagerT,
         persistence_manager: PersistenceManagerT,
+        dead_letter_manager: DeadLetterManagerT,
+        notification_sender: Option<NotificationSenderT>,
     ) -> Self {
         Self {
             next_step_receiver,
             active_step_sender,
             steps_awaiting_event_manager,
             persistence_manager,
+            dead_letter_manager,
+            notification_sender,
             marker: PhantomData,
         }
     }
@@ -0,0 +1,60 @@
+use std::marker::PhantomData;
+
+use surgeflow_types::Project;
+
+use crate::{
+    outbox::Outbox,
+    senders::{CompletedInstanceSender, FailedInstanceSender, NextStepSender},
+};
+
+pub struct OutboxRelayWorkerDependencies<
+    P,
+    OutboxT,
+    NextStepSenderT,
+    CompletedInstanceSenderT,
+    FailedInstanceSenderT,
+> where
+    P: Project,
+    OutboxT: Outbox<P>,
+    NextStepSenderT: NextStepSender<P>,
+    CompletedInstanceSenderT: CompletedInstanceSender<P>,
+    FailedInstanceSenderT: FailedInstanceSender<P>,
+{
+    /// `None` unless the adapter opted in to the outbox, in which case there is nothing to relay.
+    pub outbox: Option<OutboxT>,
+    pub next_step_sender: NextStepSenderT,
+    pub completed_instance_sender: CompletedInstanceSenderT,
+    pub failed_instance_sender: FailedInstanceSenderT,
+    _marker: PhantomData<P>,
+}
+
+impl<P, OutboxT, NextStepSenderT, CompletedInstanceSenderT, FailedInstanceSenderT>
+    OutboxRelayWorkerDependencies<
+        P,
+        OutboxT,
+        NextStepSenderT,
+        CompletedInstanceSenderT,
+        FailedInstanceSenderT,
+    >
+where
+    P: Project,
+    OutboxT: Outbox<P>,
+    NextStepSenderT: NextStepSender<P>,
+    CompletedInstanceSenderT: CompletedInstanceSender<P>,
+    FailedInstanceSenderT: FailedInstanceSender<P>,
+{
+    pub fn new(
+        outbox: Option<OutboxT>,
+        next_step_sender: NextStepSenderT,
+        completed_instance_sender: CompletedInstanceSenderT,
+        failed_instance_sender: FailedInstanceSenderT,
+    ) -> Self {
+        Self {
+            outbox,
+            next_step_sender,
+            completed_instance_sender,
+            failed_instance_sender,
+            _marker: PhantomData,
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use std::marker::PhantomData;
+
+use surgeflow_types::Project;
+
+use crate::{
+    managers::PersistenceManager,
+    senders::{ActiveStepSender, FailedStepSender, NextStepSender},
+};
+
+pub struct ReaperWorkerDependencies<
+    P,
+    NextStepSenderT,
+    ActiveStepSenderT,
+    FailedStepSenderT,
+    PersistenceManagerT,
+> where
+    P: Project,
+    NextStepSenderT: NextStepSender<P>,
+    ActiveStepSenderT: ActiveStepSender<P>,
+    FailedStepSenderT: FailedStepSender<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+{
+    pub next_step_sender: NextStepSenderT,
+    pub active_step_sender: ActiveStepSenderT,
+    pub failed_step_sender: FailedStepSenderT,
+    pub persistence_manager: PersistenceManagerT,
+    _marker: PhantomData<P>,
+}
+
+impl<P, NextStepSenderT, ActiveStepSenderT, FailedStepSenderT, PersistenceManagerT>
+    ReaperWorkerDependencies<
+        P,
+        NextStepSenderT,
+        ActiveStepSenderT,
+        FailedStepSenderT,
+        PersistenceManagerT,
+    >
+where
+    P: Project,
+    NextStepSenderT: NextStepSender<P>,
+    ActiveStepSenderT: ActiveStepSender<P>,
+    FailedStepSenderT: FailedStepSender<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+{
+    pub fn new(
+        next_step_sender: NextStepSenderT,
+        active_step_sender: ActiveStepSenderT,
+        failed_step_sender: FailedStepSenderT,
+        persistence_manager: PersistenceManagerT,
+    ) -> Self {
+        Self {
+            next_step_sender,
+            active_step_sender,
+            failed_step_sender,
+            persistence_manager,
+            _marker: PhantomData,
+        }
+    }
+}
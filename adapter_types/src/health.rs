@@ -0,0 +1,231 @@
+//! Liveness and readiness of the workers in a process. Workers register their loop and the
+//! dependencies they use with a shared [`Health`], which the control server, or the probe
+//! endpoints of processes without it, report on.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use futures::{
+    FutureExt,
+    future::{BoxFuture, join_all},
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::time::timeout;
+
+/// How long a dependency gets to answer its health check before it counts as unhealthy.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+type Check = Box<dyn Fn() -> BoxFuture<'static, Result<(), String>> + Send + Sync>;
+
+struct DependencyCheck {
+    worker: &'static str,
+    dependency: &'static str,
+    check: Check,
+}
+
+#[derive(Default)]
+pub struct Health {
+    workers: Mutex<BTreeMap<&'static str, WorkerHealth>>,
+    checks: Mutex<Vec<Arc<DependencyCheck>>>,
+}
+
+impl Health {
+    /// Tracks `worker`'s loop until the returned handle is dropped.
+    pub fn register_worker(self: &Arc<Self>, worker: &'static str) -> WorkerLiveness {
+        self.workers.lock().expect("health lock poisoned").insert(
+            worker,
+            WorkerHealth {
+                running: true,
+                last_beat: Utc::now(),
+            },
+        );
+        WorkerLiveness {
+            health: self.clone(),
+            worker,
+        }
+    }
+
+    /// Adds `dependency` of `worker` to the readiness checks. `check` gets its own clone of the
+    /// dependency on every run.
+    pub fn register_check<T, F, Fut, E>(
+        &self,
+        worker: &'static str,
+        dependency: &'static str,
+        value: T,
+        check: F,
+    ) where
+        T: Clone + Send + 'static,
+        F: Fn(T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Display + 'static,
+    {
+        // dependencies need not be `Sync`, so every run clones one out of a lock
+        let value = Mutex::new(value);
+        let check: Check = Box::new(move || {
+            let value = value.lock().expect("health lock poisoned").clone();
+            run_check(check(value)).boxed()
+        });
+        self.checks
+            .lock()
+            .expect("health lock poisoned")
+            .push(Arc::new(DependencyCheck {
+                worker,
+                dependency,
+                check,
+            }));
+    }
+
+    pub fn liveness(&self) -> LivenessResponse {
+        let workers = self.workers.lock().expect("health lock poisoned");
+        LivenessResponse {
+            alive: workers.values().all(|worker| worker.running),
+            workers: workers
+                .iter()
+                .map(|(worker, health)| (worker.to_string(), health.clone()))
+                .collect(),
+        }
+    }
+
+    /// Runs every registered check, concurrently.
+    pub async fn dependency_checks(&self) -> Vec<DependencyHealth> {
+        let checks = self.checks.lock().expect("health lock poisoned").clone();
+        join_all(checks.into_iter().map(|check| async move {
+            let result = (check.check)().await;
+            DependencyHealth::new(check.worker, check.dependency, result)
+        }))
+        .await
+    }
+}
+
+/// Runs a health check, failing it if it doesn't answer in time.
+pub async fn run_check<E: Display>(
+    check: impl Future<Output = Result<(), E>>,
+) -> Result<(), String> {
+    match timeout(CHECK_TIMEOUT, check).await {
+        Ok(result) => result.map_err(|err| err.to_string()),
+        Err(_) => Err(format!("timed out after {CHECK_TIMEOUT:?}")),
+    }
+}
+
+/// A worker's registration with [`Health`]. The worker counts as stopped once it is dropped.
+pub struct WorkerLiveness {
+    health: Arc<Health>,
+    worker: &'static str,
+}
+
+impl WorkerLiveness {
+    /// Records that the worker loop went around once more.
+    pub fn beat(&self) {
+        if let Some(worker) = self
+            .health
+            .workers
+            .lock()
+            .expect("health lock poisoned")
+            .get_mut(self.worker)
+        {
+            worker.last_beat = Utc::now();
+        }
+    }
+}
+
+impl Drop for WorkerLiveness {
+    fn drop(&mut self) {
+        // don't panic while unwinding from another panic
+        if let Ok(mut workers) = self.health.workers.lock()
+            && let Some(worker) = workers.get_mut(self.worker)
+        {
+            worker.running = false;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct WorkerHealth {
+    pub running: bool,
+    /// When the worker last went around its loop. Idle workers wait for messages, so an old
+    /// beat alone doesn't mean the worker is stuck.
+    pub last_beat: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct LivenessResponse {
+    pub alive: bool,
+    pub workers: BTreeMap<String, WorkerHealth>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DependencyHealth {
+    /// `control_server` for the control server's own dependencies.
+    pub worker: String,
+    pub dependency: String,
+    pub healthy: bool,
+    pub error: Option<String>,
+}
+
+impl DependencyHealth {
+    pub fn new(worker: &str, dependency: &str, result: Result<(), String>) -> Self {
+        Self {
+            worker: worker.to_string(),
+            dependency: dependency.to_string(),
+            healthy: result.is_ok(),
+            error: result.err(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ReadinessResponse {
+    pub ready: bool,
+    pub dependencies: Vec<DependencyHealth>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::pending;
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn checks_that_hang_time_out() {
+        let result = run_check(pending::<Result<(), String>>()).await;
+        assert_eq!(result, Err("timed out after 5s".to_string()));
+    }
+
+    #[tokio::test]
+    async fn checks_report_their_errors() {
+        assert_eq!(run_check(async { Ok::<_, String>(()) }).await, Ok(()));
+        assert_eq!(
+            run_check(async { Err("connection refused") }).await,
+            Err("connection refused".to_string())
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_hanging_dependency_is_unhealthy() {
+        let health = Health::default();
+        health.register_check("worker", "healthy", (), |()| async { Ok::<_, String>(()) });
+        health.register_check("worker", "hanging", (), |()| {
+            pending::<Result<(), String>>()
+        });
+
+        let healthy: Vec<_> = health
+            .dependency_checks()
+            .await
+            .into_iter()
+            .map(|dependency| (dependency.dependency, dependency.healthy))
+            .collect();
+        assert_eq!(
+            healthy,
+            [
+                ("healthy".to_string(), true),
+                ("hanging".to_string(), false)
+            ]
+        );
+    }
+}
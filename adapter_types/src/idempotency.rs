@@ -0,0 +1,51 @@
+//! Idempotency keys for instance creation. A client that retries a create request, say after a
+//! timeout, sends the same key again and gets back the instance the first request created
+//! instead of a duplicate.
+//!
+//! A key is claimed before its instance is sent, and marked sent afterwards. A key that is still
+//! unsent may belong to a request that stopped in between, so repeating it sends the instance
+//! again under the same id. That is harmless if the first send went through, as an instance is
+//! only ever created once per id.
+
+use std::{error::Error, time::Duration};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use surgeflow_types::{Project, WorkflowInstanceId, WorkflowName};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdempotencyRecord {
+    pub instance_id: WorkflowInstanceId,
+    /// The request the key was first used with. Repeating the key with another request is an
+    /// error.
+    pub request: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+    /// Whether the instance was sent. Set by [`IdempotencyStore::mark_sent`].
+    #[serde(default)]
+    pub sent: bool,
+}
+
+/// Keys are scoped to a workflow, so the same key can create one instance of each workflow.
+pub trait IdempotencyStore<P: Project>: Sized + Send + 'static + Clone {
+    type Error: Error + Send + Sync + 'static;
+
+    /// Stores `record` under `key`, unless the key already has a record created less than
+    /// `retention` ago, which is returned instead. Older records are replaced.
+    ///
+    /// Must be atomic, so that concurrent requests with the same key agree on one instance.
+    fn claim(
+        &self,
+        workflow: &WorkflowName,
+        key: &str,
+        record: &IdempotencyRecord,
+        retention: Duration,
+    ) -> impl Future<Output = Result<Option<IdempotencyRecord>, Self::Error>> + Send;
+
+    /// Records that the instance `key` was claimed for was sent, so repeating the key no longer
+    /// sends it again.
+    fn mark_sent(
+        &self,
+        workflow: &WorkflowName,
+        key: &str,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+}
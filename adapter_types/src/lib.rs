@@ -1,4 +1,11 @@
+pub mod dead_letters;
 pub mod dependencies;
+pub mod health;
+pub mod idempotency;
 pub mod managers;
+pub mod notifications;
+pub mod outbox;
 pub mod receivers;
+pub mod registry;
 pub mod senders;
+pub mod telemetry;
@@ -1,7 +1,10 @@
 use std::error::Error;
-use surgeflow_types::{FullyQualifiedStep, Project, WorkflowInstanceId};
+use surgeflow_types::{FullyQualifiedStep, Project, StepId, WorkflowInstanceId};
 
-pub use persistence_manager::PersistenceManager;
+pub use persistence_manager::{
+    AttemptOutcome, AttemptStart, InstanceFilter, InstanceOrder, InstanceRecord,
+    PersistenceManager, StepAttemptRecord, StepRecord,
+};
 
 pub trait StepsAwaitingEventManager<P: Project>: Sized + Send + 'static + Clone {
     type Error: Error + Send + Sync + 'static;
@@ -9,20 +12,141 @@ pub trait StepsAwaitingEventManager<P: Project>: Sized + Send + 'static + Clone
         &mut self,
         instance_id: WorkflowInstanceId,
     ) -> impl Future<Output = Result<Option<FullyQualifiedStep<P>>, Self::Error>> + Send;
+    /// Deletes the step awaiting event of `instance_id` if it is `step_id`. Once the step was
+    /// sent, the instance may already await an event for a later step, which must be kept.
     fn delete_step(
         &mut self,
         instance_id: WorkflowInstanceId,
+        step_id: StepId,
     ) -> impl Future<Output = Result<(), Self::Error>> + Send;
 
     fn put_step(
         &mut self,
         step: FullyQualifiedStep<P>,
     ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn health_check(&self) -> impl Future<Output = Result<(), Self::Error>> + Send;
 }
 
 mod persistence_manager {
-    use std::error::Error;
-    use surgeflow_types::{__Workflow, Project, StepId, WorkflowInstance, WorkflowInstanceId};
+    use chrono::{DateTime, Utc};
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+    use std::{error::Error, time::Duration};
+    use surgeflow_types::{
+        __Step, __Workflow, BusinessId, FullyQualifiedStep, HeartbeatDetails, IdReusePolicy,
+        InstanceStatus, Project, RawStep, StepId, StepStatus, TagUpdates, Tags, WorkflowInstance,
+        WorkflowInstanceId, WorkflowName,
+    };
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(bound = "")]
+    pub struct InstanceRecord<P: Project> {
+        pub instance: WorkflowInstance<P>,
+        pub status: InstanceStatus,
+        /// The [`step_type`](__Step::step_type) of the instance's most recent step.
+        pub current_step_type: Option<String>,
+        /// The current tags, unlike `instance.tags` which are the ones it was created with.
+        pub tags: Tags,
+        pub created_at: DateTime<Utc>,
+        pub updated_at: DateTime<Utc>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(bound = "")]
+    pub struct StepRecord<P: Project> {
+        pub step_id: StepId,
+        pub previous_step_id: Option<StepId>,
+        pub step: RawStep<P, P::Workflow>,
+        pub status: StepStatus,
+        /// Failed attempts so far.
+        pub retry_count: u32,
+        /// The step this one returned. Always `None` unless the step completed.
+        pub output: Option<<P::Workflow as __Workflow<P>>::Step>,
+        pub created_at: DateTime<Utc>,
+        pub updated_at: DateTime<Utc>,
+    }
+
+    type ProjectEvent<P> = <<<P as Project>::Workflow as __Workflow<P>>::Step as __Step<
+        P,
+        <P as Project>::Workflow,
+    >>::Event;
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(bound = "")]
+    pub struct StepAttemptRecord<P: Project> {
+        pub step_id: StepId,
+        /// Starts at 1.
+        pub attempt: u32,
+        /// The event the attempt ran with.
+        pub event: Option<ProjectEvent<P>>,
+        pub started_at: DateTime<Utc>,
+        /// `None` while the attempt is running.
+        pub finished_at: Option<DateTime<Utc>>,
+        /// Set by [`PersistenceManager::insert_step_error`] if the attempt failed.
+        pub error: Option<String>,
+        pub last_heartbeat: Option<HeartbeatDetails>,
+    }
+
+    /// How an attempt of a step ended, stored when the attempt finishes so that its outcome can
+    /// be forwarded again if the worker stopped before forwarding it.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(bound = "")]
+    pub enum AttemptOutcome<P: Project> {
+        Completed {
+            /// The step the attempt returned.
+            next_step: Option<RawStep<P, P::Workflow>>,
+            /// The tag changes the step made.
+            tag_updates: TagUpdates,
+        },
+        Failed {
+            error: String,
+        },
+    }
+
+    /// What [`PersistenceManager::start_step_attempt`] found.
+    #[derive(Clone)]
+    pub enum AttemptStart<P: Project> {
+        /// The attempt is new, and must run.
+        Started,
+        /// The attempt is running, a later attempt was started, or the step already completed
+        /// or failed. The delivery must not run.
+        Duplicate,
+        /// The attempt already finished, but the step hasn't moved on since. Its outcome may not
+        /// have been forwarded, so it must be forwarded again.
+        Finished(AttemptOutcome<P>),
+    }
+
+    /// Which instances [`PersistenceManager::list_instances`] returns. `None` fields don't
+    /// filter.
+    #[derive(Debug, Clone, Default, Serialize, Deserialize)]
+    pub struct InstanceFilter {
+        pub workflow: Option<WorkflowName>,
+        pub status: Option<InstanceStatus>,
+        /// Inclusive.
+        pub created_after: Option<DateTime<Utc>>,
+        /// Exclusive.
+        pub created_before: Option<DateTime<Utc>>,
+        /// Matched against [`InstanceRecord::current_step_type`].
+        pub current_step_type: Option<String>,
+        /// Only instances that currently have all of these tags. Adapters should index tags so
+        /// this doesn't scan every instance.
+        #[serde(default)]
+        pub tags: Tags,
+        /// Every instance that had this id, not only the current one.
+        pub business_id: Option<BusinessId>,
+    }
+
+    /// The order [`PersistenceManager::list_instances`] returns instances in. Ties are broken by
+    /// instance id, so that paging with a cursor neither skips nor repeats instances.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+    #[serde(rename_all = "kebab-case")]
+    pub enum InstanceOrder {
+        #[default]
+        OldestFirst,
+        NewestFirst,
+        LeastRecentlyUpdatedFirst,
+        MostRecentlyUpdatedFirst,
+    }
 
     // TODO: should these take references instead of ownership?
     pub trait PersistenceManager<P: Project>: Sized + Send + 'static + Clone {
@@ -30,15 +154,105 @@ mod persistence_manager {
         fn set_step_status(
             &self,
             step_id: StepId,
-            // TODO: use an enum for status
-            status: i32,
+            status: StepStatus,
         ) -> impl Future<Output = Result<(), Self::Error>> + Send;
 
+        /// Inserts `step` as pending, unless a step with the same id already exists, and returns
+        /// the step's status either way. The whole step is stored so it can be re-enqueued if it
+        /// gets stuck. Step ids are deterministic, so an existing step means a redelivery.
         fn insert_step(
             &self,
-            workflow_instance_id: WorkflowInstanceId,
+            step: &FullyQualifiedStep<P>,
+        ) -> impl Future<Output = Result<StepStatus, Self::Error>> + Send;
+
+        /// Stores a running attempt of `step`, along with the event it runs with, so it can be
+        /// recovered if its worker dies. With a heartbeat timeout, the attempt's deadline is set
+        /// to now plus the timeout.
+        ///
+        /// Must be atomic: stores nothing if this attempt (same step id and retry count) was
+        /// already started, and returns either [`AttemptStart::Duplicate`] or, if the attempt
+        /// finished and the step didn't move on since, [`AttemptStart::Finished`] with the outcome
+        /// stored by [`finish_step_attempt`](Self::finish_step_attempt).
+        fn start_step_attempt(
+            &self,
+            step: &FullyQualifiedStep<P>,
+            heartbeat_timeout: Option<Duration>,
+        ) -> impl Future<Output = Result<AttemptStart<P>, Self::Error>> + Send;
+
+        /// Stores the last heartbeat of the running attempt of a step and, if the attempt has a
+        /// heartbeat timeout, pushes its deadline back to now plus the timeout.
+        fn record_step_heartbeat(
+            &self,
+            step_id: StepId,
+            details: &HeartbeatDetails,
+        ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+        /// Ends the running attempt of a step, storing its outcome in the same write. Returns
+        /// `false`, storing nothing, if the attempt had already been claimed as lost, in which
+        /// case its outcome must be discarded.
+        fn finish_step_attempt(
+            &self,
+            step_id: StepId,
+            outcome: &AttemptOutcome<P>,
+        ) -> impl Future<Output = Result<bool, Self::Error>> + Send;
+
+        /// Atomically ends and returns up to `limit` running attempts whose deadline passed more
+        /// than `grace` ago, so that every lost attempt is recovered by exactly one caller. The
+        /// attempts are finished as [`AttemptOutcome::Failed`] with `error`.
+        ///
+        /// A claimed attempt stays claimable until it is
+        /// [settled](Self::settle_claimed_step_attempt): attempts claimed more than
+        /// `reclaim_after` ago but not settled are returned again, so a step whose caller failed
+        /// to forward it isn't stranded.
+        fn claim_lost_step_attempts(
+            &self,
+            grace: Duration,
+            reclaim_after: Duration,
+            limit: u32,
+            error: &str,
+        ) -> impl Future<Output = Result<Vec<FullyQualifiedStep<P>>, Self::Error>> + Send;
+
+        /// Atomically ends and returns up to `limit` running attempts that started more than
+        /// `older_than` ago without a heartbeat timeout. The attempts are finished as
+        /// [`AttemptOutcome::Failed`] with `error`, and are returned again like the ones of
+        /// [`claim_lost_step_attempts`](Self::claim_lost_step_attempts) until settled.
+        ///
+        /// Attempts started with a heartbeat timeout must never be returned, however long they
+        /// run: they have a deadline, so [`claim_lost_step_attempts`](Self::claim_lost_step_attempts)
+        /// recovers them once they stop heartbeating, and a step that keeps heartbeating is
+        /// allowed to run for as long as it needs.
+        fn claim_stale_step_attempts(
+            &self,
+            older_than: Duration,
+            reclaim_after: Duration,
+            limit: u32,
+            error: &str,
+        ) -> impl Future<Output = Result<Vec<FullyQualifiedStep<P>>, Self::Error>> + Send;
+
+        /// Stops returning the claimed attempt of `step_id` with `retry_count` from the claims,
+        /// once its step has been forwarded.
+        fn settle_claimed_step_attempt(
+            &self,
+            step_id: StepId,
+            retry_count: u32,
+        ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+        /// Returns up to `limit` steps that have been pending for more than `older_than`,
+        /// restarting their pending time so they are not returned again before `older_than`
+        /// has passed once more.
+        fn claim_stale_pending_steps(
+            &self,
+            older_than: Duration,
+            limit: u32,
+        ) -> impl Future<Output = Result<Vec<FullyQualifiedStep<P>>, Self::Error>> + Send;
+
+        /// Records why an attempt of a step failed. `attempt` starts at 1. Recording the same
+        /// attempt again, when its outcome is forwarded again, must keep a single error.
+        fn insert_step_error(
+            &self,
             step_id: StepId,
-            step: &<P::Workflow as __Workflow<P>>::Step,
+            attempt: u32,
+            error: &str,
         ) -> impl Future<Output = Result<(), Self::Error>> + Send;
 
         fn insert_step_output(
@@ -47,9 +261,93 @@ mod persistence_manager {
             output: Option<&<P::Workflow as __Workflow<P>>::Step>,
         ) -> impl Future<Output = Result<(), Self::Error>> + Send;
 
+        fn get_instance(
+            &self,
+            instance_id: WorkflowInstanceId,
+        ) -> impl Future<Output = Result<Option<InstanceRecord<P>>, Self::Error>> + Send;
+
+        /// The current instance of `business_id` among the instances of `workflow`, as made
+        /// current by [`claim_business_id`](Self::claim_business_id). `None` if no instance has
+        /// the id, or if its current instance wasn't inserted yet.
+        fn get_instance_by_business_id(
+            &self,
+            workflow: &WorkflowName,
+            business_id: &BusinessId,
+        ) -> impl Future<Output = Result<Option<InstanceRecord<P>>, Self::Error>> + Send;
+
+        /// Lists the steps of an instance in the order they were inserted, which is the order
+        /// they ran in.
+        fn list_steps(
+            &self,
+            instance_id: WorkflowInstanceId,
+        ) -> impl Future<Output = Result<Vec<StepRecord<P>>, Self::Error>> + Send;
+
+        /// Lists the attempts of every step of an instance, ordered by step like
+        /// [`list_steps`](Self::list_steps), then by attempt.
+        fn list_step_attempts(
+            &self,
+            instance_id: WorkflowInstanceId,
+        ) -> impl Future<Output = Result<Vec<StepAttemptRecord<P>>, Self::Error>> + Send;
+
+        /// Lists the instances matching `filter` in the given order, starting after the instance
+        /// `after` if given.
+        fn list_instances(
+            &self,
+            filter: &InstanceFilter,
+            order: InstanceOrder,
+            after: Option<WorkflowInstanceId>,
+            limit: u32,
+        ) -> impl Future<Output = Result<Vec<InstanceRecord<P>>, Self::Error>> + Send;
+
+        /// Records that an instance reached a terminal status. Called by the completed and failed
+        /// instance workers.
+        fn set_instance_status(
+            &self,
+            instance_id: WorkflowInstanceId,
+            status: InstanceStatus,
+        ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+        /// Applies `updates` to the current tags of an instance.
+        fn update_instance_tags(
+            &self,
+            instance_id: WorkflowInstanceId,
+            updates: &TagUpdates,
+        ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+        /// Makes `instance_id` the current instance of `business_id` among the instances of
+        /// `workflow`, unless `policy` doesn't allow taking the id over from its current instance.
+        /// That instance is then returned, and nothing is stored. A current instance that wasn't
+        /// inserted yet counts as running. Claiming the id for the instance that already holds it
+        /// succeeds, as an instance may be sent again.
+        ///
+        /// Must be atomic, so that only one of concurrent claims for the same id succeeds.
+        fn claim_business_id(
+            &self,
+            workflow: &WorkflowName,
+            business_id: &BusinessId,
+            instance_id: WorkflowInstanceId,
+            policy: IdReusePolicy,
+        ) -> impl Future<Output = Result<Option<WorkflowInstanceId>, Self::Error>> + Send;
+
+        /// Undoes the claim of `business_id` by `instance_id`, which couldn't be created, making
+        /// the instance it took the id over from current again. Does nothing if another instance
+        /// claimed the id since.
+        fn release_business_id(
+            &self,
+            workflow: &WorkflowName,
+            business_id: &BusinessId,
+            instance_id: WorkflowInstanceId,
+        ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+        /// Also stores the instance's tags as its current tags.
+        /// Inserting an instance that already exists must leave it untouched.
         fn insert_instance(
             &self,
             workflow_instance: WorkflowInstance<P>,
         ) -> impl Future<Output = Result<WorkflowInstanceId, Self::Error>> + Send;
+
+        /// Whether the backing store can be reached, for readiness checks. Should be cheap, it
+        /// runs on every readiness probe.
+        fn health_check(&self) -> impl Future<Output = Result<(), Self::Error>> + Send;
     }
 }
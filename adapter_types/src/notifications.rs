@@ -0,0 +1,108 @@
+//! Lifecycle notifications. Workers publish one whenever a step or instance changes state, and
+//! the control server streams them to clients as they happen. Notifications are best effort:
+//! they are not persisted, not acknowledged and may be lost, so persistence stays the source of
+//! truth. Adapters that don't support them use [`NoNotifications`] and nothing is published.
+
+use std::{convert::Infallible, error::Error};
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use surgeflow_types::{Project, StepId, WorkflowInstanceId};
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct InstanceNotification {
+    pub instance_id: WorkflowInstanceId,
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub kind: NotificationKind,
+}
+
+impl InstanceNotification {
+    pub fn new(instance_id: WorkflowInstanceId, kind: NotificationKind) -> Self {
+        Self {
+            instance_id,
+            at: Utc::now(),
+            kind,
+        }
+    }
+}
+
+/// What happened. `attempt` starts at 1.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum NotificationKind {
+    StepStarted {
+        step_id: StepId,
+        step_type: String,
+        attempt: u32,
+    },
+    /// An attempt failed and the step will run again.
+    StepRetried {
+        step_id: StepId,
+        step_type: String,
+        attempt: u32,
+        error: String,
+    },
+    /// The step is parked until an event of `event_type` arrives.
+    StepWaitingForEvent {
+        step_id: StepId,
+        step_type: String,
+        event_type: String,
+    },
+    StepCompleted {
+        step_id: StepId,
+        step_type: String,
+    },
+    /// The step ran out of retries.
+    StepFailed {
+        step_id: StepId,
+        step_type: String,
+    },
+    InstanceCompleted,
+    InstanceFailed,
+}
+
+impl NotificationKind {
+    /// Whether nothing follows this notification for the same instance.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, Self::InstanceCompleted | Self::InstanceFailed)
+    }
+}
+
+pub trait NotificationSender<P: Project>: Sized + Send + Sync + 'static + Clone {
+    type Error: Error + Send + Sync + 'static;
+    fn send(
+        &self,
+        notification: InstanceNotification,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+}
+
+/// A subscription to the notifications of every instance. Unlike the queue receivers, each
+/// receiver gets every notification sent after it was created, and there is nothing to settle.
+pub trait NotificationReceiver<P: Project>: Sized + Send + 'static {
+    type Error: Error + Send + Sync + 'static;
+    fn receive(&mut self)
+    -> impl Future<Output = Result<InstanceNotification, Self::Error>> + Send;
+}
+
+/// The notification sender and receiver of adapters that don't support notifications. It can't
+/// be constructed, so dependency providers using it always hand out `None`.
+#[derive(Debug, Clone, Copy)]
+pub enum NoNotifications {}
+
+impl<P: Project> NotificationSender<P> for NoNotifications {
+    type Error = Infallible;
+
+    async fn send(&self, _: InstanceNotification) -> Result<(), Self::Error> {
+        match *self {}
+    }
+}
+
+impl<P: Project> NotificationReceiver<P> for NoNotifications {
+    type Error = Infallible;
+
+    async fn receive(&mut self) -> Result<InstanceNotification, Self::Error> {
+        match *self {}
+    }
+}
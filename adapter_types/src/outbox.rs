@@ -0,0 +1,111 @@
+//! Transactional outbox. A worker that changes state and then publishes messages would leave
+//! the two disagreeing if it crashed in between. Adapters that opt in provide an [`Outbox`]:
+//! the worker hands it the state changes and the messages together, they are committed in one
+//! transaction, and the relay worker publishes the recorded messages afterwards. Adapters that
+//! don't opt in use [`NoOutbox`] and workers write and send directly.
+
+use std::{convert::Infallible, error::Error, fmt};
+
+use serde::{Deserialize, Serialize};
+use surgeflow_types::{
+    __Workflow, FullyQualifiedStep, Project, StepId, StepStatus, WorkflowInstance,
+};
+use uuid::Uuid;
+
+/// A state change committed together with outbox messages. Each variant mirrors the
+/// [`PersistenceManager`](crate::managers::PersistenceManager) method of the same name.
+#[derive(Debug, Clone)]
+pub enum StateChange<P: Project> {
+    InsertInstance(WorkflowInstance<P>),
+    SetStepStatus {
+        step_id: StepId,
+        status: StepStatus,
+    },
+    InsertStepOutput {
+        step_id: StepId,
+        output: Option<<P::Workflow as __Workflow<P>>::Step>,
+    },
+}
+
+/// A message waiting in the outbox, along with the queue it is for.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub enum OutboxMessage<P: Project> {
+    NextStep(FullyQualifiedStep<P>),
+    CompletedInstance(WorkflowInstance<P>),
+    FailedInstance(WorkflowInstance<P>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct OutboxEntryId(Uuid);
+
+impl fmt::Display for OutboxEntryId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.hyphenated())
+    }
+}
+
+impl OutboxEntryId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+impl Default for OutboxEntryId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OutboxEntry<P: Project> {
+    pub id: OutboxEntryId,
+    pub message: OutboxMessage<P>,
+}
+
+pub trait Outbox<P: Project>: Sized + Send + Sync + 'static + Clone {
+    type Error: Error + Send + Sync + 'static;
+
+    /// Applies `changes` and records `messages` in a single transaction.
+    fn commit(
+        &self,
+        changes: Vec<StateChange<P>>,
+        messages: Vec<OutboxMessage<P>>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+    /// Up to `limit` recorded messages that were not published yet, oldest first.
+    fn unpublished(
+        &self,
+        limit: u32,
+    ) -> impl Future<Output = Result<Vec<OutboxEntry<P>>, Self::Error>> + Send;
+
+    fn mark_published(
+        &self,
+        id: OutboxEntryId,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+}
+
+/// The outbox of adapters that don't have one. It can't be constructed, so dependency
+/// providers using it always hand out `None`.
+#[derive(Debug, Clone, Copy)]
+pub enum NoOutbox {}
+
+impl<P: Project> Outbox<P> for NoOutbox {
+    type Error = Infallible;
+
+    async fn commit(
+        &self,
+        _: Vec<StateChange<P>>,
+        _: Vec<OutboxMessage<P>>,
+    ) -> Result<(), Self::Error> {
+        match *self {}
+    }
+
+    async fn unpublished(&self, _: u32) -> Result<Vec<OutboxEntry<P>>, Self::Error> {
+        match *self {}
+    }
+
+    async fn mark_published(&self, _: OutboxEntryId) -> Result<(), Self::Error> {
+        match *self {}
+    }
+}
@@ -1,12 +1,24 @@
-use std::error::Error;
+use std::{error::Error, time::Duration};
 
+use chrono::{DateTime, Utc};
 use surgeflow_types::{FullyQualifiedStep, InstanceEvent, Project, WorkflowInstance};
 
+/// Handle to a received message, used to settle it with `accept` or `reject`.
+/// `reject` hands the message back to the queue, optionally delaying its redelivery.
+pub trait DeliveryHandle: Send + Sync + 'static {
+    /// Number of times the message was delivered before this delivery (0 on first delivery).
+    fn redelivery_count(&self) -> u32;
+    /// When the message was first sent, if the queue records it. Used to measure queue lag.
+    fn enqueued_at(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+}
+
 // Steps
 
 pub trait NextStepReceiver<P: Project>: Sized + Send + 'static + Clone {
     type Error: Error + Send + Sync + 'static;
-    type Handle: Send + Sync + 'static;
+    type Handle: DeliveryHandle;
     fn receive(
         &mut self,
     ) -> impl Future<Output = Result<(FullyQualifiedStep<P>, Self::Handle), Self::Error>> + Send;
@@ -14,11 +26,19 @@ pub trait NextStepReceiver<P: Project>: Sized + Send + 'static + Clone {
         &mut self,
         handle: Self::Handle,
     ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn reject(
+        &mut self,
+        handle: Self::Handle,
+        redeliver_after: Option<Duration>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn health_check(&self) -> impl Future<Output = Result<(), Self::Error>> + Send;
 }
 
+/// Receives from the task queues it was created for, see
+/// [`ActiveStepWorkerDependencyProvider::active_step_worker_dependencies`](crate::dependencies::ActiveStepWorkerDependencyProvider::active_step_worker_dependencies).
 pub trait ActiveStepReceiver<P: Project>: Sized + Send + 'static + Clone {
     type Error: Error + Send + Sync + 'static;
-    type Handle: Send + Sync + 'static;
+    type Handle: DeliveryHandle;
     fn receive(
         &mut self,
     ) -> impl Future<Output = Result<(FullyQualifiedStep<P>, Self::Handle), Self::Error>> + Send;
@@ -26,11 +46,17 @@ pub trait ActiveStepReceiver<P: Project>: Sized + Send + 'static + Clone {
         &mut self,
         handle: Self::Handle,
     ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn reject(
+        &mut self,
+        handle: Self::Handle,
+        redeliver_after: Option<Duration>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn health_check(&self) -> impl Future<Output = Result<(), Self::Error>> + Send;
 }
 
 pub trait CompletedStepReceiver<P: Project>: Sized + Send + 'static + Clone {
     type Error: Error + Send + Sync + 'static;
-    type Handle: Send + Sync + 'static;
+    type Handle: DeliveryHandle;
     fn receive(
         &mut self,
     ) -> impl Future<Output = Result<(FullyQualifiedStep<P>, Self::Handle), Self::Error>> + Send;
@@ -38,11 +64,17 @@ pub trait CompletedStepReceiver<P: Project>: Sized + Send + 'static + Clone {
         &mut self,
         handle: Self::Handle,
     ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn reject(
+        &mut self,
+        handle: Self::Handle,
+        redeliver_after: Option<Duration>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn health_check(&self) -> impl Future<Output = Result<(), Self::Error>> + Send;
 }
 
 pub trait FailedStepReceiver<P: Project>: Sized + Send + 'static + Clone {
     type Error: Error + Send + Sync + 'static;
-    type Handle: Send;
+    type Handle: DeliveryHandle;
     fn receive(
         &mut self,
     ) -> impl Future<Output = Result<(FullyQualifiedStep<P>, Self::Handle), Self::Error>> + Send;
@@ -50,13 +82,19 @@ pub trait FailedStepReceiver<P: Project>: Sized + Send + 'static + Clone {
         &mut self,
         handle: Self::Handle,
     ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn reject(
+        &mut self,
+        handle: Self::Handle,
+        redeliver_after: Option<Duration>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn health_check(&self) -> impl Future<Output = Result<(), Self::Error>> + Send;
 }
 
 // Events
 
 pub trait EventReceiver<P: Project>: Sized + Send + 'static + Clone {
     type Error: Error + Send + Sync + 'static;
-    type Handle: Send + Sync + 'static;
+    type Handle: DeliveryHandle;
     fn receive(
         &mut self,
     ) -> impl Future<Output = Result<(InstanceEvent<P>, Self::Handle), Self::Error>> + Send;
@@ -64,13 +102,19 @@ pub trait EventReceiver<P: Project>: Sized + Send + 'static + Clone {
         &mut self,
         handle: Self::Handle,
     ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn reject(
+        &mut self,
+        handle: Self::Handle,
+        redeliver_after: Option<Duration>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn health_check(&self) -> impl Future<Output = Result<(), Self::Error>> + Send;
 }
 
 // Instances
 
 pub trait NewInstanceReceiver<P: Project>: Sized + Send + 'static + Clone {
     type Error: Error + Send + Sync + 'static;
-    type Handle: Send + Sync + 'static;
+    type Handle: DeliveryHandle;
     fn receive(
         &mut self,
     ) -> impl Future<Output = Result<(WorkflowInstance<P>, Self::Handle), Self::Error>> + Send;
@@ -78,11 +122,17 @@ pub trait NewInstanceReceiver<P: Project>: Sized + Send + 'static + Clone {
         &mut self,
         handle: Self::Handle,
     ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn reject(
+        &mut self,
+        handle: Self::Handle,
+        redeliver_after: Option<Duration>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn health_check(&self) -> impl Future<Output = Result<(), Self::Error>> + Send;
 }
 
 pub trait CompletedInstanceReceiver<P: Project>: Sized + Send + 'static + Clone {
     type Error: Error + Send + Sync + 'static;
-    type Handle: Send + Sync + 'static;
+    type Handle: DeliveryHandle;
     fn receive(
         &mut self,
     ) -> impl Future<Output = Result<(WorkflowInstance<P>, Self::Handle), Self::Error>> + Send;
@@ -90,11 +140,17 @@ pub trait CompletedInstanceReceiver<P: Project>: Sized + Send + 'static + Clone
         &mut self,
         handle: Self::Handle,
     ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn reject(
+        &mut self,
+        handle: Self::Handle,
+        redeliver_after: Option<Duration>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn health_check(&self) -> impl Future<Output = Result<(), Self::Error>> + Send;
 }
 
 pub trait FailedInstanceReceiver<P: Project>: Sized + Send + 'static + Clone {
     type Error: Error + Send + Sync + 'static;
-    type Handle: Send + Sync + 'static;
+    type Handle: DeliveryHandle;
     fn receive(
         &mut self,
     ) -> impl Future<Output = Result<(WorkflowInstance<P>, Self::Handle), Self::Error>> + Send;
@@ -102,4 +158,10 @@ pub trait FailedInstanceReceiver<P: Project>: Sized + Send + 'static + Clone {
         &mut self,
         handle: Self::Handle,
     ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn reject(
+        &mut self,
+        handle: Self::Handle,
+        redeliver_after: Option<Duration>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn health_check(&self) -> impl Future<Output = Result<(), Self::Error>> + Send;
 }
@@ -0,0 +1,107 @@
+//! Which worker processes are alive. Every `main_handler` process registers itself and keeps
+//! heartbeating while it runs; the control server lists the processes with a recent heartbeat.
+//! Adapters without a registry use [`NoWorkerRegistry`].
+
+use std::{collections::BTreeMap, convert::Infallible, error::Error, fmt, time::Duration};
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use surgeflow_types::{Project, TaskQueue};
+use uuid::Uuid;
+
+/// How often a process heartbeats.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+/// How old a process's last heartbeat may be for it to count as live. A few missed heartbeats
+/// don't make a process disappear.
+pub const LIVENESS_WINDOW: Duration = Duration::from_secs(3 * HEARTBEAT_INTERVAL.as_secs());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub struct WorkerProcessId(Uuid);
+
+impl fmt::Display for WorkerProcessId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.hyphenated())
+    }
+}
+
+impl WorkerProcessId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+}
+
+impl Default for WorkerProcessId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A process running `main_handler`, as of its last heartbeat.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct WorkerProcess {
+    pub id: WorkerProcessId,
+    /// The `HOSTNAME` of the process, if set.
+    pub hostname: Option<String>,
+    pub pid: u32,
+    /// The project's `Project::VERSION`.
+    pub version: String,
+    /// The workers the process runs, like `active_step_worker`.
+    pub workers: Vec<String>,
+    /// The task queues its active step worker runs steps from.
+    pub task_queues: Vec<TaskQueue>,
+    /// Messages each worker is processing.
+    pub in_flight: BTreeMap<String, u64>,
+    pub started_at: DateTime<Utc>,
+    pub last_heartbeat: DateTime<Utc>,
+}
+
+pub trait WorkerRegistry<P: Project>: Sized + Send + Sync + 'static + Clone {
+    type Error: Error + Send + Sync + 'static;
+
+    /// Registers `process`, or replaces its previous heartbeat.
+    fn heartbeat(
+        &self,
+        process: &WorkerProcess,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+    /// Removes the process when it stops. Processes that die without deregistering are left
+    /// out of [`live`](Self::live) once their heartbeat is too old, and may be removed then.
+    fn deregister(
+        &self,
+        id: WorkerProcessId,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+    /// The processes whose last heartbeat is after `since`.
+    fn live(
+        &self,
+        since: DateTime<Utc>,
+    ) -> impl Future<Output = Result<Vec<WorkerProcess>, Self::Error>> + Send;
+
+    fn health_check(&self) -> impl Future<Output = Result<(), Self::Error>> + Send;
+}
+
+/// The worker registry of adapters that don't have one. It can't be constructed, so dependency
+/// providers using it always hand out `None`.
+#[derive(Debug, Clone, Copy)]
+pub enum NoWorkerRegistry {}
+
+impl<P: Project> WorkerRegistry<P> for NoWorkerRegistry {
+    type Error = Infallible;
+
+    async fn heartbeat(&self, _: &WorkerProcess) -> Result<(), Self::Error> {
+        match *self {}
+    }
+
+    async fn deregister(&self, _: WorkerProcessId) -> Result<(), Self::Error> {
+        match *self {}
+    }
+
+    async fn live(&self, _: DateTime<Utc>) -> Result<Vec<WorkerProcess>, Self::Error> {
+        match *self {}
+    }
+
+    async fn health_check(&self) -> Result<(), Self::Error> {
+        match *self {}
+    }
+}
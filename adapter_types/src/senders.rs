@@ -10,14 +10,18 @@ pub trait NextStepSender<P: Project>: Sized + Send + 'static + Clone {
         &mut self,
         step: FullyQualifiedStep<P>,
     ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn health_check(&self) -> impl Future<Output = Result<(), Self::Error>> + Send;
 }
 
+/// Partitioned by task queue: each step goes to the queue of its
+/// [`StepSettings::task_queue`](surgeflow_types::StepSettings::task_queue).
 pub trait ActiveStepSender<P: Project>: Sized + Send + 'static + Clone {
     type Error: Error + Send + Sync + 'static;
     fn send(
         &mut self,
         step: FullyQualifiedStep<P>,
     ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn health_check(&self) -> impl Future<Output = Result<(), Self::Error>> + Send;
 }
 
 pub trait FailedStepSender<P: Project>: Sized + Send + 'static + Clone {
@@ -26,6 +30,7 @@ pub trait FailedStepSender<P: Project>: Sized + Send + 'static + Clone {
         &mut self,
         step: FullyQualifiedStep<P>,
     ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn health_check(&self) -> impl Future<Output = Result<(), Self::Error>> + Send;
 }
 
 pub trait CompletedStepSender<P: Project>: Sized + Send + 'static + Clone {
@@ -34,6 +39,7 @@ pub trait CompletedStepSender<P: Project>: Sized + Send + 'static + Clone {
         &mut self,
         step: FullyQualifiedStep<P>,
     ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn health_check(&self) -> impl Future<Output = Result<(), Self::Error>> + Send;
 }
 
 // Events
@@ -42,6 +48,7 @@ pub trait EventSender<P: Project>: Sized + Send + Sync + 'static {
     type Error: Error + Send + Sync + 'static;
     fn send(&self, event: InstanceEvent<P>)
     -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn health_check(&self) -> impl Future<Output = Result<(), Self::Error>> + Send;
 }
 
 // Instances
@@ -52,6 +59,7 @@ pub trait NewInstanceSender<P: Project>: Sized + Send + Sync + 'static {
         &self,
         event: WorkflowInstance<P>,
     ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn health_check(&self) -> impl Future<Output = Result<(), Self::Error>> + Send;
 }
 
 pub trait CompletedInstanceSender<P: Project>: Sized + Send + 'static + Clone {
@@ -60,6 +68,7 @@ pub trait CompletedInstanceSender<P: Project>: Sized + Send + 'static + Clone {
         &self,
         event: WorkflowInstance<P>,
     ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn health_check(&self) -> impl Future<Output = Result<(), Self::Error>> + Send;
 }
 
 pub trait FailedInstanceSender<P: Project>: Sized + Send + 'static + Clone {
@@ -68,4 +77,5 @@ pub trait FailedInstanceSender<P: Project>: Sized + Send + 'static + Clone {
         &self,
         event: WorkflowInstance<P>,
     ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+    fn health_check(&self) -> impl Future<Output = Result<(), Self::Error>> + Send;
 }
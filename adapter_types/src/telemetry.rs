@@ -0,0 +1,215 @@
+//! Tracing setup and trace context propagation. Queue messages carry the [`TraceContext`] of
+//! the span that sent them, and requests to the control server can carry one in their
+//! `traceparent` header, so an instance's spans join one trace from the request that created it
+//! to its last worker.
+
+use std::env;
+
+use opentelemetry::{
+    global,
+    propagation::{Extractor, Injector},
+    trace::TracerProvider,
+};
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::{Resource, propagation::TraceContextPropagator, trace::SdkTracerProvider};
+use surgeflow_types::TraceContext;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
+
+const TRACEPARENT: &str = "traceparent";
+const TRACESTATE: &str = "tracestate";
+/// Either sets where spans are exported to, see the `opentelemetry-otlp` docs.
+const OTLP_ENDPOINT_VARS: [&str; 2] = [
+    "OTEL_EXPORTER_OTLP_ENDPOINT",
+    "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+];
+
+/// Exports spans until dropped. Dropping it flushes the spans not exported yet.
+pub struct Telemetry {
+    provider: SdkTracerProvider,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Err(err) = self.provider.shutdown() {
+            eprintln!("Failed to flush spans: {err}");
+        }
+    }
+}
+
+/// Installs the global `tracing` subscriber. Logs go to stdout, filtered by `RUST_LOG`, and
+/// spans are exported over OTLP/HTTP if `OTEL_EXPORTER_OTLP_ENDPOINT` or
+/// `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT` is set, e.g. to `http://localhost:4318` for a local
+/// collector. Without an endpoint, trace context is still propagated, so workers in other
+/// processes that do export keep joining their spans to the same traces.
+pub fn init_telemetry(service_name: &'static str) -> anyhow::Result<Telemetry> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let exporter = OTLP_ENDPOINT_VARS
+        .iter()
+        .any(|var| env::var_os(var).is_some())
+        .then(|| SpanExporter::builder().with_http().build())
+        .transpose()?;
+    let provider = tracer_provider(service_name, exporter);
+
+    tracing_subscriber::registry()
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer(service_name)))
+        .try_init()?;
+    global::set_tracer_provider(provider.clone());
+
+    Ok(Telemetry { provider })
+}
+
+/// Exports the spans of `service_name` in batches with `exporter`, if any.
+fn tracer_provider(
+    service_name: &'static str,
+    exporter: Option<SpanExporter>,
+) -> SdkTracerProvider {
+    let mut provider = SdkTracerProvider::builder()
+        .with_resource(Resource::builder().with_service_name(service_name).build());
+    if let Some(exporter) = exporter {
+        provider = provider.with_batch_exporter(exporter);
+    }
+    provider.build()
+}
+
+/// The trace context of the current span, to send along with a message. `None` if the span
+/// isn't exported to OpenTelemetry.
+pub fn current_trace_context() -> Option<TraceContext> {
+    let mut carrier = Carrier::default();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&Span::current().context(), &mut carrier)
+    });
+    Some(TraceContext {
+        traceparent: carrier.traceparent?,
+        tracestate: carrier.tracestate.filter(|state| !state.is_empty()),
+    })
+}
+
+/// Makes `span` a child of the span that sent a message with `trace_context`.
+pub fn set_parent(span: &Span, trace_context: Option<&TraceContext>) {
+    let Some(trace_context) = trace_context else {
+        return;
+    };
+    let carrier = Carrier {
+        traceparent: Some(trace_context.traceparent.clone()),
+        tracestate: trace_context.tracestate.clone(),
+    };
+    let parent = global::get_text_map_propagator(|propagator| propagator.extract(&carrier));
+    span.set_parent(parent);
+}
+
+#[derive(Default)]
+struct Carrier {
+    traceparent: Option<String>,
+    tracestate: Option<String>,
+}
+
+impl Injector for Carrier {
+    fn set(&mut self, key: &str, value: String) {
+        match key {
+            TRACEPARENT => self.traceparent = Some(value),
+            TRACESTATE => self.tracestate = Some(value),
+            _ => {}
+        }
+    }
+}
+
+impl Extractor for Carrier {
+    fn get(&self, key: &str) -> Option<&str> {
+        match key {
+            TRACEPARENT => self.traceparent.as_deref(),
+            TRACESTATE => self.tracestate.as_deref(),
+            _ => None,
+        }
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        vec![TRACEPARENT, TRACESTATE]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::mpsc,
+        thread,
+        time::Duration,
+    };
+
+    use opentelemetry_otlp::WithExportConfig;
+
+    use super::*;
+
+    /// Accepts OTLP/HTTP requests on a local port and sends their request line and body.
+    fn collector() -> (String, mpsc::Receiver<(String, Vec<u8>)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}/v1/traces", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = BufReader::new(stream.unwrap());
+                let mut request_line = String::new();
+                stream.read_line(&mut request_line).unwrap();
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    stream.read_line(&mut header).unwrap();
+                    let header = header.trim_end();
+                    if header.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':')
+                        && name.eq_ignore_ascii_case("content-length")
+                    {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; content_length];
+                stream.read_exact(&mut body).unwrap();
+                stream
+                    .get_mut()
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                    .unwrap();
+                let _ = sender.send((request_line.trim_end().to_string(), body));
+            }
+        });
+        (endpoint, receiver)
+    }
+
+    fn contains(haystack: &[u8], needle: &str) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle.as_bytes())
+    }
+
+    #[test]
+    fn dropping_telemetry_exports_spans_to_the_collector() {
+        let (endpoint, requests) = collector();
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(endpoint)
+            .build()
+            .unwrap();
+        let telemetry = Telemetry {
+            provider: tracer_provider("surgeflow-test", Some(exporter)),
+        };
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_opentelemetry::layer().with_tracer(telemetry.provider.tracer("surgeflow-test")),
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("exported_span").in_scope(|| {});
+        });
+        drop(telemetry);
+
+        let (request_line, body) = requests.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(request_line, "POST /v1/traces HTTP/1.1");
+        assert!(contains(&body, "surgeflow-test"));
+        assert!(contains(&body, "exported_span"));
+    }
+}
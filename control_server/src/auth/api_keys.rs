@@ -0,0 +1,61 @@
+//! Static API keys, sent in the `X-Api-Key` header. Only their SHA-256 digests are configured, so
+//! the config file doesn't hold the keys themselves.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, ensure};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::{AuthError, AuthRequest, Authenticator, Principal, Scope};
+
+pub(super) const API_KEY_HEADER: &str = "x-api-key";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKey {
+    /// Identifies the key in logs.
+    pub name: String,
+    /// The hex encoded SHA-256 digest of the key.
+    pub sha256: String,
+    pub scopes: Vec<Scope>,
+}
+
+pub struct ApiKeyAuthenticator {
+    keys: HashMap<[u8; 32], (String, Vec<Scope>)>,
+}
+
+impl ApiKeyAuthenticator {
+    pub fn new(keys: Vec<ApiKey>) -> anyhow::Result<Self> {
+        let mut digests = HashMap::with_capacity(keys.len());
+        for key in keys {
+            let mut digest = [0; 32];
+            hex::decode_to_slice(&key.sha256, &mut digest)
+                .with_context(|| format!("invalid digest for API key {}", key.name))?;
+            let name = key.name.clone();
+            ensure!(
+                digests.insert(digest, (key.name, key.scopes)).is_none(),
+                "API key {name} is configured twice"
+            );
+        }
+        Ok(Self { keys: digests })
+    }
+}
+
+impl Authenticator for ApiKeyAuthenticator {
+    fn authenticate(&self, request: &AuthRequest<'_>) -> Result<Option<Principal>, AuthError> {
+        let Some(key) = request.headers.get(API_KEY_HEADER) else {
+            return Ok(None);
+        };
+        // looking up the digest doesn't leak the key through timing, unlike comparing keys
+        let digest: [u8; 32] = Sha256::digest(key.as_bytes()).into();
+        let (name, scopes) = self
+            .keys
+            .get(&digest)
+            .ok_or(AuthError::InvalidCredentials)?;
+        Ok(Some(Principal {
+            subject: format!("api-key:{name}"),
+            scopes: scopes.clone(),
+        }))
+    }
+}
@@ -0,0 +1,213 @@
+//! Requests signed with a shared secret. The signature is the hex encoded HMAC-SHA256 of
+//!
+//! ```text
+//! <timestamp>\n<method>\n<path and query>\n<body>
+//! ```
+//!
+//! where the path is the full one the client requested, including the control server's base
+//! path, and the timestamp is the Unix time in seconds, sent alongside the key id and signature
+//! in headers. Requests more than five minutes off the server's clock are rejected, which bounds
+//! how long a captured request can be replayed.
+//!
+//! Within those five minutes a captured request is accepted again, as no nonces are kept.
+//! Repeating a create request with an `Idempotency-Key` only returns the instance it created,
+//! but other requests, like sending an event, take effect again.
+
+use std::{collections::HashMap, time::Duration};
+
+use ::hmac::{Hmac, Mac};
+use anyhow::{Context, ensure};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use chrono::Utc;
+use serde::Deserialize;
+use sha2::Sha256;
+
+use super::{AuthError, AuthRequest, Authenticator, Principal, Scope};
+
+const KEY_ID_HEADER: &str = "x-surgeflow-key-id";
+const TIMESTAMP_HEADER: &str = "x-surgeflow-timestamp";
+pub(super) const SIGNATURE_HEADER: &str = "x-surgeflow-signature";
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(5 * 60);
+
+pub(super) const SCHEME_DESCRIPTION: &str = "The hex encoded HMAC-SHA256 of \
+    `<timestamp>\\n<method>\\n<path and query>\\n<body>`, keyed with the secret of the key in \
+    `X-Surgeflow-Key-Id`. The path is the full request path, including the base path the control \
+    server is served under. The timestamp is the Unix time in seconds, sent in \
+    `X-Surgeflow-Timestamp`, and must be within five minutes of the server's clock. A signed \
+    request can be replayed within that window: send an `Idempotency-Key` with requests that \
+    create instances, so that a replay returns the instance the original created.";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HmacKey {
+    pub id: String,
+    /// The base64 encoded secret.
+    pub secret: String,
+    pub scopes: Vec<Scope>,
+}
+
+pub struct HmacAuthenticator {
+    keys: HashMap<String, (Vec<u8>, Vec<Scope>)>,
+}
+
+impl HmacAuthenticator {
+    pub fn new(keys: Vec<HmacKey>) -> anyhow::Result<Self> {
+        let mut secrets = HashMap::with_capacity(keys.len());
+        for key in keys {
+            let secret = BASE64_STANDARD
+                .decode(&key.secret)
+                .with_context(|| format!("invalid secret for HMAC key {}", key.id))?;
+            ensure!(!secret.is_empty(), "empty secret for HMAC key {}", key.id);
+            let id = key.id.clone();
+            ensure!(
+                secrets.insert(key.id, (secret, key.scopes)).is_none(),
+                "HMAC key {id} is configured twice"
+            );
+        }
+        Ok(Self { keys: secrets })
+    }
+}
+
+impl Authenticator for HmacAuthenticator {
+    fn needs_body(&self) -> bool {
+        true
+    }
+
+    fn authenticate(&self, request: &AuthRequest<'_>) -> Result<Option<Principal>, AuthError> {
+        let header = |name| {
+            request
+                .headers
+                .get(name)
+                .ok_or(AuthError::InvalidCredentials)?
+                .to_str()
+                .map_err(|_| AuthError::InvalidCredentials)
+        };
+        if !request.headers.contains_key(KEY_ID_HEADER) {
+            return Ok(None);
+        }
+        let key_id = header(KEY_ID_HEADER)?;
+        let timestamp = header(TIMESTAMP_HEADER)?;
+        let signature =
+            hex::decode(header(SIGNATURE_HEADER)?).map_err(|_| AuthError::InvalidCredentials)?;
+        let (secret, scopes) = self.keys.get(key_id).ok_or(AuthError::InvalidCredentials)?;
+
+        let signed_at: i64 = timestamp
+            .parse()
+            .map_err(|_| AuthError::InvalidCredentials)?;
+        if Utc::now().timestamp().abs_diff(signed_at) > MAX_CLOCK_SKEW.as_secs() {
+            return Err(AuthError::InvalidCredentials);
+        }
+
+        let path = request
+            .uri
+            .path_and_query()
+            .map_or("/", |path| path.as_str());
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+        for part in [
+            timestamp.as_bytes(),
+            request.method.as_str().as_bytes(),
+            path.as_bytes(),
+        ] {
+            mac.update(part);
+            mac.update(b"\n");
+        }
+        mac.update(request.body);
+        mac.verify_slice(&signature)
+            .map_err(|_| AuthError::InvalidCredentials)?;
+
+        Ok(Some(Principal {
+            subject: format!("hmac:{key_id}"),
+            scopes: scopes.clone(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{HeaderMap, HeaderValue, Method, Uri};
+
+    use super::*;
+
+    const SECRET: &[u8] = b"test secret";
+    const BODY: &[u8] = br#"{"x":1}"#;
+
+    fn authenticator() -> HmacAuthenticator {
+        HmacAuthenticator::new(vec![HmacKey {
+            id: "test".to_string(),
+            secret: BASE64_STANDARD.encode(SECRET),
+            scopes: vec!["orders:start".parse().unwrap()],
+        }])
+        .unwrap()
+    }
+
+    fn sign(timestamp: i64, path: &str, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(SECRET).unwrap();
+        mac.update(format!("{timestamp}\nPOST\n{path}\n").as_bytes());
+        mac.update(body);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    fn headers(key_id: &str, timestamp: i64, signature: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(KEY_ID_HEADER, HeaderValue::from_str(key_id).unwrap());
+        headers.insert(TIMESTAMP_HEADER, timestamp.into());
+        headers.insert(SIGNATURE_HEADER, HeaderValue::from_str(signature).unwrap());
+        headers
+    }
+
+    fn authenticate(headers: &HeaderMap, body: &[u8]) -> Result<Option<Principal>, AuthError> {
+        let uri = Uri::from_static("/workflow/orders/?wait=1");
+        authenticator().authenticate(&AuthRequest {
+            method: &Method::POST,
+            uri: &uri,
+            headers,
+            body,
+        })
+    }
+
+    #[test]
+    fn valid_signatures_are_accepted() {
+        let now = Utc::now().timestamp();
+        let signature = sign(now, "/workflow/orders/?wait=1", BODY);
+        let principal = authenticate(&headers("test", now, &signature), BODY)
+            .unwrap()
+            .unwrap();
+        assert_eq!(principal.subject, "hmac:test");
+        assert_eq!(principal.scopes, ["orders:start".parse().unwrap()]);
+    }
+
+    #[test]
+    fn requests_without_a_key_id_are_left_to_other_authenticators() {
+        assert!(authenticate(&HeaderMap::new(), BODY).unwrap().is_none());
+    }
+
+    #[test]
+    fn tampered_requests_are_rejected() {
+        let now = Utc::now().timestamp();
+        let signature = sign(now, "/workflow/orders/?wait=1", BODY);
+        let other_path = sign(now, "/workflow/orders/?wait=2", BODY);
+        for (headers, body) in [
+            (headers("test", now, &signature), &br#"{"x":2}"#[..]),
+            (headers("test", now, &other_path), BODY),
+            (headers("test", now + 1, &signature), BODY),
+            (headers("other", now, &signature), BODY),
+            (headers("test", now, "not hex"), BODY),
+        ] {
+            assert!(matches!(
+                authenticate(&headers, body),
+                Err(AuthError::InvalidCredentials)
+            ));
+        }
+    }
+
+    #[test]
+    fn requests_signed_too_long_ago_are_rejected() {
+        let then = Utc::now().timestamp() - MAX_CLOCK_SKEW.as_secs() as i64 - 60;
+        let signature = sign(then, "/workflow/orders/?wait=1", BODY);
+        assert!(matches!(
+            authenticate(&headers("test", then, &signature), BODY),
+            Err(AuthError::InvalidCredentials)
+        ));
+    }
+}
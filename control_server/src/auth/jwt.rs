@@ -0,0 +1,287 @@
+//! JWT bearer tokens, verified against the keys of a JWKS given in the config. The keys are fixed
+//! once the control server starts, so rotating them needs a restart.
+
+use std::str::FromStr;
+
+use jsonwebtoken::{
+    Algorithm, DecodingKey, Validation, decode, decode_header,
+    jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet},
+};
+use serde::Deserialize;
+
+use super::{AuthError, AuthRequest, Authenticator, Principal, Scope};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JwtConfig {
+    /// The keys tokens may be signed with.
+    pub jwks: JwkSet,
+    /// Required `iss` claim, if any.
+    pub issuer: Option<String>,
+    /// Required `aud` claim, if any.
+    pub audience: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    sub: String,
+    /// Space separated, as in OAuth 2.0.
+    #[serde(default)]
+    scope: String,
+}
+
+pub struct JwtAuthenticator {
+    keys: JwkSet,
+    issuer: Option<String>,
+    audience: Option<String>,
+}
+
+impl JwtAuthenticator {
+    pub fn new(config: JwtConfig) -> Self {
+        Self {
+            keys: config.jwks,
+            issuer: config.issuer,
+            audience: config.audience,
+        }
+    }
+}
+
+impl Authenticator for JwtAuthenticator {
+    fn authenticate(&self, request: &AuthRequest<'_>) -> Result<Option<Principal>, AuthError> {
+        let Some(authorization) = request.headers.get("authorization") else {
+            return Ok(None);
+        };
+        let Some(token) = authorization
+            .to_str()
+            .ok()
+            .and_then(|authorization| authorization.strip_prefix("Bearer "))
+        else {
+            return Ok(None);
+        };
+
+        let header = decode_header(token).map_err(|_| AuthError::InvalidCredentials)?;
+        let jwk = header
+            .kid
+            .as_deref()
+            .and_then(|kid| self.keys.find(kid))
+            .ok_or(AuthError::InvalidCredentials)?;
+        let key = DecodingKey::from_jwk(jwk).map_err(|_| AuthError::InvalidCredentials)?;
+        // the key decides which algorithms are acceptable, not the token
+        let algorithms = allowed_algorithms(jwk);
+        let Some(&algorithm) = algorithms.first() else {
+            return Err(AuthError::InvalidCredentials);
+        };
+        let mut validation = Validation::new(algorithm);
+        validation.algorithms = algorithms;
+        // checking a claim doesn't require it, so a token without it would pass
+        match &self.issuer {
+            Some(issuer) => {
+                validation.set_issuer(&[issuer]);
+                validation.required_spec_claims.insert("iss".to_string());
+            }
+            None => validation.iss = None,
+        }
+        match &self.audience {
+            Some(audience) => {
+                validation.set_audience(&[audience]);
+                validation.required_spec_claims.insert("aud".to_string());
+            }
+            None => validation.validate_aud = false,
+        }
+        let claims = decode::<Claims>(token, &key, &validation)
+            .map_err(|err| {
+                tracing::debug!("invalid JWT: {err}");
+                AuthError::InvalidCredentials
+            })?
+            .claims;
+
+        Ok(Some(Principal {
+            subject: format!("jwt:{}", claims.sub),
+            // tokens may carry scopes for other services too
+            scopes: claims
+                .scope
+                .split_whitespace()
+                .filter_map(|scope| scope.parse().ok())
+                .collect::<Vec<Scope>>(),
+        }))
+    }
+}
+
+/// The algorithms tokens signed with `jwk` may use: the key's `alg` if it has one, otherwise
+/// every signature algorithm of its key type. None for keys that can't verify signatures.
+fn allowed_algorithms(jwk: &Jwk) -> Vec<Algorithm> {
+    if let Some(algorithm) = jwk.common.key_algorithm {
+        // encryption algorithms aren't signature algorithms, and fail to parse as one
+        return Algorithm::from_str(&algorithm.to_string())
+            .into_iter()
+            .collect();
+    }
+    match &jwk.algorithm {
+        AlgorithmParameters::RSA(_) => vec![
+            Algorithm::RS256,
+            Algorithm::RS384,
+            Algorithm::RS512,
+            Algorithm::PS256,
+            Algorithm::PS384,
+            Algorithm::PS512,
+        ],
+        AlgorithmParameters::EllipticCurve(parameters) => match parameters.curve {
+            EllipticCurve::P256 => vec![Algorithm::ES256],
+            EllipticCurve::P384 => vec![Algorithm::ES384],
+            _ => Vec::new(),
+        },
+        AlgorithmParameters::OctetKeyPair(_) => vec![Algorithm::EdDSA],
+        AlgorithmParameters::OctetKey(_) => {
+            vec![Algorithm::HS256, Algorithm::HS384, Algorithm::HS512]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{HeaderMap, HeaderValue, Method, Uri};
+    use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+    use chrono::Utc;
+    use jsonwebtoken::{EncodingKey, Header, encode};
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    const SECRET: &[u8] = b"test secret";
+
+    fn authenticator(issuer: Option<&str>, audience: Option<&str>) -> JwtAuthenticator {
+        let config = json!({
+            "jwks": {
+                "keys": [{
+                    "kty": "oct",
+                    "kid": "k1",
+                    "alg": "HS256",
+                    "k": BASE64_URL_SAFE_NO_PAD.encode(SECRET),
+                }]
+            },
+            "issuer": issuer,
+            "audience": audience,
+        });
+        JwtAuthenticator::new(serde_json::from_value(config).unwrap())
+    }
+
+    fn token(algorithm: Algorithm, kid: &str, claims: Value) -> String {
+        let mut header = Header::new(algorithm);
+        header.kid = Some(kid.to_string());
+        encode(&header, &claims, &EncodingKey::from_secret(SECRET)).unwrap()
+    }
+
+    fn claims(extra: Value) -> Value {
+        let mut claims = json!({
+            "sub": "deploy-bot",
+            "exp": Utc::now().timestamp() + 60,
+        });
+        claims
+            .as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        claims
+    }
+
+    fn authenticate(
+        authenticator: &JwtAuthenticator,
+        token: &str,
+    ) -> Result<Option<Principal>, AuthError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "authorization",
+            HeaderValue::from_str(&format!("Bearer {token}")).unwrap(),
+        );
+        authenticator.authenticate(&AuthRequest {
+            method: &Method::GET,
+            uri: &Uri::from_static("/instances"),
+            headers: &headers,
+            body: &[],
+        })
+    }
+
+    #[test]
+    fn valid_tokens_grant_their_known_scopes() {
+        let token = token(
+            Algorithm::HS256,
+            "k1",
+            claims(json!({ "scope": "orders:start openid *:read" })),
+        );
+        let principal = authenticate(&authenticator(None, None), &token)
+            .unwrap()
+            .unwrap();
+        assert_eq!(principal.subject, "jwt:deploy-bot");
+        assert_eq!(
+            principal.scopes,
+            ["orders:start".parse().unwrap(), "*:read".parse().unwrap()]
+        );
+    }
+
+    #[test]
+    fn requests_without_a_bearer_token_are_left_to_other_authenticators() {
+        let request = |headers: &HeaderMap| {
+            authenticator(None, None).authenticate(&AuthRequest {
+                method: &Method::GET,
+                uri: &Uri::from_static("/instances"),
+                headers,
+                body: &[],
+            })
+        };
+        assert!(request(&HeaderMap::new()).unwrap().is_none());
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", HeaderValue::from_static("Basic dXNlcg=="));
+        assert!(request(&headers).unwrap().is_none());
+    }
+
+    #[test]
+    fn expired_tokens_are_rejected() {
+        let token = token(
+            Algorithm::HS256,
+            "k1",
+            claims(json!({ "exp": Utc::now().timestamp() - 600 })),
+        );
+        assert!(matches!(
+            authenticate(&authenticator(None, None), &token),
+            Err(AuthError::InvalidCredentials)
+        ));
+    }
+
+    #[test]
+    fn tokens_signed_with_unknown_keys_are_rejected() {
+        let token = token(Algorithm::HS256, "k2", claims(json!({})));
+        assert!(matches!(
+            authenticate(&authenticator(None, None), &token),
+            Err(AuthError::InvalidCredentials)
+        ));
+    }
+
+    #[test]
+    fn tokens_using_another_algorithm_than_their_key_are_rejected() {
+        let token = token(Algorithm::HS384, "k1", claims(json!({})));
+        assert!(matches!(
+            authenticate(&authenticator(None, None), &token),
+            Err(AuthError::InvalidCredentials)
+        ));
+    }
+
+    #[test]
+    fn issuer_and_audience_are_checked_when_configured() {
+        let authenticator = authenticator(Some("https://issuer"), Some("surgeflow"));
+        let valid = claims(json!({ "iss": "https://issuer", "aud": "surgeflow" }));
+        assert!(
+            authenticate(&authenticator, &token(Algorithm::HS256, "k1", valid))
+                .unwrap()
+                .is_some()
+        );
+        for claims in [
+            claims(json!({ "iss": "https://other", "aud": "surgeflow" })),
+            claims(json!({ "iss": "https://issuer", "aud": "other" })),
+            claims(json!({})),
+        ] {
+            assert!(matches!(
+                authenticate(&authenticator, &token(Algorithm::HS256, "k1", claims)),
+                Err(AuthError::InvalidCredentials)
+            ));
+        }
+    }
+}
@@ -0,0 +1,646 @@
+//! Authentication and authorization for the control server. Every request must carry credentials
+//! that one of the configured [`Authenticator`]s accepts, and each route checks the scopes they
+//! grant with the [`Authorized`] extractor.
+//!
+//! Scopes are written `<workflow>:<operation>`, like `orders:start`, where either part can be
+//! `*`. The dead letter routes use `dead-letters` in place of a workflow name, and the worker
+//! routes `workers`.
+
+use std::{fmt, fs, marker::PhantomData, path::Path, str::FromStr, sync::Arc};
+
+use aide::{
+    OperationInput, OperationIo,
+    generate::GenContext,
+    openapi::{ApiKeyLocation, Components, OpenApi, ReferenceOr, SecurityScheme},
+};
+use anyhow::Context;
+use axum::{
+    body::{Body, Bytes, to_bytes},
+    extract::{FromRequestParts, OriginalUri, Request, State},
+    http::{HeaderMap, Method, StatusCode, Uri, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use surgeflow_types::{Project, Workflow};
+
+mod api_keys;
+mod hmac;
+mod jwt;
+
+pub use api_keys::{ApiKey, ApiKeyAuthenticator};
+pub use hmac::{HmacAuthenticator, HmacKey};
+pub use jwt::{JwtAuthenticator, JwtConfig};
+
+/// Largest body buffered to verify a request signature, even if the control server accepts
+/// larger bodies otherwise.
+const MAX_SIGNED_BODY: usize = 2 * 1024 * 1024;
+
+const API_KEY_SCHEME: &str = "api-key";
+const HMAC_SCHEME: &str = "hmac";
+const JWT_SCHEME: &str = "jwt";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// Creating instances.
+    Start,
+    SendEvent,
+    Cancel,
+    /// Reading instances, their history and their notifications.
+    Read,
+    /// Deleting and redriving dead letters.
+    Manage,
+}
+
+impl Operation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Start => "start",
+            Self::SendEvent => "send-event",
+            Self::Cancel => "cancel",
+            Self::Read => "read",
+            Self::Manage => "manage",
+        }
+    }
+}
+
+impl FromStr for Operation {
+    type Err = InvalidScope;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "start" => Self::Start,
+            "send-event" => Self::SendEvent,
+            "cancel" => Self::Cancel,
+            "read" => Self::Read,
+            "manage" => Self::Manage,
+            _ => return Err(InvalidScope(s.to_string())),
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid scope {0:?}")]
+pub struct InvalidScope(String);
+
+/// Permission for an operation on a workflow. `None` stands for `*`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Scope {
+    target: Option<String>,
+    operation: Option<Operation>,
+}
+
+impl Scope {
+    pub fn new(target: &str, operation: Operation) -> Self {
+        Self {
+            target: Some(target.to_string()),
+            operation: Some(operation),
+        }
+    }
+
+    /// A scope that grants everything.
+    pub fn all() -> Self {
+        Self {
+            target: None,
+            operation: None,
+        }
+    }
+
+    pub fn grants(&self, target: &str, operation: Operation) -> bool {
+        self.target.as_deref().is_none_or(|t| t == target)
+            && self.operation.is_none_or(|o| o == operation)
+    }
+}
+
+impl FromStr for Scope {
+    type Err = InvalidScope;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "*" {
+            return Ok(Self::all());
+        }
+        let (target, operation) = s
+            .split_once(':')
+            .ok_or_else(|| InvalidScope(s.to_string()))?;
+        if target.is_empty() {
+            return Err(InvalidScope(s.to_string()));
+        }
+        Ok(Self {
+            target: (target != "*").then(|| target.to_string()),
+            operation: match operation {
+                "*" => None,
+                operation => Some(operation.parse().map_err(|_| InvalidScope(s.to_string()))?),
+            },
+        })
+    }
+}
+
+impl TryFrom<String> for Scope {
+    type Error = InvalidScope;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.target, self.operation) {
+            (None, None) => f.write_str("*"),
+            (target, operation) => write!(
+                f,
+                "{}:{}",
+                target.as_deref().unwrap_or("*"),
+                operation.map_or("*", Operation::as_str)
+            ),
+        }
+    }
+}
+
+/// Whoever sent a request, as established by an [`Authenticator`].
+#[derive(Debug, Clone)]
+pub struct Principal {
+    /// Identifies the credentials in logs, like `api-key:deploy-bot`.
+    pub subject: String,
+    pub scopes: Vec<Scope>,
+}
+
+impl Principal {
+    /// The principal of every request when authentication is disabled.
+    fn anonymous() -> Self {
+        Self {
+            subject: "anonymous".to_string(),
+            scopes: vec![Scope::all()],
+        }
+    }
+
+    pub fn is_granted(&self, target: &str, operation: Operation) -> bool {
+        self.scopes
+            .iter()
+            .any(|scope| scope.grants(target, operation))
+    }
+}
+
+/// What an [`Authenticator`] gets to see of a request.
+pub struct AuthRequest<'a> {
+    pub method: &'a Method,
+    /// As sent by the client, including the control server's base path.
+    pub uri: &'a Uri,
+    pub headers: &'a HeaderMap,
+    /// Empty unless an authenticator [needs it](Authenticator::needs_body).
+    pub body: &'a [u8],
+}
+
+pub trait Authenticator: Send + Sync + 'static {
+    /// Whether the body must be buffered for [`AuthRequest::body`].
+    fn needs_body(&self) -> bool {
+        false
+    }
+
+    /// `Ok(None)` if the request has no credentials of this kind, so the next authenticator gets
+    /// to try.
+    fn authenticate(&self, request: &AuthRequest<'_>) -> Result<Option<Principal>, AuthError>;
+}
+
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    Clone,
+    thiserror::Error,
+    axum_thiserror::ErrorStatus,
+    OperationIo,
+)]
+pub enum AuthError {
+    #[error("missing credentials")]
+    #[status(StatusCode::UNAUTHORIZED)]
+    MissingCredentials,
+    #[error("invalid credentials")]
+    #[status(StatusCode::UNAUTHORIZED)]
+    InvalidCredentials,
+    #[error("request body too large")]
+    #[status(StatusCode::PAYLOAD_TOO_LARGE)]
+    BodyTooLarge,
+    #[error("missing the {0} scope")]
+    #[status(StatusCode::FORBIDDEN)]
+    MissingScope(String),
+}
+
+/// The credentials the control server accepts.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub api_keys: Vec<ApiKey>,
+    #[serde(default)]
+    pub hmac_keys: Vec<HmacKey>,
+    pub jwt: Option<JwtConfig>,
+}
+
+impl AuthConfig {
+    /// Reads the config from a JSON file, for binaries that keep their credentials in one.
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let config = fs::read(path)
+            .with_context(|| format!("couldn't read auth config {}", path.display()))?;
+        serde_json::from_slice(&config)
+            .with_context(|| format!("invalid auth config {}", path.display()))
+    }
+}
+
+/// Whether the control server requires credentials.
+#[derive(Debug, Clone)]
+pub enum AuthMode {
+    /// Every request is allowed everything, as for local development.
+    Unauthenticated,
+    /// Requests must carry credentials the config accepts. A config without any denies every
+    /// request.
+    Required(AuthConfig),
+}
+
+impl Default for AuthMode {
+    fn default() -> Self {
+        Self::Required(AuthConfig::default())
+    }
+}
+
+pub enum Auth {
+    /// Every request is allowed everything.
+    Disabled,
+    /// Requests must be accepted by one of these, tried in order.
+    Enabled(Vec<Box<dyn Authenticator>>),
+}
+
+impl Auth {
+    pub fn new(mode: AuthMode) -> anyhow::Result<Self> {
+        match mode {
+            AuthMode::Unauthenticated => {
+                tracing::warn!(
+                    "Authentication is disabled, the control server accepts every request"
+                );
+                Ok(Self::Disabled)
+            }
+            AuthMode::Required(config) => Self::from_config(config),
+        }
+    }
+
+    pub fn from_config(config: AuthConfig) -> anyhow::Result<Self> {
+        let mut authenticators: Vec<Box<dyn Authenticator>> = Vec::new();
+        if !config.api_keys.is_empty() {
+            authenticators.push(Box::new(ApiKeyAuthenticator::new(config.api_keys)?));
+        }
+        if !config.hmac_keys.is_empty() {
+            authenticators.push(Box::new(HmacAuthenticator::new(config.hmac_keys)?));
+        }
+        if let Some(jwt) = config.jwt {
+            authenticators.push(Box::new(JwtAuthenticator::new(jwt)));
+        }
+        // an empty config denies everything rather than silently allowing everything
+        if authenticators.is_empty() {
+            tracing::warn!(
+                "No credentials are configured, the control server denies every request"
+            );
+        }
+        Ok(Self::Enabled(authenticators))
+    }
+
+    fn authenticate(
+        authenticators: &[Box<dyn Authenticator>],
+        request: &AuthRequest<'_>,
+    ) -> Result<Principal, AuthError> {
+        for authenticator in authenticators {
+            if let Some(principal) = authenticator.authenticate(request)? {
+                return Ok(principal);
+            }
+        }
+        Err(AuthError::MissingCredentials)
+    }
+}
+
+/// Middleware that authenticates every request and stores its [`Principal`] for [`Authorized`].
+/// Signed requests have their body buffered, so it must run inside a request body limit.
+///
+/// Signatures are checked against the request's [`OriginalUri`], so any layer rewriting the path
+/// must run inside a router that records it first.
+pub async fn authenticate(State(auth): State<Arc<Auth>>, request: Request, next: Next) -> Response {
+    let authenticators = match auth.as_ref() {
+        Auth::Disabled => {
+            let mut request = request;
+            request.extensions_mut().insert(Principal::anonymous());
+            return next.run(request).await;
+        }
+        Auth::Enabled(authenticators) => authenticators,
+    };
+
+    let (mut parts, body) = request.into_parts();
+    let (body, bytes) = if authenticators.iter().any(|a| a.needs_body()) {
+        // the control server's body limit applies too, whichever is smaller
+        let Ok(bytes) = to_bytes(body, MAX_SIGNED_BODY).await else {
+            return AuthError::BodyTooLarge.into_response();
+        };
+        (Body::from(bytes.clone()), bytes)
+    } else {
+        (body, Bytes::new())
+    };
+    // the URI the client sent, recorded by the outermost router, not the one left after nesting
+    // under a base path
+    let uri = parts
+        .extensions
+        .get::<OriginalUri>()
+        .map_or(&parts.uri, |OriginalUri(uri)| uri);
+    let request = AuthRequest {
+        method: &parts.method,
+        uri,
+        headers: &parts.headers,
+        body: &bytes,
+    };
+    match Auth::authenticate(authenticators, &request) {
+        Ok(principal) => {
+            tracing::debug!("authenticated {}", principal.subject);
+            parts.extensions.insert(principal);
+        }
+        Err(err) => {
+            tracing::debug!("rejected {} {}: {err}", parts.method, parts.uri);
+            return err.into_response();
+        }
+    }
+    next.run(Request::from_parts(parts, body)).await
+}
+
+/// The operation a route performs, and on what.
+pub trait RequiredScope<P: Project> {
+    fn target() -> &'static str;
+    const OPERATION: Operation;
+}
+
+macro_rules! workflow_scopes {
+    ($($(#[$meta:meta])* $name:ident => $operation:ident,)*) => {$(
+        $(#[$meta])*
+        pub struct $name<T>(PhantomData<T>);
+
+        impl<P: Project, T: Workflow<P>> RequiredScope<P> for $name<T> {
+            fn target() -> &'static str {
+                <T as Workflow<P>>::NAME
+            }
+            const OPERATION: Operation = Operation::$operation;
+        }
+    )*};
+}
+
+workflow_scopes! {
+    /// Creating instances of `T`.
+    StartScope => Start,
+    /// Sending events to instances of `T`.
+    SendEventScope => SendEvent,
+    /// Cancelling instances of `T`.
+    CancelScope => Cancel,
+    /// Reading instances of `T`.
+    ReadScope => Read,
+}
+
+pub const DEAD_LETTERS: &str = "dead-letters";
+
+/// Listing and reading dead letters.
+pub struct ReadDeadLettersScope;
+
+impl<P: Project> RequiredScope<P> for ReadDeadLettersScope {
+    fn target() -> &'static str {
+        DEAD_LETTERS
+    }
+    const OPERATION: Operation = Operation::Read;
+}
+
+/// Deleting and redriving dead letters.
+pub struct ManageDeadLettersScope;
+
+impl<P: Project> RequiredScope<P> for ManageDeadLettersScope {
+    fn target() -> &'static str {
+        DEAD_LETTERS
+    }
+    const OPERATION: Operation = Operation::Manage;
+}
+
+pub const WORKERS: &str = "workers";
+
+/// Listing the live worker processes.
+pub struct ReadWorkersScope;
+
+impl<P: Project> RequiredScope<P> for ReadWorkersScope {
+    fn target() -> &'static str {
+        WORKERS
+    }
+    const OPERATION: Operation = Operation::Read;
+}
+
+/// Rejects requests whose principal lacks the scope `S`, and documents that scope on the route.
+pub struct Authorized<P, S>(pub Principal, PhantomData<fn() -> (P, S)>);
+
+impl<P, S, State> FromRequestParts<State> for Authorized<P, S>
+where
+    P: Project,
+    S: RequiredScope<P>,
+    State: Send + Sync,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, _: &State) -> Result<Self, Self::Rejection> {
+        // missing if the router wasn't wrapped in `authenticate`, which must not mean open
+        let principal = parts
+            .extensions
+            .get::<Principal>()
+            .cloned()
+            .ok_or(AuthError::MissingCredentials)?;
+        if !principal.is_granted(S::target(), S::OPERATION) {
+            let scope = Scope::new(S::target(), S::OPERATION);
+            tracing::debug!("{} lacks the {scope} scope", principal.subject);
+            return Err(AuthError::MissingScope(scope.to_string()));
+        }
+        Ok(Self(principal, PhantomData))
+    }
+}
+
+impl<P: Project, S: RequiredScope<P>> OperationInput for Authorized<P, S> {
+    fn operation_input(_: &mut GenContext, operation: &mut aide::openapi::Operation) {
+        let scope = Scope::new(S::target(), S::OPERATION).to_string();
+        for scheme in [API_KEY_SCHEME, HMAC_SCHEME, JWT_SCHEME] {
+            operation.security.push(
+                [(scheme.to_string(), vec![scope.clone()])]
+                    .into_iter()
+                    .collect(),
+            );
+        }
+    }
+}
+
+/// Documents the credentials [`Auth`] can accept.
+pub fn add_security_schemes(api: &mut OpenApi) {
+    let schemes = &mut api
+        .components
+        .get_or_insert_with(Components::default)
+        .security_schemes;
+    schemes.insert(
+        API_KEY_SCHEME.to_string(),
+        ReferenceOr::Item(SecurityScheme::ApiKey {
+            location: ApiKeyLocation::Header,
+            name: api_keys::API_KEY_HEADER.to_string(),
+            description: Some("A static API key.".to_string()),
+            extensions: Default::default(),
+        }),
+    );
+    schemes.insert(
+        HMAC_SCHEME.to_string(),
+        ReferenceOr::Item(SecurityScheme::ApiKey {
+            location: ApiKeyLocation::Header,
+            name: hmac::SIGNATURE_HEADER.to_string(),
+            description: Some(hmac::SCHEME_DESCRIPTION.to_string()),
+            extensions: Default::default(),
+        }),
+    );
+    schemes.insert(
+        JWT_SCHEME.to_string(),
+        ReferenceOr::Item(SecurityScheme::Http {
+            scheme: "bearer".to_string(),
+            bearer_format: Some("JWT".to_string()),
+            description: Some(
+                "A JWT signed by a key in the configured JWKS, with the granted scopes in its \
+                 space separated `scope` claim."
+                    .to_string(),
+            ),
+            extensions: Default::default(),
+        }),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use ::hmac::{Hmac, Mac};
+    use axum::{Router, middleware, routing::post};
+    use base64::{Engine, prelude::BASE64_STANDARD};
+    use chrono::Utc;
+    use sha2::Sha256;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    const SECRET: &[u8] = b"test secret";
+    const BODY: &str = r#"{"x":1}"#;
+
+    /// A router serving `/instances` under the base path `/surgeflow`, behind HMAC authentication.
+    fn app() -> Router {
+        app_with(AuthMode::Required(AuthConfig {
+            hmac_keys: vec![HmacKey {
+                id: "test".to_string(),
+                secret: BASE64_STANDARD.encode(SECRET),
+                scopes: vec![Scope::all()],
+            }],
+            ..AuthConfig::default()
+        }))
+    }
+
+    fn app_with(mode: AuthMode) -> Router {
+        let auth = Auth::new(mode).unwrap();
+        let routes = Router::new()
+            .route("/instances", post(|| async { "created" }))
+            .layer(middleware::from_fn_with_state(Arc::new(auth), authenticate));
+        Router::new().nest("/surgeflow", routes)
+    }
+
+    fn signed_request(uri: &str, signed_path: &str) -> Request {
+        let timestamp = Utc::now().timestamp().to_string();
+        let mut mac = Hmac::<Sha256>::new_from_slice(SECRET).unwrap();
+        mac.update(format!("{timestamp}\nPOST\n{signed_path}\n{BODY}").as_bytes());
+        Request::builder()
+            .method(Method::POST)
+            .uri(uri)
+            .header("x-surgeflow-key-id", "test")
+            .header("x-surgeflow-timestamp", timestamp)
+            .header(
+                "x-surgeflow-signature",
+                hex::encode(mac.finalize().into_bytes()),
+            )
+            .body(Body::from(BODY))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn signatures_cover_the_base_path() {
+        let uri = "/surgeflow/instances?wait=1";
+        let response = app().oneshot(signed_request(uri, uri)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn signatures_without_the_base_path_are_rejected() {
+        let request = signed_request("/surgeflow/instances?wait=1", "/instances?wait=1");
+        let response = app().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn requests_are_denied_until_credentials_are_configured() {
+        let request = || {
+            Request::builder()
+                .method(Method::POST)
+                .uri("/surgeflow/instances")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let denied = app_with(AuthMode::default())
+            .oneshot(request())
+            .await
+            .unwrap();
+        assert_eq!(denied.status(), StatusCode::UNAUTHORIZED);
+        let allowed = app_with(AuthMode::Unauthenticated)
+            .oneshot(request())
+            .await
+            .unwrap();
+        assert_eq!(allowed.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn scopes_parse_workflow_and_operation() {
+        let scope: Scope = "orders:start".parse().unwrap();
+        assert_eq!(scope, Scope::new("orders", Operation::Start));
+        assert!(scope.grants("orders", Operation::Start));
+        assert!(!scope.grants("orders", Operation::Read));
+        assert!(!scope.grants("invoices", Operation::Start));
+    }
+
+    #[test]
+    fn scopes_parse_wildcards() {
+        assert_eq!("*".parse::<Scope>().unwrap(), Scope::all());
+        assert_eq!("*:*".parse::<Scope>().unwrap(), Scope::all());
+
+        let any_workflow: Scope = "*:read".parse().unwrap();
+        assert!(any_workflow.grants("orders", Operation::Read));
+        assert!(any_workflow.grants("dead-letters", Operation::Read));
+        assert!(!any_workflow.grants("orders", Operation::Start));
+
+        let any_operation: Scope = "orders:*".parse().unwrap();
+        assert!(any_operation.grants("orders", Operation::Cancel));
+        assert!(!any_operation.grants("invoices", Operation::Cancel));
+    }
+
+    #[test]
+    fn invalid_scopes_are_rejected() {
+        for scope in [
+            "",
+            "orders",
+            ":start",
+            "orders:",
+            "orders:fly",
+            "orders:start:now",
+        ] {
+            assert!(scope.parse::<Scope>().is_err(), "{scope:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn scopes_display_as_they_parse() {
+        for scope in ["*", "orders:start", "*:send-event", "orders:*"] {
+            assert_eq!(scope.parse::<Scope>().unwrap().to_string(), scope);
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    path::PathBuf,
+};
+
+use crate::auth::AuthMode;
+
+/// How the control server is served.
+#[derive(Debug, Clone)]
+pub struct ControlServerConfig {
+    pub bind: SocketAddr,
+    /// Serve HTTPS instead of plain HTTP.
+    pub tls: Option<TlsConfig>,
+    /// Prefix of every route, like `/surgeflow`. Empty to serve from the root.
+    pub base_path: String,
+    /// Whether to serve `/openapi.json` and `/docs`. Defaults to debug builds only.
+    pub expose_docs: bool,
+    /// Largest request body accepted, in bytes. Larger requests get a 413.
+    pub max_body_size: usize,
+    /// Defaults to requiring credentials without accepting any, so that serving requests takes
+    /// either configuring some or choosing [`AuthMode::Unauthenticated`].
+    pub auth: AuthMode,
+}
+
+impl Default for ControlServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from((Ipv4Addr::UNSPECIFIED, 8080)),
+            tls: None,
+            base_path: String::new(),
+            expose_docs: cfg!(debug_assertions),
+            max_body_size: 2 * 1024 * 1024,
+            auth: AuthMode::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// PEM encoded certificate chain.
+    pub cert: PathBuf,
+    /// PEM encoded private key.
+    pub key: PathBuf,
+}
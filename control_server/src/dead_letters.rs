@@ -0,0 +1,199 @@
+use adapter_types::{
+    dead_letters::{DeadLetter, DeadLetterId, DeadLetterManager, Queue},
+    dependencies::ControlServerDependencyProvider,
+};
+use aide::{OperationIo, axum::ApiRouter};
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use axum_extra::routing::TypedPath;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use surgeflow_types::Project;
+
+use crate::{
+    ArcAppState, DEFAULT_LIST_LIMIT, MAX_LIST_LIMIT,
+    auth::{Authorized, ManageDeadLettersScope, ReadDeadLettersScope},
+};
+
+const TAG: &str = "dead-letters";
+
+pub fn dead_letter_router<P: Project, D: ControlServerDependencyProvider<P> + 'static>()
+-> ApiRouter<ArcAppState<P, D>> {
+    ApiRouter::new().nest(
+        "/dead-letters",
+        ApiRouter::new()
+            .merge(list_dead_letters_api_route::<P, D>())
+            .merge(get_dead_letter_api_route::<P, D>())
+            .merge(delete_dead_letter_api_route::<P, D>())
+            .merge(redrive_dead_letter_api_route::<P, D>()),
+    )
+}
+
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    Clone,
+    thiserror::Error,
+    axum_thiserror::ErrorStatus,
+    OperationIo,
+)]
+enum DeadLetterError {
+    #[error("dead letter not found")]
+    #[status(StatusCode::NOT_FOUND)]
+    NotFound,
+    #[error("could not access dead letter queue")]
+    #[status(StatusCode::INTERNAL_SERVER_ERROR)]
+    CouldntAccessDeadLetterQueue,
+}
+
+fn list_dead_letters_api_route<P: Project, D: ControlServerDependencyProvider<P> + 'static>()
+-> ApiRouter<ArcAppState<P, D>> {
+    #[derive(TypedPath, Deserialize, JsonSchema, OperationIo)]
+    #[typed_path("/{queue}")]
+    pub struct ListDeadLetters {
+        queue: Queue,
+    }
+
+    #[derive(Deserialize, JsonSchema)]
+    pub struct ListDeadLettersQuery {
+        /// Only return dead letters after this one.
+        after: Option<DeadLetterId>,
+        limit: Option<u32>,
+    }
+
+    // more readable than a closure
+    async fn handler<P: Project, D: ControlServerDependencyProvider<P>>(
+        ListDeadLetters { queue }: ListDeadLetters,
+        _: Authorized<P, ReadDeadLettersScope>,
+        State(ArcAppState(state)): State<ArcAppState<P, D>>,
+        Query(ListDeadLettersQuery { after, limit }): Query<ListDeadLettersQuery>,
+    ) -> Result<Json<Vec<DeadLetter>>, DeadLetterError> {
+        let limit = limit.unwrap_or(DEFAULT_LIST_LIMIT).min(MAX_LIST_LIMIT);
+        let dead_letters = state
+            .dependencies
+            .dead_letter_manager
+            .list(queue, after, limit)
+            .await
+            .map_err(|_| DeadLetterError::CouldntAccessDeadLetterQueue)?;
+        Ok(Json(dead_letters))
+    }
+
+    ApiRouter::new().typed_get_with(handler::<P, D>, |op| {
+        op.description("List the dead letters of a queue, oldest first")
+            .summary("List dead letters")
+            .id("list-dead-letters")
+            .tag(TAG)
+            .hidden(false)
+    })
+}
+
+fn get_dead_letter_api_route<P: Project, D: ControlServerDependencyProvider<P> + 'static>()
+-> ApiRouter<ArcAppState<P, D>> {
+    #[derive(TypedPath, Deserialize, JsonSchema, OperationIo)]
+    #[typed_path("/{queue}/{id}")]
+    pub struct GetDeadLetter {
+        queue: Queue,
+        id: DeadLetterId,
+    }
+
+    // more readable than a closure
+    async fn handler<P: Project, D: ControlServerDependencyProvider<P>>(
+        GetDeadLetter { queue, id }: GetDeadLetter,
+        _: Authorized<P, ReadDeadLettersScope>,
+        State(ArcAppState(state)): State<ArcAppState<P, D>>,
+    ) -> Result<Json<DeadLetter>, DeadLetterError> {
+        let dead_letter = state
+            .dependencies
+            .dead_letter_manager
+            .get(queue, id)
+            .await
+            .map_err(|_| DeadLetterError::CouldntAccessDeadLetterQueue)?
+            .ok_or(DeadLetterError::NotFound)?;
+        Ok(Json(dead_letter))
+    }
+
+    ApiRouter::new().typed_get_with(handler::<P, D>, |op| {
+        op.description("Get a dead letter, including its payload and failure reason")
+            .summary("Get dead letter")
+            .id("get-dead-letter")
+            .tag(TAG)
+            .hidden(false)
+    })
+}
+
+fn delete_dead_letter_api_route<P: Project, D: ControlServerDependencyProvider<P> + 'static>()
+-> ApiRouter<ArcAppState<P, D>> {
+    #[derive(TypedPath, Deserialize, JsonSchema, OperationIo)]
+    #[typed_path("/{queue}/{id}")]
+    pub struct DeleteDeadLetter {
+        queue: Queue,
+        id: DeadLetterId,
+    }
+
+    // more readable than a closure
+    async fn handler<P: Project, D: ControlServerDependencyProvider<P>>(
+        DeleteDeadLetter { queue, id }: DeleteDeadLetter,
+        _: Authorized<P, ManageDeadLettersScope>,
+        State(ArcAppState(state)): State<ArcAppState<P, D>>,
+    ) -> Result<(), DeadLetterError> {
+        let deleted = state
+            .dependencies
+            .dead_letter_manager
+            .delete(queue, id)
+            .await
+            .map_err(|_| DeadLetterError::CouldntAccessDeadLetterQueue)?;
+        if !deleted {
+            return Err(DeadLetterError::NotFound);
+        }
+        Ok(())
+    }
+
+    ApiRouter::new().typed_delete_with(handler::<P, D>, |op| {
+        op.description("Discard a dead letter")
+            .summary("Delete dead letter")
+            .id("delete-dead-letter")
+            .tag(TAG)
+            .hidden(false)
+    })
+}
+
+fn redrive_dead_letter_api_route<P: Project, D: ControlServerDependencyProvider<P> + 'static>()
+-> ApiRouter<ArcAppState<P, D>> {
+    #[derive(TypedPath, Deserialize, JsonSchema, OperationIo)]
+    #[typed_path("/{queue}/{id}/redrive")]
+    pub struct RedriveDeadLetter {
+        queue: Queue,
+        id: DeadLetterId,
+    }
+
+    // more readable than a closure
+    async fn handler<P: Project, D: ControlServerDependencyProvider<P>>(
+        RedriveDeadLetter { queue, id }: RedriveDeadLetter,
+        _: Authorized<P, ManageDeadLettersScope>,
+        State(ArcAppState(state)): State<ArcAppState<P, D>>,
+    ) -> Result<(), DeadLetterError> {
+        let redriven = state
+            .dependencies
+            .dead_letter_manager
+            .redrive(queue, id)
+            .await
+            .map_err(|_| DeadLetterError::CouldntAccessDeadLetterQueue)?;
+        if !redriven {
+            return Err(DeadLetterError::NotFound);
+        }
+        Ok(())
+    }
+
+    ApiRouter::new().typed_post_with(handler::<P, D>, |op| {
+        op.description("Publish a dead letter back to the queue it came from")
+            .summary("Redrive dead letter")
+            .id("redrive-dead-letter")
+            .tag(TAG)
+            .hidden(false)
+    })
+}
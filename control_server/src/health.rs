@@ -0,0 +1,153 @@
+//! `/healthz` and `/readyz`, reporting the workers' [`Health`]: whether every worker loop is still
+//! running, and whether every dependency passes its health check.
+
+use adapter_types::{
+    dependencies::ControlServerDependencyProvider,
+    health::{DependencyHealth, Health, LivenessResponse, ReadinessResponse, run_check},
+    managers::{PersistenceManager, StepsAwaitingEventManager},
+    senders::{EventSender, NewInstanceSender},
+};
+use aide::{OperationIo, axum::ApiRouter};
+use axum::{Json, extract::State, http::StatusCode};
+use axum_extra::routing::TypedPath;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use surgeflow_types::Project;
+
+use crate::ArcAppState;
+
+const TAG: &str = "health";
+/// The worker name the control server's own dependencies are reported under.
+const CONTROL_SERVER: &str = "control_server";
+
+pub(crate) fn liveness_response(health: &Health) -> (StatusCode, Json<LivenessResponse>) {
+    let liveness = health.liveness();
+    let status = if liveness.alive {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(liveness))
+}
+
+pub(crate) fn readiness_response(
+    dependencies: Vec<DependencyHealth>,
+) -> (StatusCode, Json<ReadinessResponse>) {
+    let ready = dependencies.iter().all(|dependency| dependency.healthy);
+    for dependency in dependencies.iter().filter(|dependency| !dependency.healthy) {
+        tracing::warn!(
+            "{} of {} is unhealthy: {}",
+            dependency.dependency,
+            dependency.worker,
+            dependency.error.as_deref().unwrap_or_default()
+        );
+    }
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(ReadinessResponse {
+            ready,
+            dependencies,
+        }),
+    )
+}
+
+pub fn health_router<P: Project, D: ControlServerDependencyProvider<P> + 'static>()
+-> ApiRouter<ArcAppState<P, D>> {
+    ApiRouter::new()
+        .merge(healthz_api_route::<P, D>())
+        .merge(readyz_api_route::<P, D>())
+}
+
+fn healthz_api_route<P: Project, D: ControlServerDependencyProvider<P> + 'static>()
+-> ApiRouter<ArcAppState<P, D>> {
+    #[derive(TypedPath, Deserialize, JsonSchema, OperationIo)]
+    #[typed_path("/healthz")]
+    pub struct Healthz;
+
+    // more readable than a closure
+    async fn handler<P: Project, D: ControlServerDependencyProvider<P>>(
+        _: Healthz,
+        State(ArcAppState(state)): State<ArcAppState<P, D>>,
+    ) -> (StatusCode, Json<LivenessResponse>) {
+        liveness_response(&state.health)
+    }
+    ApiRouter::new().typed_get_with(handler::<P, D>, |op| {
+        op.description(
+            "Whether every worker loop in this process is running. Responds with 503 otherwise.",
+        )
+        .summary("Liveness")
+        .id("healthz")
+        .tag(TAG)
+        .hidden(false)
+    })
+}
+
+fn readyz_api_route<P: Project, D: ControlServerDependencyProvider<P> + 'static>()
+-> ApiRouter<ArcAppState<P, D>> {
+    #[derive(TypedPath, Deserialize, JsonSchema, OperationIo)]
+    #[typed_path("/readyz")]
+    pub struct Readyz;
+
+    // more readable than a closure
+    async fn handler<P: Project, D: ControlServerDependencyProvider<P>>(
+        _: Readyz,
+        State(ArcAppState(state)): State<ArcAppState<P, D>>,
+    ) -> (StatusCode, Json<ReadinessResponse>) {
+        let own = &state.dependencies;
+        let (event_sender, new_instance_sender, persistence_manager, steps_awaiting_event, workers) = tokio::join!(
+            run_check(own.event_sender.health_check()),
+            run_check(own.new_instance_sender.health_check()),
+            run_check(own.persistence_manager.health_check()),
+            run_check(own.steps_awaiting_event_manager.health_check()),
+            state.health.dependency_checks(),
+        );
+        let mut dependencies = vec![
+            DependencyHealth::new(CONTROL_SERVER, "event_sender", event_sender),
+            DependencyHealth::new(CONTROL_SERVER, "new_instance_sender", new_instance_sender),
+            DependencyHealth::new(CONTROL_SERVER, "persistence_manager", persistence_manager),
+            DependencyHealth::new(
+                CONTROL_SERVER,
+                "steps_awaiting_event_manager",
+                steps_awaiting_event,
+            ),
+        ];
+        dependencies.extend(workers);
+
+        readiness_response(dependencies)
+    }
+    ApiRouter::new().typed_get_with(handler::<P, D>, |op| {
+        op.description(
+            "Health of every dependency of the workers in this process and of the control \
+             server. Responds with 503 if any is unhealthy.",
+        )
+        .summary("Readiness")
+        .id("readyz")
+        .tag(TAG)
+        .hidden(false)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::pending;
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn a_hanging_dependency_makes_the_process_unready() {
+        let health = Health::default();
+        health.register_check("worker", "healthy", (), |()| async { Ok::<_, String>(()) });
+        health.register_check("worker", "hanging", (), |()| {
+            pending::<Result<(), String>>()
+        });
+
+        let (status, Json(readiness)) = readiness_response(health.dependency_checks().await);
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(!readiness.ready);
+    }
+}
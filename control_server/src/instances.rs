@@ -0,0 +1,682 @@
+use std::collections::HashMap;
+
+use adapter_types::{
+    dependencies::ControlServerDependencyProvider,
+    idempotency::{IdempotencyRecord, IdempotencyStore},
+    managers::{
+        InstanceRecord, PersistenceManager, StepAttemptRecord, StepRecord,
+        StepsAwaitingEventManager,
+    },
+    senders::NewInstanceSender,
+    telemetry::current_trace_context,
+};
+use aide::OperationIo;
+use axum::http::{HeaderMap, StatusCode};
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use surgeflow_types::{
+    __Step, __WorkflowStatic, BusinessId, HeartbeatDetails, InstanceStatus, Project, StepId,
+    StepStatus, Tags, TaskQueue, Workflow, WorkflowInstance, WorkflowInstanceId, WorkflowName,
+};
+
+use crate::{Dependencies, IDEMPOTENCY_RETENTION};
+
+const IDEMPOTENCY_KEY: &str = "idempotency-key";
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+const MAX_BUSINESS_ID_LEN: usize = 255;
+
+/// What an instance is doing right now.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct InstanceStatusResponse {
+    pub instance_id: WorkflowInstanceId,
+    pub business_id: Option<BusinessId>,
+    pub workflow: String,
+    pub status: InstanceStatus,
+    /// The most recent step of the instance.
+    pub current_step: Option<StepSummary>,
+    /// Set while the current step is parked until an event arrives.
+    pub waiting_for_event: Option<WaitingForEvent>,
+    pub tags: Tags,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// What `POST /workflow/{name}/run` returns.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RunInstanceResponse {
+    pub instance_id: WorkflowInstanceId,
+    /// `false` if the instance was still running when the wait timed out. Its status can then be
+    /// polled at `GET /workflow/{name}/{instance_id}`.
+    pub finished: bool,
+    /// The instance when the wait ended. For a completed instance, `current_step` is the step
+    /// that finished it. `None` if the instance hadn't been persisted yet.
+    pub instance: Option<InstanceStatusResponse>,
+    /// For a completed instance, the step that finished it, with the data it ran with. Steps
+    /// return the step to run next rather than a value, so this is the instance's result.
+    pub output: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct StepSummary {
+    pub step_id: StepId,
+    pub step_type: String,
+    pub status: StepStatus,
+    /// Failed attempts so far.
+    pub retry_count: u32,
+    pub max_retries: u32,
+    pub task_queue: TaskQueue,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl<P: Project> From<&StepRecord<P>> for StepSummary {
+    fn from(record: &StepRecord<P>) -> Self {
+        Self {
+            step_id: record.step_id,
+            step_type: record.step.step.step_type().into_owned(),
+            status: record.status,
+            retry_count: record.retry_count,
+            max_retries: record.step.settings.max_retries,
+            task_queue: record.step.settings.task_queue.clone(),
+            created_at: record.created_at,
+            updated_at: record.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct WaitingForEvent {
+    pub step_id: StepId,
+    pub event_type: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct InstanceListResponse {
+    pub instances: Vec<InstanceSummary>,
+    /// Pass as `after` to get the next page. `None` on the last page.
+    pub next: Option<WorkflowInstanceId>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct InstanceSummary {
+    pub instance_id: WorkflowInstanceId,
+    pub business_id: Option<BusinessId>,
+    pub workflow: String,
+    pub status: InstanceStatus,
+    pub current_step_type: Option<String>,
+    pub tags: Tags,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl<P: Project> From<InstanceRecord<P>> for InstanceSummary {
+    fn from(record: InstanceRecord<P>) -> Self {
+        Self {
+            instance_id: record.instance.external_id,
+            business_id: record.instance.business_id,
+            workflow: record.instance.workflow.name().to_string(),
+            status: record.status,
+            current_step_type: record.current_step_type,
+            tags: record.tags,
+            created_at: record.created_at,
+            updated_at: record.updated_at,
+        }
+    }
+}
+
+/// Everything that happened to an instance, step by step.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct InstanceHistoryResponse {
+    pub instance_id: WorkflowInstanceId,
+    pub business_id: Option<BusinessId>,
+    pub workflow: String,
+    pub status: InstanceStatus,
+    /// From the entrypoint onwards, each step followed by the one it returned.
+    pub steps: Vec<StepHistory>,
+    pub tags: Tags,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct StepHistory {
+    pub step_id: StepId,
+    pub previous_step_id: Option<StepId>,
+    pub step_type: String,
+    pub status: StepStatus,
+    /// The step as it was stored.
+    pub step: serde_json::Value,
+    pub attempts: Vec<AttemptHistory>,
+    /// The step this one returned, once it completed.
+    pub output: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AttemptHistory {
+    /// Starts at 1.
+    pub attempt: u32,
+    /// The event the attempt ran with.
+    pub event: Option<serde_json::Value>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+    pub last_heartbeat: Option<HeartbeatDetails>,
+}
+
+impl<P: Project> From<StepAttemptRecord<P>> for AttemptHistory {
+    fn from(record: StepAttemptRecord<P>) -> Self {
+        Self {
+            attempt: record.attempt,
+            event: record
+                .event
+                .and_then(|event| serde_json::to_value(event).ok()),
+            started_at: record.started_at,
+            finished_at: record.finished_at,
+            error: record.error,
+            last_heartbeat: record.last_heartbeat,
+        }
+    }
+}
+
+/// Creates an instance of `T`. If the request has an `Idempotency-Key` header that an identical
+/// request already used within the retention window, the instance that request created is
+/// returned instead, after sending it again if that request may have stopped before sending it.
+pub(crate) async fn create_instance<P, T, D>(
+    dependencies: &Dependencies<P, D>,
+    headers: &HeaderMap,
+    tags: Tags,
+    business_id: Option<BusinessId>,
+) -> Result<WorkflowInstanceId, InstanceError>
+where
+    P: Project,
+    T: Workflow<P>,
+    D: ControlServerDependencyProvider<P>,
+{
+    let workflow = WorkflowName::from(<T as Workflow<P>>::NAME);
+    let idempotency_key = idempotency_key(headers)?;
+    if business_id
+        .as_ref()
+        .is_some_and(|id| id.as_str().is_empty() || id.as_str().len() > MAX_BUSINESS_ID_LEN)
+    {
+        return Err(InstanceError::InvalidBusinessId);
+    }
+    let mut external_id = WorkflowInstanceId::new();
+
+    if let Some(key) = idempotency_key {
+        let record = IdempotencyRecord {
+            instance_id: external_id,
+            request: serde_json::json!({ "tags": tags, "business_id": business_id }),
+            created_at: Utc::now(),
+            sent: false,
+        };
+        let existing = dependencies
+            .idempotency_store
+            .claim(&workflow, key, &record, IDEMPOTENCY_RETENTION)
+            .await
+            .map_err(|_| InstanceError::CouldntAccessPersistence)?;
+        if let Some(existing) = existing {
+            if existing.request != record.request {
+                return Err(InstanceError::IdempotencyKeyConflict);
+            }
+            tracing::debug!("repeated request for instance {}", existing.instance_id);
+            if existing.sent || instance_exists::<P, D>(dependencies, existing.instance_id).await? {
+                return Ok(existing.instance_id);
+            }
+            // the request that claimed the key may have stopped before sending the instance,
+            // send it again under the same id
+            tracing::debug!("instance {} may not have been sent", existing.instance_id);
+            external_id = existing.instance_id;
+        }
+    }
+
+    let instance = WorkflowInstance {
+        workflow: <T as Workflow<P>>::WORKFLOW_STATIC.into(),
+        external_id,
+        tags,
+        business_id,
+        trace_context: current_trace_context(),
+    };
+    // on failure the key stays unsent, so a retry sends the instance again
+    send_instance::<P, T, D>(dependencies, &workflow, instance).await?;
+    if let Some(key) = idempotency_key
+        && let Err(err) = dependencies
+            .idempotency_store
+            .mark_sent(&workflow, key)
+            .await
+    {
+        // a retry with the key will only send the instance again
+        tracing::error!("Failed to mark idempotency key {key:?} as sent: {:?}", err);
+    }
+
+    Ok(external_id)
+}
+
+async fn instance_exists<P, D>(
+    dependencies: &Dependencies<P, D>,
+    instance_id: WorkflowInstanceId,
+) -> Result<bool, InstanceError>
+where
+    P: Project,
+    D: ControlServerDependencyProvider<P>,
+{
+    let instance = dependencies
+        .persistence_manager
+        .get_instance(instance_id)
+        .await
+        .map_err(|_| InstanceError::CouldntAccessPersistence)?;
+    Ok(instance.is_some())
+}
+
+/// Claims the instance's business id, if it has one, and sends the instance.
+async fn send_instance<P, T, D>(
+    dependencies: &Dependencies<P, D>,
+    workflow: &WorkflowName,
+    instance: WorkflowInstance<P>,
+) -> Result<(), InstanceError>
+where
+    P: Project,
+    T: Workflow<P>,
+    D: ControlServerDependencyProvider<P>,
+{
+    let external_id = instance.external_id;
+    let business_id = instance.business_id.clone();
+
+    if let Some(business_id) = &business_id {
+        let current = dependencies
+            .persistence_manager
+            .claim_business_id(
+                workflow,
+                business_id,
+                external_id,
+                <T as Workflow<P>>::ID_REUSE_POLICY,
+            )
+            .await
+            .map_err(|_| InstanceError::CouldntAccessPersistence)?;
+        if let Some(current) = current {
+            tracing::debug!("id {business_id} is still held by instance {current}");
+            return Err(InstanceError::BusinessIdInUse);
+        }
+    }
+
+    tracing::debug!("creating instance...");
+    if dependencies
+        .new_instance_sender
+        .send(instance)
+        .await
+        .is_err()
+    {
+        if let Some(business_id) = &business_id
+            && let Err(err) = dependencies
+                .persistence_manager
+                .release_business_id(workflow, business_id, external_id)
+                .await
+        {
+            tracing::error!("Failed to release id {business_id}: {:?}", err);
+        }
+        return Err(InstanceError::CouldntCreateInstance);
+    }
+
+    Ok(())
+}
+
+fn idempotency_key(headers: &HeaderMap) -> Result<Option<&str>, InstanceError> {
+    let Some(key) = headers.get(IDEMPOTENCY_KEY) else {
+        return Ok(None);
+    };
+    key.to_str()
+        .ok()
+        .filter(|key| !key.is_empty() && key.len() <= MAX_IDEMPOTENCY_KEY_LEN)
+        .map(Some)
+        .ok_or(InstanceError::InvalidIdempotencyKey)
+}
+
+/// Builds the status of an instance of `T`. Instances of other workflows are not found.
+pub(crate) async fn instance_status<P, T, D>(
+    dependencies: &Dependencies<P, D>,
+    instance_id: WorkflowInstanceId,
+) -> Result<InstanceStatusResponse, InstanceError>
+where
+    P: Project,
+    T: Workflow<P>,
+    D: ControlServerDependencyProvider<P>,
+{
+    let persistence_manager = &dependencies.persistence_manager;
+    let instance = persistence_manager
+        .get_instance(instance_id)
+        .await
+        .map_err(|_| InstanceError::CouldntAccessPersistence)?
+        .filter(|instance| instance.instance.workflow.name() == <T as Workflow<P>>::NAME)
+        .ok_or(InstanceError::NotFound)?;
+    let steps = persistence_manager
+        .list_steps(instance_id)
+        .await
+        .map_err(|_| InstanceError::CouldntAccessPersistence)?;
+
+    let waiting_for_event = dependencies
+        .steps_awaiting_event_manager
+        .clone()
+        .get_step(instance_id)
+        .await
+        .map_err(|_| InstanceError::CouldntAccessPersistence)?
+        .map(|step| WaitingForEvent {
+            step_id: step.step_id,
+            event_type: step.step.step.event_type().into_owned(),
+        });
+
+    Ok(InstanceStatusResponse {
+        instance_id,
+        business_id: instance.instance.business_id,
+        workflow: <T as Workflow<P>>::NAME.to_string(),
+        status: instance.status,
+        current_step: steps.last().map(StepSummary::from),
+        waiting_for_event,
+        tags: instance.tags,
+        created_at: instance.created_at,
+        updated_at: instance.updated_at,
+    })
+}
+
+/// The last step of an instance, as returned by the step before it and stored with
+/// [`PersistenceManager::insert_step_output`]. An instance that finished in its entrypoint
+/// returns the entrypoint.
+pub(crate) async fn instance_output<P, D>(
+    dependencies: &Dependencies<P, D>,
+    instance_id: WorkflowInstanceId,
+) -> Result<Option<serde_json::Value>, InstanceError>
+where
+    P: Project,
+    D: ControlServerDependencyProvider<P>,
+{
+    let mut steps = dependencies
+        .persistence_manager
+        .list_steps(instance_id)
+        .await
+        .map_err(|_| InstanceError::CouldntAccessPersistence)?;
+    let Some(last) = steps.pop() else {
+        return Ok(None);
+    };
+    let output = match last.previous_step_id {
+        Some(previous_step_id) => steps
+            .into_iter()
+            .find(|step| step.step_id == previous_step_id)
+            .and_then(|step| step.output),
+        None => Some(last.step.step),
+    };
+    Ok(output.and_then(|output| serde_json::to_value(output).ok()))
+}
+
+/// The current instance of `T` with `business_id`.
+pub(crate) async fn instance_by_business_id<P, T, D>(
+    dependencies: &Dependencies<P, D>,
+    business_id: &BusinessId,
+) -> Result<WorkflowInstanceId, InstanceError>
+where
+    P: Project,
+    T: Workflow<P>,
+    D: ControlServerDependencyProvider<P>,
+{
+    let workflow = WorkflowName::from(<T as Workflow<P>>::NAME);
+    let instance = dependencies
+        .persistence_manager
+        .get_instance_by_business_id(&workflow, business_id)
+        .await
+        .map_err(|_| InstanceError::CouldntAccessPersistence)?
+        .ok_or(InstanceError::NotFound)?;
+    Ok(instance.instance.external_id)
+}
+
+pub(crate) async fn instance_history<P, T, D>(
+    dependencies: &Dependencies<P, D>,
+    instance_id: WorkflowInstanceId,
+) -> Result<InstanceHistoryResponse, InstanceError>
+where
+    P: Project,
+    T: Workflow<P>,
+    D: ControlServerDependencyProvider<P>,
+{
+    let persistence_manager = &dependencies.persistence_manager;
+    let instance = persistence_manager
+        .get_instance(instance_id)
+        .await
+        .map_err(|_| InstanceError::CouldntAccessPersistence)?
+        .filter(|instance| instance.instance.workflow.name() == <T as Workflow<P>>::NAME)
+        .ok_or(InstanceError::NotFound)?;
+    let steps = persistence_manager
+        .list_steps(instance_id)
+        .await
+        .map_err(|_| InstanceError::CouldntAccessPersistence)?;
+    let attempts = persistence_manager
+        .list_step_attempts(instance_id)
+        .await
+        .map_err(|_| InstanceError::CouldntAccessPersistence)?;
+
+    Ok(InstanceHistoryResponse {
+        instance_id,
+        business_id: instance.instance.business_id,
+        workflow: <T as Workflow<P>>::NAME.to_string(),
+        status: instance.status,
+        steps: step_history(steps, attempts),
+        tags: instance.tags,
+        created_at: instance.created_at,
+        updated_at: instance.updated_at,
+    })
+}
+
+/// Orders `steps` by following `previous_step_id` from the entrypoint and attaches their
+/// attempts. Steps that aren't reachable that way are appended in their original order.
+fn step_history<P: Project>(
+    steps: Vec<StepRecord<P>>,
+    attempts: Vec<StepAttemptRecord<P>>,
+) -> Vec<StepHistory> {
+    let mut attempts_by_step: HashMap<StepId, Vec<AttemptHistory>> = HashMap::new();
+    for attempt in attempts {
+        attempts_by_step
+            .entry(attempt.step_id)
+            .or_default()
+            .push(attempt.into());
+    }
+
+    let mut ordered = Vec::with_capacity(steps.len());
+    let mut remaining = steps;
+    let mut previous = None;
+    while let Some(index) = remaining
+        .iter()
+        .position(|step| step.previous_step_id == previous)
+    {
+        let step = remaining.remove(index);
+        previous = Some(step.step_id);
+        ordered.push(step);
+    }
+    ordered.append(&mut remaining);
+
+    ordered
+        .into_iter()
+        .map(|step| StepHistory {
+            step_id: step.step_id,
+            previous_step_id: step.previous_step_id,
+            step_type: step.step.step.step_type().into_owned(),
+            status: step.status,
+            step: serde_json::to_value(&step.step.step).unwrap_or_default(),
+            attempts: attempts_by_step.remove(&step.step_id).unwrap_or_default(),
+            output: step
+                .output
+                .and_then(|output| serde_json::to_value(output).ok()),
+            created_at: step.created_at,
+            updated_at: step.updated_at,
+        })
+        .collect()
+}
+
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    Clone,
+    thiserror::Error,
+    axum_thiserror::ErrorStatus,
+    OperationIo,
+)]
+pub(crate) enum InstanceError {
+    #[error("instance not found")]
+    #[status(StatusCode::NOT_FOUND)]
+    NotFound,
+    #[error("tag filters must be comma separated `key:value` pairs")]
+    #[status(StatusCode::BAD_REQUEST)]
+    InvalidTagFilter,
+    #[error("idempotency keys must be 1 to 255 visible ASCII characters")]
+    #[status(StatusCode::BAD_REQUEST)]
+    InvalidIdempotencyKey,
+    #[error("the idempotency key was already used with a different request")]
+    #[status(StatusCode::UNPROCESSABLE_ENTITY)]
+    IdempotencyKeyConflict,
+    #[error("instance ids must be 1 to 255 bytes long")]
+    #[status(StatusCode::BAD_REQUEST)]
+    InvalidBusinessId,
+    #[error("the instance id is taken by an instance the workflow's reuse policy protects")]
+    #[status(StatusCode::CONFLICT)]
+    BusinessIdInUse,
+    #[error("could not create instance")]
+    #[status(StatusCode::INTERNAL_SERVER_ERROR)]
+    CouldntCreateInstance,
+    #[error("the adapter doesn't support notifications")]
+    #[status(StatusCode::NOT_IMPLEMENTED)]
+    NotificationsUnsupported,
+    #[error("could not access persistence")]
+    #[status(StatusCode::INTERNAL_SERVER_ERROR)]
+    CouldntAccessPersistence,
+}
+
+/// Parses `key:value,key:value`. Values may contain `:`, keys may not.
+pub(crate) fn parse_tag_filter(tags: &str) -> Result<Tags, InstanceError> {
+    tags.split(',')
+        .map(|tag| {
+            tag.split_once(':')
+                .filter(|(key, _)| !key.is_empty())
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .ok_or(InstanceError::InvalidTagFilter)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use surgeflow_types::testing::{TestProject, TestStep};
+
+    use super::*;
+
+    fn step(
+        step_id: StepId,
+        previous_step_id: Option<StepId>,
+        output: Option<TestStep>,
+    ) -> StepRecord<TestProject> {
+        StepRecord {
+            step_id,
+            previous_step_id,
+            step: TestStep(0).raw(),
+            status: if output.is_some() {
+                StepStatus::Completed
+            } else {
+                StepStatus::Running
+            },
+            retry_count: 0,
+            output,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn attempt(step_id: StepId, attempt: u32) -> StepAttemptRecord<TestProject> {
+        StepAttemptRecord {
+            step_id,
+            attempt,
+            event: None,
+            started_at: Utc::now(),
+            finished_at: None,
+            error: None,
+            last_heartbeat: None,
+        }
+    }
+
+    #[test]
+    fn step_history_follows_previous_steps_from_the_entrypoint() {
+        let first = StepId::entrypoint_of(WorkflowInstanceId::new());
+        let second = StepId::successor_of(first);
+        let third = StepId::successor_of(second);
+        let steps = vec![
+            step(third, Some(second), None),
+            step(first, None, Some(TestStep(1))),
+            step(second, Some(first), Some(TestStep(2))),
+        ];
+
+        let history = step_history(steps, Vec::new());
+        let order: Vec<_> = history.iter().map(|step| step.step_id).collect();
+        assert_eq!(order, [first, second, third]);
+        assert_eq!(history[0].output, Some(serde_json::json!(1)));
+        assert_eq!(history[2].output, None);
+    }
+
+    #[test]
+    fn step_history_appends_unreachable_steps() {
+        let first = StepId::entrypoint_of(WorkflowInstanceId::new());
+        let orphan = StepId::new();
+        let steps = vec![
+            step(orphan, Some(StepId::new()), None),
+            step(first, None, None),
+        ];
+
+        let order: Vec<_> = step_history(steps, Vec::new())
+            .iter()
+            .map(|step| step.step_id)
+            .collect();
+        assert_eq!(order, [first, orphan]);
+    }
+
+    #[test]
+    fn step_history_attaches_attempts_to_their_step() {
+        let first = StepId::entrypoint_of(WorkflowInstanceId::new());
+        let second = StepId::successor_of(first);
+        let steps = vec![step(first, None, None), step(second, Some(first), None)];
+        let attempts = vec![attempt(first, 1), attempt(first, 2), attempt(second, 1)];
+
+        let history = step_history(steps, attempts);
+        let attempts = |index: usize| -> Vec<u32> {
+            history[index]
+                .attempts
+                .iter()
+                .map(|attempt| attempt.attempt)
+                .collect()
+        };
+        assert_eq!(attempts(0), [1, 2]);
+        assert_eq!(attempts(1), [1]);
+    }
+
+    #[test]
+    fn tag_filters_are_comma_separated_key_value_pairs() {
+        let tags = parse_tag_filter("env:prod,url:https://example.com").unwrap();
+        assert_eq!(
+            tags,
+            Tags::from([
+                ("env".to_string(), "prod".to_string()),
+                ("url".to_string(), "https://example.com".to_string()),
+            ])
+        );
+        assert_eq!(
+            parse_tag_filter("empty:").unwrap(),
+            Tags::from([("empty".to_string(), String::new())])
+        );
+    }
+
+    #[test]
+    fn tag_filters_need_a_key_and_a_value() {
+        for tags in ["env", ":prod", "env:prod,", ""] {
+            assert!(
+                matches!(parse_tag_filter(tags), Err(InstanceError::InvalidTagFilter)),
+                "{tags:?} was accepted"
+            );
+        }
+    }
+}
@@ -1,70 +1,151 @@
-use std::{marker::PhantomData, sync::Arc};
+use std::{marker::PhantomData, sync::Arc, time::Duration};
 
 use adapter_types::{
-    dependencies::control_server::ControlServerDependencies,
-    senders::{EventSender, NewInstanceSender},
+    dependencies::{ControlServerDependencyProvider, ProvidedControlServerDependencies},
+    health::Health,
+    managers::{InstanceFilter, InstanceOrder, PersistenceManager},
+    notifications::{InstanceNotification, NotificationKind},
+    senders::EventSender,
+    telemetry::current_trace_context,
 };
 use aide::{OperationIo, axum::ApiRouter};
-use axum::{Json, extract::State, http::StatusCode};
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+};
 use axum_extra::routing::TypedPath;
+use chrono::{DateTime, Utc};
+use metrics_exporter_prometheus::PrometheusHandle;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use surgeflow_types::{
-    __Step, __Workflow, InstanceEvent, Project, Workflow, WorkflowInstance, WorkflowInstanceId,
+    __Step, __Workflow, BusinessId, InstanceEvent, InstanceStatus, Project, Tags, Workflow,
+    WorkflowInstanceId,
+};
+use tokio::{
+    sync::broadcast,
+    time::{Instant, sleep_until},
+};
+
+pub mod auth;
+mod config;
+mod dead_letters;
+mod health;
+mod instances;
+mod metrics;
+mod notifications;
+mod probes;
+mod telemetry;
+mod workers;
+
+pub use config::{ControlServerConfig, TlsConfig};
+pub use dead_letters::dead_letter_router;
+pub use health::health_router;
+pub use instances::{
+    AttemptHistory, InstanceHistoryResponse, InstanceListResponse, InstanceStatusResponse,
+    InstanceSummary, RunInstanceResponse, StepHistory, StepSummary, WaitingForEvent,
+};
+pub use metrics::metrics_router;
+pub use probes::probe_router;
+pub use telemetry::trace_request;
+pub use workers::worker_router;
+
+const DEFAULT_LIST_LIMIT: u32 = 50;
+const MAX_LIST_LIMIT: u32 = 500;
+const DEFAULT_RUN_WAIT_SECS: u64 = 30;
+const MAX_RUN_WAIT_SECS: u64 = 300;
+/// How often `/run` polls the instance if the adapter doesn't support notifications.
+const RUN_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How long a repeated `Idempotency-Key` returns the instance it first created.
+const IDEMPOTENCY_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+use auth::{Authorized, ReadScope, SendEventScope, StartScope};
+use instances::{
+    InstanceError, create_instance, instance_by_business_id, instance_history, instance_output,
+    instance_status, parse_tag_filter,
 };
+use notifications::{NotificationStream, notification_stream, wait_until_finished};
+
+/// The control server's dependencies, as provided by `D`.
+pub type Dependencies<P, D> = ProvidedControlServerDependencies<P, D>;
 
-pub struct AppState<P: Project, E: EventSender<P>, I: NewInstanceSender<P>> {
-    pub dependencies: ControlServerDependencies<P, E, I>,
+pub struct AppState<P: Project, D: ControlServerDependencyProvider<P>> {
+    pub dependencies: Dependencies<P, D>,
+    /// `None` if the adapter doesn't support notifications.
+    notifications: Option<broadcast::Sender<InstanceNotification>>,
+    health: Arc<Health>,
+    /// `None` if no metrics recorder could be installed.
+    metrics: Option<PrometheusHandle>,
 
     _marker: PhantomData<P>,
 }
 
-pub struct ArcAppState<P: Project, E: EventSender<P>, I: NewInstanceSender<P>>(
-    pub Arc<AppState<P, E, I>>,
-);
+pub struct ArcAppState<P: Project, D: ControlServerDependencyProvider<P>>(pub Arc<AppState<P, D>>);
 
-impl<P: Project, E: EventSender<P>, I: NewInstanceSender<P>> Clone for ArcAppState<P, E, I> {
+impl<P: Project, D: ControlServerDependencyProvider<P>> Clone for ArcAppState<P, D> {
     fn clone(&self) -> Self {
         Self(self.0.clone())
     }
 }
 
-pub async fn init_app_state<
-    P: Project,
-    EventSenderT: EventSender<P>,
-    NewInstanceSenderT: NewInstanceSender<P>,
->(
-    dependencies: ControlServerDependencies<P, EventSenderT, NewInstanceSenderT>,
-) -> anyhow::Result<ArcAppState<P, EventSenderT, NewInstanceSenderT>> {
+pub async fn init_app_state<P: Project, D: ControlServerDependencyProvider<P>>(
+    mut dependencies: Dependencies<P, D>,
+    health: Arc<Health>,
+    metrics: Option<PrometheusHandle>,
+) -> anyhow::Result<ArcAppState<P, D>> {
+    let notifications = dependencies
+        .notification_receiver
+        .take()
+        .map(notifications::fan_out);
     Ok(ArcAppState(Arc::new(AppState {
         dependencies,
+        notifications,
+        health,
+        metrics,
 
         _marker: PhantomData,
     })))
 }
 pub trait WorkflowControl<P: Project>: Workflow<P> {
-    fn control_router<
-        E: EventSender<P> + Send + Sync + 'static,
-        N: NewInstanceSender<P> + Send + Sync + 'static,
-    >() -> impl Future<Output = anyhow::Result<ApiRouter<ArcAppState<P, E, N>>>> + Send {
+    fn control_router<D: ControlServerDependencyProvider<P> + 'static>()
+    -> impl Future<Output = anyhow::Result<ApiRouter<ArcAppState<P, D>>>> + Send {
         async {
-            let post_workflow_event_api_route = Self::post_workflow_event_api_route::<E, N>();
-            let post_workflow_instance_api_route = Self::post_workflow_instance_api_route::<E, N>();
+            let post_workflow_event_api_route = Self::post_workflow_event_api_route::<D>();
+            let post_workflow_instance_api_route = Self::post_workflow_instance_api_route::<D>();
+            let post_workflow_run_api_route = Self::post_workflow_run_api_route::<D>();
+            let get_workflow_instance_api_route = Self::get_workflow_instance_api_route::<D>();
+            let list_workflow_instances_api_route = Self::list_workflow_instances_api_route::<D>();
+            let get_workflow_instance_history_api_route =
+                Self::get_workflow_instance_history_api_route::<D>();
+            let get_workflow_instance_events_stream_api_route =
+                Self::get_workflow_instance_events_stream_api_route::<D>();
+            let get_workflow_instance_by_business_id_api_route =
+                Self::get_workflow_instance_by_business_id_api_route::<D>();
+            let get_workflow_instance_history_by_business_id_api_route =
+                Self::get_workflow_instance_history_by_business_id_api_route::<D>();
+            let get_workflow_instance_events_stream_by_business_id_api_route =
+                Self::get_workflow_instance_events_stream_by_business_id_api_route::<D>();
 
             let router = ApiRouter::new().nest(
                 &format!("/workflow/{}", <Self as Workflow<P>>::NAME),
                 ApiRouter::new()
                     .merge(post_workflow_instance_api_route)
-                    .merge(post_workflow_event_api_route),
+                    .merge(post_workflow_run_api_route)
+                    .merge(post_workflow_event_api_route)
+                    .merge(list_workflow_instances_api_route)
+                    .merge(get_workflow_instance_api_route)
+                    .merge(get_workflow_instance_history_api_route)
+                    .merge(get_workflow_instance_events_stream_api_route)
+                    .merge(get_workflow_instance_by_business_id_api_route)
+                    .merge(get_workflow_instance_history_by_business_id_api_route)
+                    .merge(get_workflow_instance_events_stream_by_business_id_api_route),
             );
             Ok(router)
         }
     }
 
-    fn post_workflow_event_api_route<
-        E: EventSender<P> + Send + Sync + 'static,
-        N: NewInstanceSender<P> + Send + Sync + 'static,
-    >() -> ApiRouter<ArcAppState<P, E, N>> {
+    fn post_workflow_event_api_route<D: ControlServerDependencyProvider<P> + 'static>()
+    -> ApiRouter<ArcAppState<P, D>> {
         #[derive(TypedPath, Deserialize, JsonSchema, OperationIo)]
         #[typed_path("/{instance_id}/event")]
         pub struct PostWorkflowEvent {
@@ -88,9 +169,10 @@ pub trait WorkflowControl<P: Project>: Workflow<P> {
         }
 
         // more readable than a closure
-        async fn handler<P: Project, T: Workflow<P>, E: EventSender<P>, N: NewInstanceSender<P>>(
+        async fn handler<P: Project, T: Workflow<P>, D: ControlServerDependencyProvider<P>>(
             PostWorkflowEvent { instance_id }: PostWorkflowEvent,
-            State(ArcAppState(state)): State<ArcAppState<P, E, N>>,
+            _: Authorized<P, SendEventScope<T>>,
+            State(ArcAppState(state)): State<ArcAppState<P, D>>,
             Json(event): Json<<<T as Workflow<P>>::Step as __Step<P, T>>::Event>,
         ) -> Result<(), PostWorkflowEventError> {
             state
@@ -99,13 +181,14 @@ pub trait WorkflowControl<P: Project>: Workflow<P> {
                 .send(InstanceEvent {
                     event: event.into(),
                     instance_id,
+                    trace_context: current_trace_context(),
                 })
                 .await
                 .map_err(|_| PostWorkflowEventError::CouldntQueueEventMessage)?;
             Ok(())
         }
 
-        ApiRouter::new().typed_post_with(handler::<P, Self, _, _>, |op| {
+        ApiRouter::new().typed_post_with(handler::<P, Self, D>, |op| {
             op.description("Send event")
                 .summary("Send event")
                 .id("post-event")
@@ -114,65 +197,460 @@ pub trait WorkflowControl<P: Project>: Workflow<P> {
         })
     }
 
-    fn post_workflow_instance_api_route<
-        E: EventSender<P> + Send + Sync + 'static,
-        N: NewInstanceSender<P> + Send + Sync + 'static,
-    >() -> ApiRouter<ArcAppState<P, E, N>> {
+    fn post_workflow_instance_api_route<D: ControlServerDependencyProvider<P> + 'static>()
+    -> ApiRouter<ArcAppState<P, D>> {
         #[derive(TypedPath, Deserialize, JsonSchema, OperationIo)]
         #[typed_path("/")]
         pub struct PostWorkflowInstance;
 
-        #[derive(
-            Debug,
-            Serialize,
-            Deserialize,
-            JsonSchema,
-            Clone,
-            thiserror::Error,
-            axum_thiserror::ErrorStatus,
-            OperationIo,
-        )]
-        enum PostWorkflowInstanceError {
-            #[error("could not create instance")]
-            #[status(StatusCode::INTERNAL_SERVER_ERROR)]
-            CouldntCreateInstance,
+        #[derive(Deserialize, JsonSchema)]
+        pub struct PostWorkflowInstanceBody {
+            #[serde(default)]
+            tags: Tags,
+            /// An id of the client's choosing, such as an order number. Whether another instance
+            /// can take it depends on the workflow's reuse policy.
+            #[serde(default)]
+            business_id: Option<BusinessId>,
         }
 
         // more readable than a closure
-        async fn handler<P: Project, T: Workflow<P>, E: EventSender<P>, N: NewInstanceSender<P>>(
+        async fn handler<P: Project, T: Workflow<P>, D: ControlServerDependencyProvider<P>>(
             _: PostWorkflowInstance,
-            State(ArcAppState(state)): State<ArcAppState<P, E, N>>,
-        ) -> Result<Json<WorkflowInstanceId>, PostWorkflowInstanceError> {
-            tracing::debug!("creating instance...");
-            let external_id = WorkflowInstanceId::new();
-            state
+            _: Authorized<P, StartScope<T>>,
+            State(ArcAppState(state)): State<ArcAppState<P, D>>,
+            headers: HeaderMap,
+            body: Option<Json<PostWorkflowInstanceBody>>,
+        ) -> Result<Json<WorkflowInstanceId>, InstanceError> {
+            let (tags, business_id) = body
+                .map(|Json(body)| (body.tags, body.business_id))
+                .unwrap_or_default();
+            let external_id =
+                create_instance::<P, T, D>(&state.dependencies, &headers, tags, business_id)
+                    .await?;
+
+            Ok(Json(external_id))
+        }
+        ApiRouter::new().typed_post_with(handler::<P, Self, D>, |op| {
+            op.description(
+                "Create instance. Requests with an `Idempotency-Key` header that repeat an \
+                 earlier request return the instance it created. Responds with 409 if the \
+                 `business_id` is taken by an instance the workflow's reuse policy protects.",
+            )
+            .summary("Create instance")
+            .id("post-workflow-instance")
+            .tag(<Self as Workflow<P>>::NAME)
+            .hidden(false)
+        })
+    }
+
+    fn post_workflow_run_api_route<D: ControlServerDependencyProvider<P> + 'static>()
+    -> ApiRouter<ArcAppState<P, D>> {
+        #[derive(TypedPath, Deserialize, JsonSchema, OperationIo)]
+        #[typed_path("/run")]
+        pub struct PostWorkflowRun;
+
+        #[derive(Deserialize, JsonSchema)]
+        pub struct PostWorkflowRunBody {
+            #[serde(default)]
+            tags: Tags,
+            /// An id of the client's choosing, such as an order number. Whether another instance
+            /// can take it depends on the workflow's reuse policy.
+            #[serde(default)]
+            business_id: Option<BusinessId>,
+        }
+
+        #[derive(Deserialize, JsonSchema)]
+        pub struct PostWorkflowRunQuery {
+            /// Seconds to wait for the instance to finish. Defaults to 30, at most 300.
+            wait: Option<u64>,
+        }
+
+        // more readable than a closure
+        async fn handler<P: Project, T: Workflow<P>, D: ControlServerDependencyProvider<P>>(
+            _: PostWorkflowRun,
+            _: Authorized<P, StartScope<T>>,
+            State(ArcAppState(state)): State<ArcAppState<P, D>>,
+            Query(query): Query<PostWorkflowRunQuery>,
+            headers: HeaderMap,
+            body: Option<Json<PostWorkflowRunBody>>,
+        ) -> Result<(StatusCode, Json<RunInstanceResponse>), InstanceError> {
+            let (tags, business_id) = body
+                .map(|Json(body)| (body.tags, body.business_id))
+                .unwrap_or_default();
+            let wait = query
+                .wait
+                .unwrap_or(DEFAULT_RUN_WAIT_SECS)
+                .min(MAX_RUN_WAIT_SECS);
+            let deadline = Instant::now() + Duration::from_secs(wait);
+            // subscribed before the instance exists, so its terminal notification can't be missed
+            let mut notifications = state
+                .notifications
+                .as_ref()
+                .map(broadcast::Sender::subscribe);
+            let external_id =
+                create_instance::<P, T, D>(&state.dependencies, &headers, tags, business_id)
+                    .await?;
+
+            // the completed and failed instance workers record the terminal status before
+            // notifying, so persistence has it whichever process they run in
+            loop {
+                let instance =
+                    match instance_status::<P, T, D>(&state.dependencies, external_id).await {
+                        Ok(instance) => Some(instance),
+                        // the new instance worker hasn't persisted it yet
+                        Err(InstanceError::NotFound) => None,
+                        Err(err) => return Err(err),
+                    };
+                let finished = instance
+                    .as_ref()
+                    .is_some_and(|instance| instance.status != InstanceStatus::Running);
+                if finished || Instant::now() >= deadline {
+                    let (status, output) = if finished {
+                        let completed = instance
+                            .as_ref()
+                            .is_some_and(|instance| instance.status == InstanceStatus::Completed);
+                        let output = if completed {
+                            instance_output::<P, D>(&state.dependencies, external_id).await?
+                        } else {
+                            None
+                        };
+                        (StatusCode::OK, output)
+                    } else {
+                        (StatusCode::ACCEPTED, None)
+                    };
+                    return Ok((
+                        status,
+                        Json(RunInstanceResponse {
+                            instance_id: external_id,
+                            finished,
+                            instance,
+                            output,
+                        }),
+                    ));
+                }
+                match &mut notifications {
+                    Some(receiver) => {
+                        if !wait_until_finished(receiver, external_id, deadline).await {
+                            notifications = None;
+                        }
+                    }
+                    // adapters without notifications are polled
+                    None => sleep_until(deadline.min(Instant::now() + RUN_POLL_INTERVAL)).await,
+                }
+            }
+        }
+        ApiRouter::new().typed_post_with(handler::<P, Self, D>, |op| {
+            op.description(
+                "Create an instance and wait for it to complete or fail. Responds with 202 if it \
+                 is still running when the wait times out. Requests with an `Idempotency-Key` \
+                 header that repeat an earlier request wait for the instance it created. A \
+                 completed instance's `output` is the step that finished it.",
+            )
+            .summary("Run instance")
+            .id("post-workflow-run")
+            .tag(<Self as Workflow<P>>::NAME)
+            .hidden(false)
+        })
+    }
+
+    fn list_workflow_instances_api_route<D: ControlServerDependencyProvider<P> + 'static>()
+    -> ApiRouter<ArcAppState<P, D>> {
+        #[derive(TypedPath, Deserialize, JsonSchema, OperationIo)]
+        #[typed_path("/instances")]
+        pub struct ListWorkflowInstances;
+
+        #[derive(Deserialize, JsonSchema)]
+        pub struct ListWorkflowInstancesQuery {
+            status: Option<InstanceStatus>,
+            /// Only return instances created at or after this time.
+            created_after: Option<DateTime<Utc>>,
+            /// Only return instances created before this time.
+            created_before: Option<DateTime<Utc>>,
+            current_step_type: Option<String>,
+            /// Only return instances with all of these tags, as comma separated `key:value`
+            /// pairs.
+            tags: Option<String>,
+            /// Only return instances created with this business id.
+            business_id: Option<BusinessId>,
+            #[serde(default)]
+            order: InstanceOrder,
+            /// Only return instances after this one, in the requested order.
+            after: Option<WorkflowInstanceId>,
+            limit: Option<u32>,
+        }
+
+        // more readable than a closure
+        async fn handler<P: Project, T: Workflow<P>, D: ControlServerDependencyProvider<P>>(
+            _: ListWorkflowInstances,
+            _: Authorized<P, ReadScope<T>>,
+            State(ArcAppState(state)): State<ArcAppState<P, D>>,
+            Query(query): Query<ListWorkflowInstancesQuery>,
+        ) -> Result<Json<InstanceListResponse>, InstanceError> {
+            let limit = query
+                .limit
+                .unwrap_or(DEFAULT_LIST_LIMIT)
+                .min(MAX_LIST_LIMIT);
+            let tags = query
+                .tags
+                .as_deref()
+                .map(parse_tag_filter)
+                .transpose()?
+                .unwrap_or_default();
+            let filter = InstanceFilter {
+                workflow: Some(<T as Workflow<P>>::NAME.into()),
+                status: query.status,
+                created_after: query.created_after,
+                created_before: query.created_before,
+                current_step_type: query.current_step_type,
+                tags,
+                business_id: query.business_id,
+            };
+            let instances = state
                 .dependencies
-                .new_instance_sender
-                .send(WorkflowInstance {
-                    workflow: <T as Workflow<P>>::WORKFLOW_STATIC.into(),
-                    external_id,
-                    // workflow_name: T::NAME.into(),
-                })
+                .persistence_manager
+                .list_instances(&filter, query.order, query.after, limit)
                 .await
-                .map_err(|_| PostWorkflowInstanceError::CouldntCreateInstance)?;
+                .map_err(|_| InstanceError::CouldntAccessPersistence)?;
 
-            Ok(Json(external_id))
+            let next = if instances.len() as u32 == limit {
+                instances
+                    .last()
+                    .map(|instance| instance.instance.external_id)
+            } else {
+                None
+            };
+            Ok(Json(InstanceListResponse {
+                instances: instances.into_iter().map(InstanceSummary::from).collect(),
+                next,
+            }))
+        }
+
+        ApiRouter::new().typed_get_with(handler::<P, Self, D>, |op| {
+            op.description("List and search the instances of the workflow")
+                .summary("List instances")
+                .id("list-workflow-instances")
+                .tag(<Self as Workflow<P>>::NAME)
+                .hidden(false)
+        })
+    }
+
+    fn get_workflow_instance_api_route<D: ControlServerDependencyProvider<P> + 'static>()
+    -> ApiRouter<ArcAppState<P, D>> {
+        #[derive(TypedPath, Deserialize, JsonSchema, OperationIo)]
+        #[typed_path("/{instance_id}")]
+        pub struct GetWorkflowInstance {
+            instance_id: WorkflowInstanceId,
+        }
+
+        // more readable than a closure
+        async fn handler<P: Project, T: Workflow<P>, D: ControlServerDependencyProvider<P>>(
+            GetWorkflowInstance { instance_id }: GetWorkflowInstance,
+            _: Authorized<P, ReadScope<T>>,
+            State(ArcAppState(state)): State<ArcAppState<P, D>>,
+        ) -> Result<Json<InstanceStatusResponse>, InstanceError> {
+            instance_status::<P, T, D>(&state.dependencies, instance_id)
+                .await
+                .map(Json)
+        }
+
+        ApiRouter::new().typed_get_with(handler::<P, Self, D>, |op| {
+            op.description("Get the status of an instance")
+                .summary("Get instance")
+                .id("get-workflow-instance")
+                .tag(<Self as Workflow<P>>::NAME)
+                .hidden(false)
+        })
+    }
+
+    fn get_workflow_instance_history_api_route<D: ControlServerDependencyProvider<P> + 'static>()
+    -> ApiRouter<ArcAppState<P, D>> {
+        #[derive(TypedPath, Deserialize, JsonSchema, OperationIo)]
+        #[typed_path("/{instance_id}/history")]
+        pub struct GetWorkflowInstanceHistory {
+            instance_id: WorkflowInstanceId,
+        }
+
+        // more readable than a closure
+        async fn handler<P: Project, T: Workflow<P>, D: ControlServerDependencyProvider<P>>(
+            GetWorkflowInstanceHistory { instance_id }: GetWorkflowInstanceHistory,
+            _: Authorized<P, ReadScope<T>>,
+            State(ArcAppState(state)): State<ArcAppState<P, D>>,
+        ) -> Result<Json<InstanceHistoryResponse>, InstanceError> {
+            instance_history::<P, T, D>(&state.dependencies, instance_id)
+                .await
+                .map(Json)
+        }
+
+        ApiRouter::new().typed_get_with(handler::<P, Self, D>, |op| {
+            op.description("Get everything that happened to an instance, step by step")
+                .summary("Get instance history")
+                .id("get-workflow-instance-history")
+                .tag(<Self as Workflow<P>>::NAME)
+                .hidden(false)
+        })
+    }
+
+    fn get_workflow_instance_events_stream_api_route<
+        D: ControlServerDependencyProvider<P> + 'static,
+    >() -> ApiRouter<ArcAppState<P, D>> {
+        #[derive(TypedPath, Deserialize, JsonSchema, OperationIo)]
+        #[typed_path("/{instance_id}/events/stream")]
+        pub struct GetWorkflowInstanceEventsStream {
+            instance_id: WorkflowInstanceId,
+        }
+
+        // more readable than a closure
+        async fn handler<P: Project, T: Workflow<P>, D: ControlServerDependencyProvider<P>>(
+            GetWorkflowInstanceEventsStream { instance_id }: GetWorkflowInstanceEventsStream,
+            _: Authorized<P, ReadScope<T>>,
+            State(ArcAppState(state)): State<ArcAppState<P, D>>,
+        ) -> Result<NotificationStream, InstanceError> {
+            instance_events_stream::<P, T, D>(&state, instance_id).await
+        }
+
+        ApiRouter::new().typed_get_with(handler::<P, Self, D>, |op| {
+            op.description(
+                "Stream the step and instance transitions of an instance as server-sent events, \
+                 until it completes or fails. A `lagged` event means transitions were dropped \
+                 and the status should be fetched again.",
+            )
+            .summary("Stream instance events")
+            .id("get-workflow-instance-events-stream")
+            .tag(<Self as Workflow<P>>::NAME)
+            .hidden(false)
+        })
+    }
+
+    fn get_workflow_instance_by_business_id_api_route<
+        D: ControlServerDependencyProvider<P> + 'static,
+    >() -> ApiRouter<ArcAppState<P, D>> {
+        #[derive(TypedPath, Deserialize, JsonSchema, OperationIo)]
+        #[typed_path("/by-business-id/{business_id}")]
+        pub struct GetWorkflowInstanceByBusinessId {
+            business_id: BusinessId,
+        }
+
+        // more readable than a closure
+        async fn handler<P: Project, T: Workflow<P>, D: ControlServerDependencyProvider<P>>(
+            GetWorkflowInstanceByBusinessId { business_id }: GetWorkflowInstanceByBusinessId,
+            _: Authorized<P, ReadScope<T>>,
+            State(ArcAppState(state)): State<ArcAppState<P, D>>,
+        ) -> Result<Json<InstanceStatusResponse>, InstanceError> {
+            let instance_id =
+                instance_by_business_id::<P, T, D>(&state.dependencies, &business_id).await?;
+            instance_status::<P, T, D>(&state.dependencies, instance_id)
+                .await
+                .map(Json)
+        }
+
+        ApiRouter::new().typed_get_with(handler::<P, Self, D>, |op| {
+            op.description("Get the status of the current instance with a business id")
+                .summary("Get instance by business id")
+                .id("get-workflow-instance-by-business-id")
+                .tag(<Self as Workflow<P>>::NAME)
+                .hidden(false)
+        })
+    }
+
+    fn get_workflow_instance_history_by_business_id_api_route<
+        D: ControlServerDependencyProvider<P> + 'static,
+    >() -> ApiRouter<ArcAppState<P, D>> {
+        #[derive(TypedPath, Deserialize, JsonSchema, OperationIo)]
+        #[typed_path("/by-business-id/{business_id}/history")]
+        pub struct GetWorkflowInstanceHistoryByBusinessId {
+            business_id: BusinessId,
+        }
+
+        // more readable than a closure
+        async fn handler<P: Project, T: Workflow<P>, D: ControlServerDependencyProvider<P>>(
+            GetWorkflowInstanceHistoryByBusinessId { business_id }: GetWorkflowInstanceHistoryByBusinessId,
+            _: Authorized<P, ReadScope<T>>,
+            State(ArcAppState(state)): State<ArcAppState<P, D>>,
+        ) -> Result<Json<InstanceHistoryResponse>, InstanceError> {
+            let instance_id =
+                instance_by_business_id::<P, T, D>(&state.dependencies, &business_id).await?;
+            instance_history::<P, T, D>(&state.dependencies, instance_id)
+                .await
+                .map(Json)
         }
-        ApiRouter::new().typed_post_with(handler::<P, Self, _, _>, |op| {
-            op.description("Create instance")
-                .summary("Create instance")
-                .id("post-workflow-instance")
+
+        ApiRouter::new().typed_get_with(handler::<P, Self, D>, |op| {
+            op.description("Get the history of the current instance with a business id")
+                .summary("Get instance history by business id")
+                .id("get-workflow-instance-history-by-business-id")
                 .tag(<Self as Workflow<P>>::NAME)
                 .hidden(false)
         })
     }
+
+    fn get_workflow_instance_events_stream_by_business_id_api_route<
+        D: ControlServerDependencyProvider<P> + 'static,
+    >() -> ApiRouter<ArcAppState<P, D>> {
+        #[derive(TypedPath, Deserialize, JsonSchema, OperationIo)]
+        #[typed_path("/by-business-id/{business_id}/events/stream")]
+        pub struct GetWorkflowInstanceEventsStreamByBusinessId {
+            business_id: BusinessId,
+        }
+
+        // more readable than a closure
+        async fn handler<P: Project, T: Workflow<P>, D: ControlServerDependencyProvider<P>>(
+            GetWorkflowInstanceEventsStreamByBusinessId { business_id }: GetWorkflowInstanceEventsStreamByBusinessId,
+            _: Authorized<P, ReadScope<T>>,
+            State(ArcAppState(state)): State<ArcAppState<P, D>>,
+        ) -> Result<NotificationStream, InstanceError> {
+            let instance_id =
+                instance_by_business_id::<P, T, D>(&state.dependencies, &business_id).await?;
+            instance_events_stream::<P, T, D>(&state, instance_id).await
+        }
+
+        ApiRouter::new().typed_get_with(handler::<P, Self, D>, |op| {
+            op.description(
+                "Stream the transitions of the current instance with a business id, like \
+                 `/{instance_id}/events/stream`",
+            )
+            .summary("Stream instance events by business id")
+            .id("get-workflow-instance-events-stream-by-business-id")
+            .tag(<Self as Workflow<P>>::NAME)
+            .hidden(false)
+        })
+    }
 }
 
 impl<P: Project, T: Workflow<P>> WorkflowControl<P> for T {}
 
+/// The transitions of an instance of `T` as server-sent events, starting with its terminal one if
+/// it already finished.
+async fn instance_events_stream<P, T, D>(
+    state: &AppState<P, D>,
+    instance_id: WorkflowInstanceId,
+) -> Result<NotificationStream, InstanceError>
+where
+    P: Project,
+    T: Workflow<P>,
+    D: ControlServerDependencyProvider<P>,
+{
+    // subscribe before reading the status, so that nothing happening in between is missed
+    let notifications = state
+        .notifications
+        .as_ref()
+        .ok_or(InstanceError::NotificationsUnsupported)?
+        .subscribe();
+    let instance = instance_status::<P, T, D>(&state.dependencies, instance_id).await?;
+    let finished = match instance.status {
+        InstanceStatus::Running => None,
+        InstanceStatus::Completed => Some(NotificationKind::InstanceCompleted),
+        InstanceStatus::Failed => Some(NotificationKind::InstanceFailed),
+    }
+    .map(|kind| InstanceNotification {
+        instance_id,
+        at: instance.updated_at,
+        kind,
+    });
+
+    Ok(notification_stream(notifications, instance_id, finished))
+}
+
 pub trait ProjectWorkflowControl<P: Project>: __Workflow<P> {
-    fn control_router<NewEventSenderT: EventSender<P>, NewInstanceSenderT: NewInstanceSender<P>>()
-    -> impl Future<
-        Output = anyhow::Result<ApiRouter<ArcAppState<P, NewEventSenderT, NewInstanceSenderT>>>,
-    > + Send;
+    fn control_router<D: ControlServerDependencyProvider<P> + 'static>()
+    -> impl Future<Output = anyhow::Result<ApiRouter<ArcAppState<P, D>>>> + Send;
 }
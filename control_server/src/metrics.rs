@@ -0,0 +1,57 @@
+//! The Prometheus scrape endpoint. The workers record their metrics through the `metrics`
+//! facade; this renders whatever the installed recorder collected.
+
+use adapter_types::dependencies::ControlServerDependencyProvider;
+use aide::{OperationIo, axum::ApiRouter};
+use axum::{
+    extract::State,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use axum_extra::routing::TypedPath;
+use metrics_exporter_prometheus::PrometheusHandle;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use surgeflow_types::Project;
+
+use crate::ArcAppState;
+
+const TAG: &str = "metrics";
+/// The content type of the Prometheus text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+pub fn metrics_router<P: Project, D: ControlServerDependencyProvider<P> + 'static>()
+-> ApiRouter<ArcAppState<P, D>> {
+    #[derive(TypedPath, Deserialize, JsonSchema, OperationIo)]
+    #[typed_path("/metrics")]
+    pub struct Metrics;
+
+    // more readable than a closure
+    async fn handler<P: Project, D: ControlServerDependencyProvider<P>>(
+        _: Metrics,
+        State(ArcAppState(state)): State<ArcAppState<P, D>>,
+    ) -> Response {
+        render(state.metrics.as_ref())
+    }
+    ApiRouter::new().typed_get_with(handler::<P, D>, |op| {
+        op.description(
+            "Metrics of the workers in this process, in the Prometheus text format. Responds \
+             with 501 if no recorder could be installed.",
+        )
+        .summary("Metrics")
+        .id("metrics")
+        .tag(TAG)
+        .hidden(false)
+    })
+}
+
+pub(crate) fn render(metrics: Option<&PrometheusHandle>) -> Response {
+    match metrics {
+        Some(metrics) => ([(header::CONTENT_TYPE, CONTENT_TYPE)], metrics.render()).into_response(),
+        None => (
+            StatusCode::NOT_IMPLEMENTED,
+            "no metrics recorder is installed in this process",
+        )
+            .into_response(),
+    }
+}
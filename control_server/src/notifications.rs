@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+use adapter_types::notifications::{InstanceNotification, NotificationReceiver};
+use aide::OperationIo;
+use axum::response::{
+    IntoResponse, Response,
+    sse::{Event, KeepAlive, KeepAliveStream, Sse},
+};
+use futures::{
+    StreamExt,
+    stream::{self, BoxStream},
+};
+use surgeflow_types::{Project, WorkflowInstanceId};
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    time::{Instant, sleep, timeout_at},
+};
+
+/// Notifications buffered per subscriber. A subscriber that falls further behind is sent a
+/// `lagged` event with the number of notifications it missed.
+const CAPACITY: usize = 1024;
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Receives the notifications of every instance once, and hands them to every subscriber of the
+/// returned sender.
+pub(crate) fn fan_out<P, NotificationReceiverT>(
+    mut notification_receiver: NotificationReceiverT,
+) -> broadcast::Sender<InstanceNotification>
+where
+    P: Project,
+    NotificationReceiverT: NotificationReceiver<P>,
+{
+    let (sender, _) = broadcast::channel(CAPACITY);
+    let notifications = sender.clone();
+    tokio::spawn(async move {
+        loop {
+            match notification_receiver.receive().await {
+                // no subscribers is not an error, the notification just isn't wanted
+                Ok(notification) => _ = notifications.send(notification),
+                Err(err) => {
+                    tracing::error!("Error receiving notification: {:?}", err);
+                    sleep(RETRY_DELAY).await;
+                }
+            }
+        }
+    });
+    sender
+}
+
+type EventStream = BoxStream<'static, Result<Event, axum::Error>>;
+
+/// The notifications of one instance as server-sent events, ending after the instance completes
+/// or fails.
+#[derive(OperationIo)]
+pub(crate) struct NotificationStream(Sse<KeepAliveStream<EventStream>>);
+
+impl IntoResponse for NotificationStream {
+    fn into_response(self) -> Response {
+        self.0.into_response()
+    }
+}
+
+/// Streams the notifications of `instance_id`, or only `finished` if the instance already
+/// completed or failed.
+pub(crate) fn notification_stream(
+    notifications: broadcast::Receiver<InstanceNotification>,
+    instance_id: WorkflowInstanceId,
+    finished: Option<InstanceNotification>,
+) -> NotificationStream {
+    let events: EventStream = match finished {
+        Some(notification) => {
+            stream::once(async move { Event::default().json_data(notification) }).boxed()
+        }
+        None => stream::unfold(Some(notifications), move |notifications| async move {
+            let mut notifications = notifications?;
+            loop {
+                match notifications.recv().await {
+                    Ok(notification) if notification.instance_id == instance_id => {
+                        let next = (!notification.kind.is_terminal()).then_some(notifications);
+                        return Some((Event::default().json_data(notification), next));
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        let lagged = Event::default().event("lagged").data(skipped.to_string());
+                        return Some((Ok(lagged), Some(notifications)));
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+        .boxed(),
+    };
+    NotificationStream(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Waits until `instance_id` completes or fails, or `deadline` passes. Returns early after
+/// missing notifications, as they may have included the instance's, and `false` if no more
+/// notifications can arrive.
+pub(crate) async fn wait_until_finished(
+    notifications: &mut broadcast::Receiver<InstanceNotification>,
+    instance_id: WorkflowInstanceId,
+    deadline: Instant,
+) -> bool {
+    loop {
+        match timeout_at(deadline, notifications.recv()).await {
+            Ok(Ok(notification))
+                if notification.instance_id == instance_id && notification.kind.is_terminal() =>
+            {
+                return true;
+            }
+            Ok(Ok(_)) => {}
+            Ok(Err(RecvError::Lagged(_))) | Err(_) => return true,
+            Ok(Err(RecvError::Closed)) => return false,
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! `/healthz`, `/readyz` and `/metrics` on their own, for processes that run workers but not the
+//! control server. They answer like the control server's, except that `/readyz` only covers the
+//! workers' dependencies.
+
+use std::sync::Arc;
+
+use axum::{Json, Router, extract::State, http::StatusCode, response::Response, routing::get};
+use metrics_exporter_prometheus::PrometheusHandle;
+
+use adapter_types::health::{Health, LivenessResponse, ReadinessResponse};
+
+use crate::{
+    health::{liveness_response, readiness_response},
+    metrics::render,
+};
+
+#[derive(Clone)]
+struct ProbeState {
+    health: Arc<Health>,
+    /// `None` if no metrics recorder could be installed.
+    metrics: Option<PrometheusHandle>,
+}
+
+pub fn probe_router(health: Arc<Health>, metrics: Option<PrometheusHandle>) -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics_handler))
+        .with_state(ProbeState { health, metrics })
+}
+
+async fn healthz(State(state): State<ProbeState>) -> (StatusCode, Json<LivenessResponse>) {
+    liveness_response(&state.health)
+}
+
+async fn readyz(State(state): State<ProbeState>) -> (StatusCode, Json<ReadinessResponse>) {
+    readiness_response(state.health.dependency_checks().await)
+}
+
+async fn metrics_handler(State(state): State<ProbeState>) -> Response {
+    render(state.metrics.as_ref())
+}
@@ -0,0 +1,79 @@
+use adapter_types::{
+    dependencies::ControlServerDependencyProvider,
+    registry::{LIVENESS_WINDOW, WorkerProcess, WorkerRegistry},
+};
+use aide::{OperationIo, axum::ApiRouter};
+use axum::{Json, extract::State, http::StatusCode};
+use axum_extra::routing::TypedPath;
+use chrono::Utc;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use surgeflow_types::Project;
+
+use crate::{
+    ArcAppState,
+    auth::{Authorized, ReadWorkersScope},
+};
+
+const TAG: &str = "workers";
+
+pub fn worker_router<P: Project, D: ControlServerDependencyProvider<P> + 'static>()
+-> ApiRouter<ArcAppState<P, D>> {
+    ApiRouter::new().merge(list_workers_api_route::<P, D>())
+}
+
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    Clone,
+    thiserror::Error,
+    axum_thiserror::ErrorStatus,
+    OperationIo,
+)]
+enum WorkerError {
+    #[error("the adapter doesn't have a worker registry")]
+    #[status(StatusCode::NOT_IMPLEMENTED)]
+    NotSupported,
+    #[error("could not access worker registry")]
+    #[status(StatusCode::INTERNAL_SERVER_ERROR)]
+    CouldntAccessWorkerRegistry,
+}
+
+fn list_workers_api_route<P: Project, D: ControlServerDependencyProvider<P> + 'static>()
+-> ApiRouter<ArcAppState<P, D>> {
+    #[derive(TypedPath, Deserialize, JsonSchema, OperationIo)]
+    #[typed_path("/workers")]
+    pub struct ListWorkers;
+
+    // more readable than a closure
+    async fn handler<P: Project, D: ControlServerDependencyProvider<P>>(
+        _: ListWorkers,
+        _: Authorized<P, ReadWorkersScope>,
+        State(ArcAppState(state)): State<ArcAppState<P, D>>,
+    ) -> Result<Json<Vec<WorkerProcess>>, WorkerError> {
+        let worker_registry = state
+            .dependencies
+            .worker_registry
+            .as_ref()
+            .ok_or(WorkerError::NotSupported)?;
+        let since = Utc::now() - LIVENESS_WINDOW;
+        let processes = worker_registry
+            .live(since)
+            .await
+            .map_err(|_| WorkerError::CouldntAccessWorkerRegistry)?;
+        Ok(Json(processes))
+    }
+
+    ApiRouter::new().typed_get_with(handler::<P, D>, |op| {
+        op.description(
+            "List the worker processes with a recent heartbeat, with the workers they run and \
+             the messages each is processing",
+        )
+        .summary("List live workers")
+        .id("list-workers")
+        .tag(TAG)
+        .hidden(false)
+    })
+}
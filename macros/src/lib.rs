@@ -86,6 +86,9 @@ pub fn workflow(_attr: TokenStream, item: TokenStream) -> TokenStream {
         }
     };
 
+    // Name the step a step enum holds, as its __Step impl must report it
+    let step_type_methods = step_type_methods(&step_enum_ident, &step_types, &step_letters);
+
     // Generate <Name>StepError with variants A/B wrapping each step's <Step as Step>::Error
     let step_error_enum_ident = format_ident!("{}StepError", self_ty_ident);
     let step_error_variants: Vec<TokenStream2> = step_types
@@ -144,6 +147,7 @@ pub fn workflow(_attr: TokenStream, item: TokenStream) -> TokenStream {
     let output = quote! {
         #impl_item
         #step_enum
+        #step_type_methods
         #event_enum
         #step_error_enum
         #(#per_step_error_items)*
@@ -152,6 +156,32 @@ pub fn workflow(_attr: TokenStream, item: TokenStream) -> TokenStream {
     TokenStream::from(output)
 }
 
+/// `step_type` and `event_type` for the step enum, naming the step held by each variant rather
+/// than the enum itself.
+fn step_type_methods(
+    step_enum_ident: &Ident,
+    step_types: &[Type],
+    letters: &[Ident],
+) -> TokenStream2 {
+    quote! {
+        impl #step_enum_ident {
+            /// The type of the step this holds, for the `__Step::step_type` of this enum.
+            pub fn step_type(&self) -> ::std::borrow::Cow<'static, str> {
+                match self {
+                    #(Self::#letters(_) => ::std::borrow::Cow::Borrowed(::std::any::type_name::<#step_types>()),)*
+                }
+            }
+
+            /// The event type of the step this holds, for the `__Step::event_type` of this enum.
+            pub fn event_type(&self) -> ::std::borrow::Cow<'static, str> {
+                match self {
+                    #(Self::#letters(_) => <<#step_types as Step>::Event as JsonSchema>::schema_name(),)*
+                }
+            }
+        }
+    }
+}
+
 fn extract_macro_types(ty: &Type, expected_macro: &str) -> Vec<Type> {
     match ty {
         Type::Macro(m) => {
@@ -231,3 +261,53 @@ impl<T: Parse> Parse for MaybeParens<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn step_types_name_the_step_each_variant_holds() {
+        let step_types: Vec<Type> = vec![syn::parse_quote!(Charge), syn::parse_quote!(Ship)];
+        let letters: Vec<Ident> = (0..2)
+            .map(|i| Ident::new(&make_letters(i), Span::call_site()))
+            .collect();
+        let methods = step_type_methods(&format_ident!("OrderStep"), &step_types, &letters);
+        let methods: syn::ItemImpl = syn::parse2(methods).unwrap();
+
+        let arms = |name: &str| -> Vec<String> {
+            let method = methods
+                .items
+                .iter()
+                .find_map(|item| match item {
+                    ImplItem::Fn(function) if function.sig.ident == name => Some(function),
+                    _ => None,
+                })
+                .unwrap();
+            let syn::Stmt::Expr(syn::Expr::Match(body), _) = &method.block.stmts[0] else {
+                panic!("{name} isn't a match");
+            };
+            body.arms
+                .iter()
+                .map(|arm| {
+                    let (pat, body) = (&arm.pat, &arm.body);
+                    quote!(#pat => #body).to_string().replace(' ', "")
+                })
+                .collect()
+        };
+        assert_eq!(
+            arms("step_type"),
+            [
+                "Self::A(_)=>::std::borrow::Cow::Borrowed(::std::any::type_name::<Charge>())",
+                "Self::B(_)=>::std::borrow::Cow::Borrowed(::std::any::type_name::<Ship>())",
+            ]
+        );
+        assert_eq!(
+            arms("event_type"),
+            [
+                "Self::A(_)=><<ChargeasStep>::EventasJsonSchema>::schema_name()",
+                "Self::B(_)=><<ShipasStep>::EventasJsonSchema>::schema_name()",
+            ]
+        );
+    }
+}
@@ -0,0 +1,336 @@
+use std::{collections::BTreeSet, fmt, net::SocketAddr, str::FromStr, time::Duration};
+
+use control_server::ControlServerConfig;
+use surgeflow_types::TaskQueue;
+
+/// A worker [`main_handler`](crate::main_handler) can run. Each has a Cargo feature of the same
+/// name, which compiles it out when disabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Worker {
+    ControlServer,
+    NewInstance,
+    NextStep,
+    ActiveStep,
+    NewEvent,
+    CompletedStep,
+    FailedStep,
+    CompletedInstance,
+    FailedInstance,
+    Reaper,
+    OutboxRelay,
+}
+
+impl Worker {
+    pub const ALL: [Worker; 11] = [
+        Worker::ControlServer,
+        Worker::NewInstance,
+        Worker::NextStep,
+        Worker::ActiveStep,
+        Worker::NewEvent,
+        Worker::CompletedStep,
+        Worker::FailedStep,
+        Worker::CompletedInstance,
+        Worker::FailedInstance,
+        Worker::Reaper,
+        Worker::OutboxRelay,
+    ];
+
+    /// Also the name of the worker's feature.
+    pub fn name(self) -> &'static str {
+        match self {
+            Worker::ControlServer => "control_server",
+            Worker::NewInstance => "new_instance_worker",
+            Worker::NextStep => "next_step_worker",
+            Worker::ActiveStep => "active_step_worker",
+            Worker::NewEvent => "new_event_worker",
+            Worker::CompletedStep => "completed_step_worker",
+            Worker::FailedStep => "failed_step_worker",
+            Worker::CompletedInstance => "completed_instance_worker",
+            Worker::FailedInstance => "failed_instance_worker",
+            Worker::Reaper => "reaper_worker",
+            Worker::OutboxRelay => "outbox_relay_worker",
+        }
+    }
+
+    /// Whether the worker's feature is enabled.
+    pub fn is_compiled(self) -> bool {
+        match self {
+            Worker::ControlServer => cfg!(feature = "control_server"),
+            Worker::NewInstance => cfg!(feature = "new_instance_worker"),
+            Worker::NextStep => cfg!(feature = "next_step_worker"),
+            Worker::ActiveStep => cfg!(feature = "active_step_worker"),
+            Worker::NewEvent => cfg!(feature = "new_event_worker"),
+            Worker::CompletedStep => cfg!(feature = "completed_step_worker"),
+            Worker::FailedStep => cfg!(feature = "failed_step_worker"),
+            Worker::CompletedInstance => cfg!(feature = "completed_instance_worker"),
+            Worker::FailedInstance => cfg!(feature = "failed_instance_worker"),
+            Worker::Reaper => cfg!(feature = "reaper_worker"),
+            Worker::OutboxRelay => cfg!(feature = "outbox_relay_worker"),
+        }
+    }
+}
+
+impl fmt::Display for Worker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Worker {
+    type Err = ConfigError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Worker::ALL
+            .into_iter()
+            .find(|worker| worker.name() == name)
+            .ok_or_else(|| ConfigError::UnknownWorker(name.to_string()))
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigError {
+    #[error("unknown worker `{0}`")]
+    UnknownWorker(String),
+    #[error("worker `{0}` was selected, but its feature is disabled")]
+    WorkerNotCompiled(Worker),
+    #[error("no workers are selected")]
+    NoWorkers,
+    #[error("unknown flag `{0}`")]
+    UnknownFlag(String),
+    #[error("flag `{0}` needs a value")]
+    MissingValue(String),
+}
+
+/// What [`main_handler`](crate::main_handler) runs, and how.
+#[derive(Debug, Clone)]
+pub struct SurgeflowConfig {
+    /// Defaults to every worker whose feature is enabled.
+    pub workers: BTreeSet<Worker>,
+    /// Only used by the control server.
+    pub control_server: ControlServerConfig,
+    /// The task queues the active step worker runs steps from. No task queues means the
+    /// default one.
+    pub task_queues: Vec<TaskQueue>,
+    /// Where `/healthz`, `/readyz` and `/metrics` are served if the control server isn't
+    /// selected, as it serves them otherwise. Not served by default, as processes sharing a host
+    /// each need their own port.
+    pub probes: Option<SocketAddr>,
+    /// Only used by the reaper.
+    pub reaper: ReaperConfig,
+}
+
+/// How often the reaper sweeps, and what it considers stuck.
+#[derive(Debug, Clone)]
+pub struct ReaperConfig {
+    pub sweep_interval: Duration,
+    /// Lets the worker running an attempt notice its own missed heartbeat deadline before the
+    /// reaper does.
+    pub lost_attempt_grace: Duration,
+    /// Attempts of steps without a heartbeat timeout that run for longer than this are
+    /// considered lost. Steps with a heartbeat timeout run for as long as they heartbeat.
+    pub stale_attempt_threshold: Duration,
+    /// How long a claimed attempt waits for its step to be forwarded before a sweep claims it
+    /// again, in case forwarding it failed.
+    pub reclaim_after: Duration,
+    /// Steps that stay pending for longer than this are re-enqueued.
+    pub stale_pending_threshold: Duration,
+    /// Most attempts or steps claimed by one sweep, of each kind.
+    pub batch_size: u32,
+}
+
+impl Default for ReaperConfig {
+    fn default() -> Self {
+        Self {
+            sweep_interval: Duration::from_secs(10),
+            lost_attempt_grace: Duration::from_secs(30),
+            stale_attempt_threshold: Duration::from_secs(60 * 60),
+            reclaim_after: Duration::from_secs(60),
+            stale_pending_threshold: Duration::from_secs(15 * 60),
+            batch_size: 100,
+        }
+    }
+}
+
+impl Default for SurgeflowConfig {
+    fn default() -> Self {
+        Self {
+            workers: Worker::ALL
+                .into_iter()
+                .filter(|worker| worker.is_compiled())
+                .collect(),
+            control_server: ControlServerConfig::default(),
+            task_queues: Vec::new(),
+            probes: None,
+            reaper: ReaperConfig::default(),
+        }
+    }
+}
+
+impl SurgeflowConfig {
+    /// Runs only `workers`, instead of every compiled one.
+    pub fn workers(mut self, workers: impl IntoIterator<Item = Worker>) -> Self {
+        self.workers = workers.into_iter().collect();
+        self
+    }
+
+    pub fn control_server(mut self, control_server: ControlServerConfig) -> Self {
+        self.control_server = control_server;
+        self
+    }
+
+    pub fn task_queues<T: Into<TaskQueue>>(
+        mut self,
+        task_queues: impl IntoIterator<Item = T>,
+    ) -> Self {
+        self.task_queues = task_queues.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn probes(mut self, probes: Option<SocketAddr>) -> Self {
+        self.probes = probes;
+        self
+    }
+
+    pub fn reaper(mut self, reaper: ReaperConfig) -> Self {
+        self.reaper = reaper;
+        self
+    }
+
+    /// Applies command line flags over this configuration:
+    /// - `--workers active_step_worker,next_step_worker` selects the workers to run,
+    /// - `--task-queues gpu,default` selects the active step worker's task queues.
+    ///
+    /// Values can also follow an `=`, as in `--workers=control_server`.
+    pub fn with_args(
+        mut self,
+        args: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Result<Self, ConfigError> {
+        let mut args = args.into_iter().map(Into::into);
+        while let Some(arg) = args.next() {
+            let (flag, value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            if !matches!(flag.as_str(), "--workers" | "--task-queues") {
+                return Err(ConfigError::UnknownFlag(flag));
+            }
+            let Some(value) = value.or_else(|| args.next()) else {
+                return Err(ConfigError::MissingValue(flag));
+            };
+            let values = value
+                .split(',')
+                .map(str::trim)
+                .filter(|value| !value.is_empty());
+            if flag == "--workers" {
+                self.workers = values.map(str::parse).collect::<Result<_, _>>()?;
+            } else {
+                self.task_queues = values.map(TaskQueue::from).collect();
+            }
+        }
+        Ok(self)
+    }
+
+    /// Checks that at least one worker is selected, and that every selected worker is compiled
+    /// in.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.workers.is_empty() {
+            return Err(ConfigError::NoWorkers);
+        }
+        match self.workers.iter().find(|worker| !worker.is_compiled()) {
+            Some(worker) => Err(ConfigError::WorkerNotCompiled(*worker)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_args(args: &[&str]) -> Result<SurgeflowConfig, ConfigError> {
+        SurgeflowConfig::default().with_args(args.iter().copied())
+    }
+
+    #[test]
+    fn flag_values_follow_a_space_or_an_equals_sign() {
+        for args in [
+            &["--workers", "active_step_worker,next_step_worker"][..],
+            &["--workers=active_step_worker,next_step_worker"],
+            &["--workers", " active_step_worker, next_step_worker,"],
+        ] {
+            let config = with_args(args).unwrap();
+            assert_eq!(
+                config.workers,
+                BTreeSet::from([Worker::NextStep, Worker::ActiveStep])
+            );
+        }
+    }
+
+    #[test]
+    fn later_flags_override_earlier_ones() {
+        let config =
+            with_args(&["--workers=reaper_worker", "--workers", "control_server"]).unwrap();
+        assert_eq!(config.workers, BTreeSet::from([Worker::ControlServer]));
+    }
+
+    #[test]
+    fn task_queues_are_selected() {
+        let config = with_args(&["--task-queues", "gpu,default"]).unwrap();
+        assert_eq!(
+            config.task_queues,
+            [TaskQueue::from("gpu"), TaskQueue::from("default")]
+        );
+        assert!(SurgeflowConfig::default().task_queues.is_empty());
+    }
+
+    #[test]
+    fn invalid_args_are_rejected() {
+        assert!(matches!(
+            with_args(&["--worker", "reaper_worker"]),
+            Err(ConfigError::UnknownFlag(flag)) if flag == "--worker"
+        ));
+        assert!(matches!(
+            with_args(&["reaper_worker"]),
+            Err(ConfigError::UnknownFlag(flag)) if flag == "reaper_worker"
+        ));
+        assert!(matches!(
+            with_args(&["--workers"]),
+            Err(ConfigError::MissingValue(flag)) if flag == "--workers"
+        ));
+        assert!(matches!(
+            with_args(&["--workers", "reaper"]),
+            Err(ConfigError::UnknownWorker(worker)) if worker == "reaper"
+        ));
+    }
+
+    #[test]
+    fn validation_needs_a_compiled_worker() {
+        assert!(SurgeflowConfig::default().validate().is_ok());
+        assert!(matches!(
+            with_args(&["--workers="]).unwrap().validate(),
+            Err(ConfigError::NoWorkers)
+        ));
+        assert!(matches!(
+            SurgeflowConfig::default().workers([]).validate(),
+            Err(ConfigError::NoWorkers)
+        ));
+    }
+
+    #[cfg(not(feature = "reaper_worker"))]
+    #[test]
+    fn validation_rejects_workers_compiled_out() {
+        assert!(matches!(
+            SurgeflowConfig::default()
+                .workers([Worker::Reaper])
+                .validate(),
+            Err(ConfigError::WorkerNotCompiled(Worker::Reaper))
+        ));
+    }
+
+    #[test]
+    fn workers_parse_from_their_feature_names() {
+        for worker in Worker::ALL {
+            assert_eq!(worker.name().parse::<Worker>().unwrap(), worker);
+        }
+    }
+}
@@ -7,14 +7,18 @@
     feature = "failed_step_worker",
     feature = "failed_instance_worker",
     feature = "completed_instance_worker",
+    feature = "reaper_worker",
+    feature = "outbox_relay_worker",
     feature = "control_server"
 )))]
 compile_error!(
-    "At least one worker feature must be enabled. Please enable one or more of the following features: active_step_worker, new_instance_worker, next_step_worker, new_event_worker, completed_step_worker, failed_step_worker, failed_instance_worker, completed_instance_worker, control_server."
+    "At least one worker feature must be enabled. Please enable one or more of the following features: active_step_worker, new_instance_worker, next_step_worker, new_event_worker, completed_step_worker, failed_step_worker, failed_instance_worker, completed_instance_worker, reaper_worker, outbox_relay_worker, control_server."
 );
 
+mod config;
 pub mod workers;
 pub use adapter_types::*;
+pub use config::{ConfigError, ReaperConfig, SurgeflowConfig, Worker};
 pub use control_server::*;
 pub use macros::*;
 pub use main_handler::main_handler;
@@ -29,97 +33,236 @@ pub use surgeflow_types::*;
     feature = "failed_step_worker",
     feature = "failed_instance_worker",
     feature = "completed_instance_worker",
+    feature = "reaper_worker",
+    feature = "outbox_relay_worker",
     feature = "control_server"
 ))]
 mod main_handler {
+    use crate::config::{SurgeflowConfig, Worker};
+    #[cfg(feature = "active_step_worker")]
     use crate::workers::active_step_worker;
+    #[cfg(feature = "completed_instance_worker")]
     use crate::workers::completed_instance_worker;
+    #[cfg(feature = "completed_step_worker")]
     use crate::workers::completed_step_worker;
+    #[cfg(feature = "control_server")]
     use crate::workers::control_server;
+    #[cfg(feature = "failed_instance_worker")]
     use crate::workers::failed_instance_worker;
+    #[cfg(feature = "failed_step_worker")]
     use crate::workers::failed_step_worker;
+    #[cfg(feature = "new_event_worker")]
     use crate::workers::new_event_worker;
+    #[cfg(feature = "new_instance_worker")]
     use crate::workers::new_instance_worker;
+    #[cfg(feature = "next_step_worker")]
     use crate::workers::next_step_worker;
+    #[cfg(feature = "outbox_relay_worker")]
+    use crate::workers::outbox_relay_worker;
+    #[cfg(feature = "reaper_worker")]
+    use crate::workers::reaper_worker;
+    use crate::workers::{probes, process_registry};
     use ::control_server::ProjectWorkflowControl;
-    use adapter_types::dependencies::DependencyManager;
-    use surgeflow_types::Project;
-    use tokio::try_join;
+    use adapter_types::{dependencies::DependencyManager, health::Health};
+    use anyhow::Context;
+    use futures::future::{LocalBoxFuture, try_join_all};
+    use std::sync::Arc;
+    use surgeflow_types::{Project, TaskQueue};
 
+    /// Runs the workers selected by `config`. Fails if `config` selects a worker whose feature
+    /// is disabled.
+    ///
+    /// If the adapter has a worker registry, the process heartbeats its workers there while they
+    /// run, and deregisters when they stop.
+    ///
+    /// Every process records metrics. Without the control server, which serves them along with
+    /// the health endpoints, they are served on [`SurgeflowConfig::probes`] if it is set.
+    #[cfg_attr(
+        not(all(feature = "control_server", feature = "active_step_worker")),
+        allow(unused_variables)
+    )]
     pub async fn main_handler<P: Project, D>(
         project: P,
         mut dependency_manager: D,
+        config: SurgeflowConfig,
     ) -> anyhow::Result<()>
     where
-        D: DependencyManager<P>,
+        D: DependencyManager<P> + 'static,
         P::Workflow: ProjectWorkflowControl<P>,
     {
-        try_join!(
-            #[cfg(feature = "control_server")]
-            control_server::main::<P, _, _>(
+        config.validate()?;
+        let SurgeflowConfig {
+            workers,
+            control_server: control_server_config,
+            mut task_queues,
+            probes: probes_bind,
+            reaper: reaper_config,
+        } = config;
+        tracing::info!(
+            "Starting {}",
+            workers
+                .iter()
+                .map(|worker| worker.name())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        // shared by the workers and the health endpoints
+        let health = Arc::new(Health::default());
+        let metrics = probes::install_metrics_recorder();
+        if task_queues.is_empty() {
+            task_queues.push(TaskQueue::default());
+        }
+
+        let worker_registry = dependency_manager
+            .worker_registry()
+            .await
+            .context("Failed to get worker registry")?;
+        let process = process_registry::this_process::<P>(&workers, &task_queues);
+        let process_id = process.id;
+
+        let mut running: Vec<LocalBoxFuture<'static, anyhow::Result<()>>> = Vec::new();
+        if let Some(worker_registry) = worker_registry.clone() {
+            running.push(Box::pin(process_registry::main::<P, _>(
+                worker_registry,
+                process,
+                health.clone(),
+            )));
+        }
+        #[cfg(feature = "control_server")]
+        if workers.contains(&Worker::ControlServer) {
+            running.push(Box::pin(control_server::main::<P, D>(
                 dependency_manager
                     .control_server_dependencies()
                     .await
-                    .expect("Failed to get control server dependencies")
-            ),
-            #[cfg(feature = "active_step_worker")]
-            active_step_worker::main::<P, _, _, _, _, _>(
-                dependency_manager
-                    .active_step_worker_dependencies()
-                    .await
-                    .expect("Failed to get active step worker dependencies"),
-                project,
-            ),
-            #[cfg(feature = "new_instance_worker")]
-            new_instance_worker::main::<P, _, _, _>(
+                    .context("Failed to get control server dependencies")?,
+                control_server_config,
+                health.clone(),
+                metrics.clone(),
+            )));
+        }
+        if let Some(bind) = probes_bind
+            && !workers.contains(&Worker::ControlServer)
+        {
+            running.push(Box::pin(probes::main(bind, health.clone(), metrics)));
+        }
+        #[cfg(feature = "active_step_worker")]
+        if workers.contains(&Worker::ActiveStep) {
+            running.push(Box::pin(
+                active_step_worker::main::<P, _, _, _, _, _, _, _>(
+                    dependency_manager
+                        .active_step_worker_dependencies(&task_queues)
+                        .await
+                        .context("Failed to get active step worker dependencies")?,
+                    project,
+                    health.clone(),
+                ),
+            ));
+        }
+        #[cfg(feature = "new_instance_worker")]
+        if workers.contains(&Worker::NewInstance) {
+            running.push(Box::pin(new_instance_worker::main::<P, _, _, _, _, _>(
                 dependency_manager
                     .new_instance_worker_dependencies()
                     .await
-                    .expect("Failed to get new instance worker dependencies")
-            ),
-            #[cfg(feature = "next_step_worker")]
-            next_step_worker::main::<P, _, _, _, _>(
+                    .context("Failed to get new instance worker dependencies")?,
+                health.clone(),
+            )));
+        }
+        #[cfg(feature = "next_step_worker")]
+        if workers.contains(&Worker::NextStep) {
+            running.push(Box::pin(next_step_worker::main::<P, _, _, _, _, _, _>(
                 dependency_manager
                     .next_step_worker_dependencies()
                     .await
-                    .expect("Failed to get next step worker dependencies")
-            ),
-            #[cfg(feature = "new_event_worker")]
-            new_event_worker::main::<P, _, _, _>(
+                    .context("Failed to get next step worker dependencies")?,
+                health.clone(),
+            )));
+        }
+        #[cfg(feature = "new_event_worker")]
+        if workers.contains(&Worker::NewEvent) {
+            running.push(Box::pin(new_event_worker::main::<P, _, _, _, _>(
                 dependency_manager
                     .new_event_worker_dependencies()
                     .await
-                    .expect("Failed to get new event worker dependencies")
-            ),
-            #[cfg(feature = "completed_step_worker")]
-            completed_step_worker::main::<P, _, _, _>(
+                    .context("Failed to get new event worker dependencies")?,
+                health.clone(),
+            )));
+        }
+        #[cfg(feature = "completed_step_worker")]
+        if workers.contains(&Worker::CompletedStep) {
+            running.push(Box::pin(completed_step_worker::main::<
+                P,
+                _,
+                _,
+                _,
+                _,
+                _,
+                _,
+                _,
+            >(
                 dependency_manager
                     .completed_step_worker_dependencies()
                     .await
-                    .expect("Failed to get completed step worker dependencies")
-            ),
-            #[cfg(feature = "failed_step_worker")]
-            failed_step_worker::main::<P, _, _, _>(
+                    .context("Failed to get completed step worker dependencies")?,
+                health.clone(),
+            )));
+        }
+        #[cfg(feature = "failed_step_worker")]
+        if workers.contains(&Worker::FailedStep) {
+            running.push(Box::pin(failed_step_worker::main::<P, _, _, _, _, _, _>(
                 dependency_manager
                     .failed_step_worker_dependencies()
                     .await
-                    .expect("Failed to get failed step worker dependencies")
-            ),
-            #[cfg(feature = "failed_instance_worker")]
-            failed_instance_worker::main::<P, _>(
+                    .context("Failed to get failed step worker dependencies")?,
+                health.clone(),
+            )));
+        }
+        #[cfg(feature = "failed_instance_worker")]
+        if workers.contains(&Worker::FailedInstance) {
+            running.push(Box::pin(failed_instance_worker::main::<P, _, _, _, _>(
                 dependency_manager
                     .failed_instance_worker_dependencies()
                     .await
-                    .expect("Failed to get failed instance worker dependencies")
-            ),
-            #[cfg(feature = "completed_instance_worker")]
-            completed_instance_worker::main::<P, _>(
+                    .context("Failed to get failed instance worker dependencies")?,
+                health.clone(),
+            )));
+        }
+        #[cfg(feature = "completed_instance_worker")]
+        if workers.contains(&Worker::CompletedInstance) {
+            running.push(Box::pin(completed_instance_worker::main::<P, _, _, _, _>(
                 dependency_manager
                     .completed_instance_worker_dependencies()
                     .await
-                    .expect("Failed to get completed instance worker dependencies")
-            ),
-        )?;
+                    .context("Failed to get completed instance worker dependencies")?,
+                health.clone(),
+            )));
+        }
+        #[cfg(feature = "reaper_worker")]
+        if workers.contains(&Worker::Reaper) {
+            running.push(Box::pin(reaper_worker::main::<P, _, _, _, _>(
+                dependency_manager
+                    .reaper_worker_dependencies()
+                    .await
+                    .context("Failed to get reaper worker dependencies")?,
+                reaper_config,
+                health.clone(),
+            )));
+        }
+        #[cfg(feature = "outbox_relay_worker")]
+        if workers.contains(&Worker::OutboxRelay) {
+            running.push(Box::pin(outbox_relay_worker::main::<P, _, _, _, _>(
+                dependency_manager
+                    .outbox_relay_worker_dependencies()
+                    .await
+                    .context("Failed to get outbox relay worker dependencies")?,
+                health.clone(),
+            )));
+        }
+        let result = try_join_all(running).await;
+        if let Some(worker_registry) = &worker_registry {
+            process_registry::deregister::<P, _>(worker_registry, process_id).await;
+        }
+        result?;
 
         Ok(())
     }
@@ -1,11 +1,83 @@
+use std::{any::Any, future::pending, panic::AssertUnwindSafe, sync::Arc};
+
 use adapter_types::{
+    dead_letters::{DeadLetterManager, Queue},
     dependencies::active_step_worker::ActiveStepWorkerDependencies,
-    managers::PersistenceManager,
-    receivers::ActiveStepReceiver,
+    health::Health,
+    managers::{AttemptOutcome, AttemptStart, PersistenceManager},
+    notifications::{NotificationKind, NotificationSender},
+    receivers::{ActiveStepReceiver, DeliveryHandle},
     senders::{ActiveStepSender, CompletedStepSender, FailedStepSender},
+    telemetry::current_trace_context,
+};
+use derive_more::Debug;
+use futures::FutureExt;
+use surgeflow_types::{
+    __Step, __Workflow, __WorkflowStatic, FullyQualifiedStep, HeartbeatDetails, HeartbeatSink,
+    Project, RawStep, StepContext, StepId, StepStatus, TagUpdates,
+};
+use tokio::{
+    sync::watch,
+    time::{Instant, sleep_until},
+};
+use tracing::Instrument;
+
+use super::{
+    attempts::{FailAttemptError, fail_attempt},
+    failure_policy::{Settlement, WorkerError, record_error, settlement, with_backoff},
+    health::register_health_checks,
+    instrumentation::{
+        received, record_step_attempt_failed, record_step_error, record_step_run, stage_span,
+        step_run_span,
+    },
+    notifications::notify,
 };
-use anyhow::Context;
-use surgeflow_types::{__Step, FullyQualifiedStep, Immediate, Project};
+
+#[derive(thiserror::Error, Debug)]
+enum ActiveStepWorkerError<
+    P,
+    ActiveStepSenderT,
+    FailedStepSenderT,
+    CompletedStepSenderT,
+    PersistenceManagerT,
+> where
+    P: Project,
+    ActiveStepSenderT: ActiveStepSender<P>,
+    FailedStepSenderT: FailedStepSender<P>,
+    CompletedStepSenderT: CompletedStepSender<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+{
+    #[error("Database error occurred")]
+    DatabaseError(#[source] PersistenceManagerT::Error),
+    #[error("Failed to fail step attempt")]
+    FailAttemptError(
+        #[source] FailAttemptError<P, ActiveStepSenderT, FailedStepSenderT, PersistenceManagerT>,
+    ),
+    #[error("Failed to send completed step")]
+    SendCompletedStepError(#[source] CompletedStepSenderT::Error),
+    #[error("Step event is missing for step: {0}")]
+    MissingEvent(StepId),
+}
+
+impl<P, ActiveStepSenderT, FailedStepSenderT, CompletedStepSenderT, PersistenceManagerT> WorkerError
+    for ActiveStepWorkerError<
+        P,
+        ActiveStepSenderT,
+        FailedStepSenderT,
+        CompletedStepSenderT,
+        PersistenceManagerT,
+    >
+where
+    P: Project,
+    ActiveStepSenderT: ActiveStepSender<P>,
+    FailedStepSenderT: FailedStepSender<P>,
+    CompletedStepSenderT: CompletedStepSender<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+{
+    fn is_transient(&self) -> bool {
+        !matches!(self, Self::MissingEvent(_))
+    }
+}
 
 async fn process<
     P,
@@ -13,54 +85,332 @@ async fn process<
     FailedStepSenderT,
     CompletedStepSenderT,
     PersistenceManagerT,
+    NotificationSenderT,
 >(
     wf: <P as Project>::Workflow,
     active_step_sender: &mut ActiveStepSenderT,
     failed_step_sender: &mut FailedStepSenderT,
     completed_step_sender: &mut CompletedStepSenderT,
     persistence_manager: &mut PersistenceManagerT,
-    mut step: FullyQualifiedStep<P>,
-) -> anyhow::Result<()>
+    notification_sender: Option<&NotificationSenderT>,
+    step: FullyQualifiedStep<P>,
+) -> Result<
+    (),
+    ActiveStepWorkerError<
+        P,
+        ActiveStepSenderT,
+        FailedStepSenderT,
+        CompletedStepSenderT,
+        PersistenceManagerT,
+    >,
+>
 where
     P: Project,
     ActiveStepSenderT: ActiveStepSender<P>,
     FailedStepSenderT: FailedStepSender<P>,
     CompletedStepSenderT: CompletedStepSender<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     tracing::debug!("Received new step");
-    persistence_manager
-        .set_step_status(step.step_id, 3)
+
+    // TODO: at this point, this error will never happen, event is guaranteed to be Some(_)
+    // TODO: Could we have a FullyQualifiedStep where event isn't optional and thus avoid this "fake" error handling?
+    let event = step
+        .step
+        .event
+        .clone()
+        .ok_or(ActiveStepWorkerError::MissingEvent(step.step_id))?;
+
+    let started = with_backoff!(
+        "start step attempt",
+        persistence_manager.start_step_attempt(&step, step.step.settings.heartbeat_timeout)
+    )
+    .map_err(ActiveStepWorkerError::DatabaseError)?;
+    match started {
+        AttemptStart::Started => {}
+        AttemptStart::Duplicate => {
+            tracing::debug!(
+                "Attempt {} of step {} already ran, skipping",
+                step.retry_count + 1,
+                step.step_id
+            );
+            return Ok(());
+        }
+        // the worker that ran it stopped before forwarding its outcome, or failed to
+        AttemptStart::Finished(outcome) => {
+            tracing::info!(
+                "Attempt {} of step {} already finished, forwarding its outcome again",
+                step.retry_count + 1,
+                step.step_id
+            );
+            return forward_outcome(
+                active_step_sender,
+                failed_step_sender,
+                completed_step_sender,
+                persistence_manager,
+                step,
+                outcome,
+            )
+            .await;
+        }
+    }
+
+    with_backoff!(
+        "set step status",
+        persistence_manager.set_step_status(step.step_id, StepStatus::Running)
+    )
+    .map_err(ActiveStepWorkerError::DatabaseError)?;
+    let workflow = step.instance.workflow.name();
+    let step_type = step.step.step.step_type().into_owned();
+    notify(
+        "active_step_worker",
+        notification_sender,
+        step.instance.external_id,
+        NotificationKind::StepStarted {
+            step_id: step.step_id,
+            step_type: step_type.clone(),
+            attempt: step.retry_count + 1,
+        },
+    )
+    .await;
+
+    let run_span = step_run_span(workflow, &step_type, step.step_id, step.retry_count + 1);
+    let run_started = Instant::now();
+    let outcome = match run_step(wf, persistence_manager, &step, event)
+        .instrument(run_span.clone())
         .await
-        .context("TODO: handle error")?;
-
-    let event = step.step.event.clone().ok_or(
-        // TODO: at this point, this error will never happen, event is guaranteed to be Some(_)
-        // TODO: Could we have a FullyQualifiedStep where event isn't optional and thus avoid this "fake" error handling?
-        anyhow::anyhow!("Step event is missing for step: {}", step.step_id),
-    )?;
-
-    let next_step = step.step.step.run(wf.clone(), event).await;
-    step.retry_count += 1;
-    if let Ok(next_step) = next_step {
-        completed_step_sender
-            .send(FullyQualifiedStep { next_step, ..step })
-            .await?;
+    {
+        Ok((next_step, tag_updates)) => AttemptOutcome::Completed {
+            next_step,
+            tag_updates,
+        },
+        Err(error) => {
+            record_step_error(&run_span, &error);
+            AttemptOutcome::Failed { error }
+        }
+    };
+    let failure = match &outcome {
+        AttemptOutcome::Completed { .. } => None,
+        AttemptOutcome::Failed { error } => Some(error.clone()),
+    };
+    let run_outcome = if failure.is_some() {
+        "failed"
     } else {
-        // tracing::debug!("Failed to run step: {:?}", step.step);
-
-        if step.retry_count <= step.step.settings.max_retries {
-            tracing::debug!("Retrying step. Retry count: {}", step.retry_count);
-            active_step_sender.send(step).await?;
-        } else {
-            tracing::debug!("Max retries reached for step: {}", step.step_id);
-            failed_step_sender.send(step).await?;
+        "completed"
+    };
+    record_step_run(workflow, &step_type, run_outcome, run_started.elapsed());
+
+    // stores the outcome along with the end of the attempt, so that a redelivery can forward it
+    // again if this worker stops before forwarding it
+    let owned = with_backoff!(
+        "finish step attempt",
+        persistence_manager.finish_step_attempt(step.step_id, &outcome)
+    )
+    .map_err(ActiveStepWorkerError::DatabaseError)?;
+    if !owned {
+        tracing::warn!(
+            "Attempt of step {} was recovered as lost, discarding its outcome",
+            step.step_id
+        );
+        return Ok(());
+    }
+
+    let instance_id = step.instance.external_id;
+    let step_id = step.step_id;
+    let attempt = step.retry_count + 1;
+    let retrying = step.retry_count < step.step.settings.max_retries;
+    forward_outcome(
+        active_step_sender,
+        failed_step_sender,
+        completed_step_sender,
+        persistence_manager,
+        step,
+        outcome,
+    )
+    .await?;
+
+    if let Some(error) = failure {
+        record_step_attempt_failed(workflow, &step_type, retrying);
+        // running out of retries is notified by the failed step worker
+        if retrying {
+            notify(
+                "active_step_worker",
+                notification_sender,
+                instance_id,
+                NotificationKind::StepRetried {
+                    step_id,
+                    step_type,
+                    attempt,
+                    error,
+                },
+            )
+            .await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Hands the outcome of a finished attempt on: the completed step to the completed step worker,
+/// or the failure to [`fail_attempt`]. Forwarding the same outcome twice is harmless, as the
+/// next workers skip duplicates.
+async fn forward_outcome<
+    P,
+    ActiveStepSenderT,
+    FailedStepSenderT,
+    CompletedStepSenderT,
+    PersistenceManagerT,
+>(
+    active_step_sender: &mut ActiveStepSenderT,
+    failed_step_sender: &mut FailedStepSenderT,
+    completed_step_sender: &mut CompletedStepSenderT,
+    persistence_manager: &mut PersistenceManagerT,
+    mut step: FullyQualifiedStep<P>,
+    outcome: AttemptOutcome<P>,
+) -> Result<
+    (),
+    ActiveStepWorkerError<
+        P,
+        ActiveStepSenderT,
+        FailedStepSenderT,
+        CompletedStepSenderT,
+        PersistenceManagerT,
+    >,
+>
+where
+    P: Project,
+    ActiveStepSenderT: ActiveStepSender<P>,
+    FailedStepSenderT: FailedStepSender<P>,
+    CompletedStepSenderT: CompletedStepSender<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+{
+    match outcome {
+        AttemptOutcome::Completed {
+            next_step,
+            tag_updates,
+        } => {
+            if !tag_updates.is_empty() {
+                with_backoff!(
+                    "update instance tags",
+                    persistence_manager
+                        .update_instance_tags(step.instance.external_id, &tag_updates)
+                )
+                .map_err(ActiveStepWorkerError::DatabaseError)?;
+            }
+            step.retry_count += 1;
+            let step = FullyQualifiedStep {
+                next_step,
+                trace_context: current_trace_context(),
+                ..step
+            };
+            with_backoff!(
+                "send completed step",
+                completed_step_sender.send(step.clone())
+            )
+            .map_err(ActiveStepWorkerError::SendCompletedStepError)?;
+        }
+        AttemptOutcome::Failed { error } => {
+            fail_attempt(
+                active_step_sender,
+                failed_step_sender,
+                persistence_manager,
+                step,
+                &error,
+            )
+            .await
+            .map_err(ActiveStepWorkerError::FailAttemptError)?;
         }
     }
 
     Ok(())
 }
 
+/// Runs the user's step code, turning errors, panics and missed heartbeats into an error message.
+/// On success, also returns the tag changes the step made.
+async fn run_step<P, PersistenceManagerT>(
+    wf: <P as Project>::Workflow,
+    persistence_manager: &mut PersistenceManagerT,
+    step: &FullyQualifiedStep<P>,
+    event: <<P::Workflow as __Workflow<P>>::Step as __Step<P, P::Workflow>>::Event,
+) -> Result<(Option<RawStep<P, P::Workflow>>, TagUpdates), String>
+where
+    P: Project,
+    PersistenceManagerT: PersistenceManager<P>,
+{
+    let (heartbeat_sender, mut heartbeats) = watch::channel(HeartbeatDetails::default());
+    let ctx = StepContext::new(
+        step.instance.external_id,
+        step.step_id,
+        step.retry_count + 1,
+        Arc::new(HeartbeatChannel(heartbeat_sender)),
+    );
+    let tags = ctx.clone();
+    let heartbeat_timeout = step.step.settings.heartbeat_timeout;
+    let mut deadline = heartbeat_timeout.map(|timeout| Instant::now() + timeout);
+
+    // a panic in user step code is treated like any other step failure
+    let run = AssertUnwindSafe(step.step.step.run(wf, event, ctx)).catch_unwind();
+    tokio::pin!(run);
+
+    loop {
+        tokio::select! {
+            result = &mut run => {
+                return match result {
+                    Ok(Ok(next_step)) => Ok((next_step, tags.tag_updates())),
+                    Ok(Err(err)) => Err(err.to_string()),
+                    Err(panic) => {
+                        let message = panic_message(panic.as_ref());
+                        tracing::error!("Step {} panicked: {}", step.step_id, message);
+                        Err(format!("step panicked: {message}"))
+                    }
+                };
+            }
+            Ok(()) = heartbeats.changed() => {
+                let details = heartbeats.borrow_and_update().clone();
+                deadline = heartbeat_timeout.map(|timeout| Instant::now() + timeout);
+                if let Err(err) = persistence_manager
+                    .record_step_heartbeat(step.step_id, &details)
+                    .await
+                {
+                    tracing::warn!("Failed to record heartbeat of step {}: {:?}", step.step_id, err);
+                    record_error("active_step_worker", "heartbeat");
+                }
+            }
+            _ = async {
+                match deadline {
+                    Some(deadline) => sleep_until(deadline).await,
+                    None => pending().await,
+                }
+            } => {
+                tracing::warn!("Step {} missed its heartbeat deadline", step.step_id);
+                return Err(format!(
+                    "no heartbeat within {:?}",
+                    heartbeat_timeout.unwrap_or_default()
+                ));
+            }
+        }
+    }
+}
+
+struct HeartbeatChannel(watch::Sender<HeartbeatDetails>);
+
+impl HeartbeatSink for HeartbeatChannel {
+    fn heartbeat(&self, details: HeartbeatDetails) {
+        self.0.send_replace(details);
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
 pub async fn main<
     P,
     ActiveStepReceiverT,
@@ -68,6 +418,8 @@ pub async fn main<
     FailedStepSenderT,
     CompletedStepSenderT,
     PersistenceManagerT,
+    DeadLetterManagerT,
+    NotificationSenderT,
 >(
     dependencies: ActiveStepWorkerDependencies<
         P,
@@ -76,8 +428,11 @@ pub async fn main<
         FailedStepSenderT,
         CompletedStepSenderT,
         PersistenceManagerT,
+        DeadLetterManagerT,
+        NotificationSenderT,
     >,
     project: P,
+    health: Arc<Health>,
 ) -> anyhow::Result<()>
 where
     P: Project,
@@ -86,14 +441,30 @@ where
     FailedStepSenderT: FailedStepSender<P>,
     CompletedStepSenderT: CompletedStepSender<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     let active_step_receiver = dependencies.active_step_receiver;
     let active_step_sender = dependencies.active_step_sender;
     let failed_step_sender = dependencies.failed_step_sender;
     let completed_step_sender = dependencies.completed_step_sender;
     let persistence_manager = dependencies.persistence_manager;
+    let dead_letter_manager = dependencies.dead_letter_manager;
+    let notification_sender = dependencies.notification_sender;
+
+    let liveness = health.register_worker("active_step_worker");
+    register_health_checks!(
+        health,
+        "active_step_worker",
+        active_step_receiver,
+        active_step_sender,
+        failed_step_sender,
+        completed_step_sender,
+        persistence_manager
+    );
 
     loop {
+        liveness.beat();
         tracing::info!("Waiting for active step...");
         if let Err(err) = receive_and_process::<
             P,
@@ -102,12 +473,16 @@ where
             FailedStepSenderT,
             CompletedStepSenderT,
             PersistenceManagerT,
+            DeadLetterManagerT,
+            NotificationSenderT,
         >(
             &active_step_receiver,
             &active_step_sender,
             &failed_step_sender,
             &completed_step_sender,
             &persistence_manager,
+            &dead_letter_manager,
+            notification_sender.as_ref(),
             &project,
         )
         .await
@@ -117,6 +492,7 @@ where
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn receive_and_process<
     P,
     ActiveStepReceiverT,
@@ -124,12 +500,16 @@ async fn receive_and_process<
     FailedStepSenderT,
     CompletedStepSenderT,
     PersistenceManagerT,
+    DeadLetterManagerT,
+    NotificationSenderT,
 >(
     active_step_receiver: &ActiveStepReceiverT,
     active_step_sender: &ActiveStepSenderT,
     failed_step_sender: &FailedStepSenderT,
     completed_step_sender: &CompletedStepSenderT,
     persistence_manager: &PersistenceManagerT,
+    dead_letter_manager: &DeadLetterManagerT,
+    notification_sender: Option<&NotificationSenderT>,
     project: &P,
 ) -> anyhow::Result<()>
 where
@@ -139,47 +519,67 @@ where
     FailedStepSenderT: FailedStepSender<P>,
     CompletedStepSenderT: CompletedStepSender<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     let mut active_step_receiver = active_step_receiver.clone();
 
     let (step, handle) = active_step_receiver.receive().await?;
+    let redelivery_count = handle.redelivery_count();
+    let in_flight = received("active_step_worker", &handle);
+    let span = stage_span("active_step_worker", step.trace_context.as_ref());
     let active_step_sender = active_step_sender.clone();
     let failed_step_sender = failed_step_sender.clone();
     let completed_step_sender = completed_step_sender.clone();
     let persistence_manager = persistence_manager.clone();
+    let dead_letter_manager = dead_letter_manager.clone();
+    let notification_sender = notification_sender.cloned();
     let project = project.clone();
 
-    tokio::spawn(async move {
+    let task = async move {
+        let _in_flight = in_flight;
         let wf = project.workflow_for_step(&step.step.step);
 
-        if let Err(err) = process::<
+        let result = process::<
             P,
             ActiveStepSenderT,
             FailedStepSenderT,
             CompletedStepSenderT,
             PersistenceManagerT,
+            NotificationSenderT,
         >(
             wf.clone(),
             &mut active_step_sender.clone(),
             &mut failed_step_sender.clone(),
             &mut completed_step_sender.clone(),
             &mut persistence_manager.clone(),
-            step,
+            notification_sender.as_ref(),
+            step.clone(),
         )
-        .await
-        {
-            tracing::error!("Error processing active step: {:?}", err);
-        }
+        .await;
 
-        tracing::debug!("acknowledging active step for instance");
-        active_step_receiver
-            .accept(handle)
-            .await
-            .inspect_err(|e| {
-                tracing::error!("Failed to acknowledge active step: {:?}", e);
-            })
-            .unwrap();
-        tracing::debug!("acknowledged active step for instance");
-    });
+        let settlement = settlement::<P, _, _>(
+            "active_step_worker",
+            Queue::ActiveStep,
+            &dead_letter_manager,
+            &step,
+            redelivery_count,
+            result,
+        )
+        .await;
+        let stage = settlement.stage();
+        tracing::debug!("settling active step for instance ({stage})");
+        let settled = match settlement {
+            Settlement::Accept => active_step_receiver.accept(handle).await,
+            Settlement::Reject(delay) => active_step_receiver.reject(handle, Some(delay)).await,
+        };
+        if let Err(err) = settled {
+            tracing::error!("Failed to settle active step ({stage}): {:?}", err);
+            record_error("active_step_worker", stage);
+            return;
+        }
+        tracing::debug!("settled active step for instance ({stage})");
+    };
+    tokio::spawn(task.instrument(span));
     Ok(())
 }
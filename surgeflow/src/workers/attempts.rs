@@ -0,0 +1,62 @@
+use adapter_types::{
+    managers::PersistenceManager,
+    senders::{ActiveStepSender, FailedStepSender},
+    telemetry::current_trace_context,
+};
+use derive_more::Debug;
+use surgeflow_types::{FullyQualifiedStep, Project};
+
+use super::failure_policy::with_backoff;
+
+#[derive(thiserror::Error, Debug)]
+#[allow(clippy::enum_variant_names)]
+pub(crate) enum FailAttemptError<P, ActiveStepSenderT, FailedStepSenderT, PersistenceManagerT>
+where
+    P: Project,
+    ActiveStepSenderT: ActiveStepSender<P>,
+    FailedStepSenderT: FailedStepSender<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+{
+    #[error("Database error occurred")]
+    DatabaseError(#[source] PersistenceManagerT::Error),
+    #[error("Failed to send active step")]
+    SendActiveStepError(#[source] ActiveStepSenderT::Error),
+    #[error("Failed to send failed step")]
+    SendFailedStepError(#[source] FailedStepSenderT::Error),
+}
+
+/// Records the failed attempt and retries the step, or fails it once it is out of retries.
+pub(crate) async fn fail_attempt<P, ActiveStepSenderT, FailedStepSenderT, PersistenceManagerT>(
+    active_step_sender: &mut ActiveStepSenderT,
+    failed_step_sender: &mut FailedStepSenderT,
+    persistence_manager: &mut PersistenceManagerT,
+    mut step: FullyQualifiedStep<P>,
+    error: &str,
+) -> Result<(), FailAttemptError<P, ActiveStepSenderT, FailedStepSenderT, PersistenceManagerT>>
+where
+    P: Project,
+    ActiveStepSenderT: ActiveStepSender<P>,
+    FailedStepSenderT: FailedStepSender<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+{
+    step.retry_count += 1;
+    // the reaper fails attempts outside of any span, keep the step's trace then
+    step.trace_context = current_trace_context().or(step.trace_context);
+    with_backoff!(
+        "insert step error",
+        persistence_manager.insert_step_error(step.step_id, step.retry_count, error)
+    )
+    .map_err(FailAttemptError::DatabaseError)?;
+
+    if step.retry_count <= step.step.settings.max_retries {
+        tracing::debug!("Retrying step. Retry count: {}", step.retry_count);
+        with_backoff!("send active step", active_step_sender.send(step.clone()))
+            .map_err(FailAttemptError::SendActiveStepError)?;
+    } else {
+        tracing::debug!("Max retries reached for step: {}", step.step_id);
+        with_backoff!("send failed step", failed_step_sender.send(step.clone()))
+            .map_err(FailAttemptError::SendFailedStepError)?;
+    }
+
+    Ok(())
+}
@@ -1,23 +1,95 @@
+use std::sync::Arc;
+
 use adapter_types::{
+    dead_letters::{DeadLetterManager, Queue},
     dependencies::completed_instance_worker::CompletedInstanceWorkerDependencies,
-    receivers::CompletedInstanceReceiver,
+    health::Health,
+    managers::PersistenceManager,
+    notifications::{NotificationKind, NotificationSender},
+    receivers::{CompletedInstanceReceiver, DeliveryHandle},
+};
+use surgeflow_types::{__WorkflowStatic, InstanceStatus, Project, WorkflowInstance};
+use tracing::Instrument;
+
+use super::{
+    failure_policy::{Settlement, record_error, settlement, with_backoff},
+    health::register_health_checks,
+    instrumentation::{received, record_instance_finished, stage_span},
+    notifications::notify,
 };
-use surgeflow_types::{Project, WorkflowInstance};
 
-async fn process<P: Project>(instance: WorkflowInstance<P>) -> anyhow::Result<()> {
+async fn process<P, PersistenceManagerT, NotificationSenderT>(
+    persistence_manager: &mut PersistenceManagerT,
+    notification_sender: Option<&NotificationSenderT>,
+    instance: WorkflowInstance<P>,
+) -> anyhow::Result<()>
+where
+    P: Project,
+    PersistenceManagerT: PersistenceManager<P>,
+    NotificationSenderT: NotificationSender<P>,
+{
     tracing::debug!("Completed instance: {:?}", instance);
 
+    with_backoff!(
+        "set instance status",
+        persistence_manager.set_instance_status(instance.external_id, InstanceStatus::Completed)
+    )?;
+    record_instance_finished(instance.workflow.name(), "completed");
+    notify(
+        "completed_instance_worker",
+        notification_sender,
+        instance.external_id,
+        NotificationKind::InstanceCompleted,
+    )
+    .await;
+
     Ok(())
 }
 
-pub async fn main<P: Project, CompletedInstanceReceiverT: CompletedInstanceReceiver<P>>(
-    dependencies: CompletedInstanceWorkerDependencies<P, CompletedInstanceReceiverT>,
+pub async fn main<
+    P: Project,
+    CompletedInstanceReceiverT: CompletedInstanceReceiver<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    NotificationSenderT: NotificationSender<P>,
+>(
+    dependencies: CompletedInstanceWorkerDependencies<
+        P,
+        CompletedInstanceReceiverT,
+        PersistenceManagerT,
+        DeadLetterManagerT,
+        NotificationSenderT,
+    >,
+    health: Arc<Health>,
 ) -> anyhow::Result<()> {
     let completed_instance_receiver = dependencies.completed_instance_receiver;
+    let persistence_manager = dependencies.persistence_manager;
+    let dead_letter_manager = dependencies.dead_letter_manager;
+    let notification_sender = dependencies.notification_sender;
+
+    let liveness = health.register_worker("completed_instance_worker");
+    register_health_checks!(
+        health,
+        "completed_instance_worker",
+        completed_instance_receiver,
+        persistence_manager
+    );
 
     loop {
-        if let Err(err) =
-            receive_and_process::<P, CompletedInstanceReceiverT>(&completed_instance_receiver).await
+        liveness.beat();
+        if let Err(err) = receive_and_process::<
+            P,
+            CompletedInstanceReceiverT,
+            PersistenceManagerT,
+            DeadLetterManagerT,
+            NotificationSenderT,
+        >(
+            &completed_instance_receiver,
+            &persistence_manager,
+            &dead_letter_manager,
+            notification_sender.as_ref(),
+        )
+        .await
         {
             tracing::error!("Error processing completed instance: {:?}", err);
         }
@@ -27,27 +99,60 @@ pub async fn main<P: Project, CompletedInstanceReceiverT: CompletedInstanceRecei
 async fn receive_and_process<
     P: Project,
     CompletedInstanceReceiverT: CompletedInstanceReceiver<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    NotificationSenderT: NotificationSender<P>,
 >(
     completed_instance_receiver: &CompletedInstanceReceiverT,
+    persistence_manager: &PersistenceManagerT,
+    dead_letter_manager: &DeadLetterManagerT,
+    notification_sender: Option<&NotificationSenderT>,
 ) -> anyhow::Result<()> {
     let mut completed_instance_receiver = completed_instance_receiver.clone();
 
     let (step, handle) = completed_instance_receiver.receive().await?;
+    let redelivery_count = handle.redelivery_count();
+    let in_flight = received("completed_instance_worker", &handle);
+    let span = stage_span("completed_instance_worker", step.trace_context.as_ref());
+    let persistence_manager = persistence_manager.clone();
+    let dead_letter_manager = dead_letter_manager.clone();
+    let notification_sender = notification_sender.cloned();
 
-    tokio::spawn(async move {
-        if let Err(err) = process::<P>(step).await {
-            tracing::error!("Error processing workflow instance: {:?}", err);
-        }
+    let task = async move {
+        let _in_flight = in_flight;
+        let result = process::<P, _, _>(
+            &mut persistence_manager.clone(),
+            notification_sender.as_ref(),
+            step.clone(),
+        )
+        .await;
 
-        tracing::debug!("acknowledging completed instance");
-        completed_instance_receiver
-            .accept(handle)
-            .await
-            .inspect_err(|e| {
-                tracing::error!("Failed to acknowledge completed instance: {:?}", e);
-            })
-            .unwrap();
-        tracing::debug!("acknowledged completed instance");
-    });
+        let settlement = settlement::<P, _, _>(
+            "completed_instance_worker",
+            Queue::CompletedInstance,
+            &dead_letter_manager,
+            &step,
+            redelivery_count,
+            result,
+        )
+        .await;
+        let stage = settlement.stage();
+        tracing::debug!("settling completed instance ({stage})");
+        let settled = match settlement {
+            Settlement::Accept => completed_instance_receiver.accept(handle).await,
+            Settlement::Reject(delay) => {
+                completed_instance_receiver
+                    .reject(handle, Some(delay))
+                    .await
+            }
+        };
+        if let Err(err) = settled {
+            tracing::error!("Failed to settle completed instance ({stage}): {:?}", err);
+            record_error("completed_instance_worker", stage);
+            return;
+        }
+        tracing::debug!("settled completed instance ({stage})");
+    };
+    tokio::spawn(task.instrument(span));
     Ok(())
 }
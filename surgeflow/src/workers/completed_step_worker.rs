@@ -1,32 +1,86 @@
+use std::sync::Arc;
+
 use adapter_types::{
+    dead_letters::{DeadLetterManager, Queue},
     dependencies::completed_step_worker::CompletedStepWorkerDependencies,
-    managers::PersistenceManager, receivers::CompletedStepReceiver, senders::NextStepSender,
+    health::Health,
+    managers::PersistenceManager,
+    notifications::{NotificationKind, NotificationSender},
+    outbox::{Outbox, OutboxMessage, StateChange},
+    receivers::{CompletedStepReceiver, DeliveryHandle},
+    senders::{CompletedInstanceSender, NextStepSender},
+    telemetry::current_trace_context,
 };
 use derive_more::Debug;
-use surgeflow_types::{FullyQualifiedStep, Project, StepId};
+use surgeflow_types::{__Step, FullyQualifiedStep, Project, StepId, StepStatus, WorkflowInstance};
+use tracing::Instrument;
+
+use super::{
+    failure_policy::{Settlement, WorkerError, record_error, settlement, with_backoff},
+    health::register_health_checks,
+    instrumentation::{received, stage_span},
+    notifications::notify,
+};
 
-pub async fn main<P: Project, CompletedStepReceiverT, NextStepSenderT, PersistenceManagerT>(
+pub async fn main<
+    P: Project,
+    CompletedStepReceiverT,
+    NextStepSenderT,
+    CompletedInstanceSenderT,
+    PersistenceManagerT,
+    DeadLetterManagerT,
+    OutboxT,
+    NotificationSenderT,
+>(
     dependencies: CompletedStepWorkerDependencies<
         P,
         CompletedStepReceiverT,
         NextStepSenderT,
+        CompletedInstanceSenderT,
         PersistenceManagerT,
+        DeadLetterManagerT,
+        OutboxT,
+        NotificationSenderT,
     >,
+    health: Arc<Health>,
 ) -> anyhow::Result<()>
 where
     CompletedStepReceiverT: CompletedStepReceiver<P>,
     NextStepSenderT: NextStepSender<P>,
+    CompletedInstanceSenderT: CompletedInstanceSender<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    OutboxT: Outbox<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     let completed_step_receiver = dependencies.completed_step_receiver;
     let next_step_sender = dependencies.next_step_sender;
+    let completed_instance_sender = dependencies.completed_instance_sender;
     let persistence_manager = dependencies.persistence_manager;
+    let dead_letter_manager = dependencies.dead_letter_manager;
+    let outbox = dependencies.outbox;
+    let notification_sender = dependencies.notification_sender;
+
+    let liveness = health.register_worker("completed_step_worker");
+    register_health_checks!(
+        health,
+        "completed_step_worker",
+        completed_step_receiver,
+        next_step_sender,
+        completed_instance_sender,
+        persistence_manager
+    );
 
     loop {
+        liveness.beat();
         if let Err(err) = receive_and_process(
             &completed_step_receiver,
             &next_step_sender,
+            &completed_instance_sender,
             &persistence_manager,
+            &dead_letter_manager,
+            outbox.as_ref(),
+            notification_sender.as_ref(),
         )
         .await
         {
@@ -39,102 +93,261 @@ async fn receive_and_process<
     P: Project,
     CompletedStepReceiverT,
     NextStepSenderT,
+    CompletedInstanceSenderT,
     PersistenceManagerT,
+    DeadLetterManagerT,
+    OutboxT,
+    NotificationSenderT,
 >(
     completed_step_receiver: &CompletedStepReceiverT,
     next_step_sender: &NextStepSenderT,
+    completed_instance_sender: &CompletedInstanceSenderT,
     persistence_manager: &PersistenceManagerT,
+    dead_letter_manager: &DeadLetterManagerT,
+    outbox: Option<&OutboxT>,
+    notification_sender: Option<&NotificationSenderT>,
 ) -> anyhow::Result<()>
 where
     CompletedStepReceiverT: CompletedStepReceiver<P>,
     NextStepSenderT: NextStepSender<P>,
+    CompletedInstanceSenderT: CompletedInstanceSender<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    OutboxT: Outbox<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     let mut completed_step_receiver = completed_step_receiver.clone();
 
     let (step, handle) = completed_step_receiver.receive().await?;
+    let redelivery_count = handle.redelivery_count();
+    let in_flight = received("completed_step_worker", &handle);
+    let span = stage_span("completed_step_worker", step.trace_context.as_ref());
     let next_step_sender = next_step_sender.clone();
+    let completed_instance_sender = completed_instance_sender.clone();
     let persistence_manager = persistence_manager.clone();
+    let dead_letter_manager = dead_letter_manager.clone();
+    let outbox = outbox.cloned();
+    let notification_sender = notification_sender.cloned();
 
-    tokio::spawn(async move {
-        if let Err(err) = process(
+    let task = async move {
+        let _in_flight = in_flight;
+        let result = process(
             &mut next_step_sender.clone(),
+            &mut completed_instance_sender.clone(),
             &mut persistence_manager.clone(),
-            step,
+            outbox.as_ref(),
+            notification_sender.as_ref(),
+            step.clone(),
         )
-        .await
-        {
-            tracing::error!("Error processing completed step: {:?}", err);
-        }
+        .await;
 
-        tracing::debug!("acknowledging completed step for instance");
-        completed_step_receiver
-            .accept(handle)
-            .await
-            .inspect_err(|e| {
-                tracing::error!("Failed to acknowledge completed step: {:?}", e);
-            })
-            .unwrap();
-        tracing::debug!("acknowledged completed step for instance");
-    });
+        let settlement = settlement::<P, _, _>(
+            "completed_step_worker",
+            Queue::CompletedStep,
+            &dead_letter_manager,
+            &step,
+            redelivery_count,
+            result,
+        )
+        .await;
+        let stage = settlement.stage();
+        tracing::debug!("settling completed step for instance ({stage})");
+        let settled = match settlement {
+            Settlement::Accept => completed_step_receiver.accept(handle).await,
+            Settlement::Reject(delay) => completed_step_receiver.reject(handle, Some(delay)).await,
+        };
+        if let Err(err) = settled {
+            tracing::error!("Failed to settle completed step ({stage}): {:?}", err);
+            record_error("completed_step_worker", stage);
+            return;
+        }
+        tracing::debug!("settled completed step for instance ({stage})");
+    };
+    tokio::spawn(task.instrument(span));
     Ok(())
 }
 
 #[derive(thiserror::Error, Debug)]
-enum CompletedStepWorkerError<P: Project, NextStepSenderT: NextStepSender<P>> {
+#[allow(clippy::enum_variant_names)]
+enum CompletedStepWorkerError<
+    P,
+    NextStepSenderT,
+    CompletedInstanceSenderT,
+    PersistenceManagerT,
+    OutboxT,
+> where
+    P: Project,
+    NextStepSenderT: NextStepSender<P>,
+    CompletedInstanceSenderT: CompletedInstanceSender<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+    OutboxT: Outbox<P>,
+{
+    #[error("Database error occurred")]
+    DatabaseError(#[source] PersistenceManagerT::Error),
     #[error("Failed to send next step")]
     SendNextStepError(#[source] <NextStepSenderT as NextStepSender<P>>::Error),
+    #[error("Failed to send completed instance")]
+    SendCompletedInstanceError(#[source] CompletedInstanceSenderT::Error),
+    #[error("Failed to commit to outbox")]
+    OutboxError(#[source] OutboxT::Error),
 }
 
-async fn process<P, NextStepSenderT, PersistenceManagerT>(
+impl<P, NextStepSenderT, CompletedInstanceSenderT, PersistenceManagerT, OutboxT> WorkerError
+    for CompletedStepWorkerError<
+        P,
+        NextStepSenderT,
+        CompletedInstanceSenderT,
+        PersistenceManagerT,
+        OutboxT,
+    >
+where
+    P: Project,
+    NextStepSenderT: NextStepSender<P>,
+    CompletedInstanceSenderT: CompletedInstanceSender<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+    OutboxT: Outbox<P>,
+{
+}
+
+async fn process<
+    P,
+    NextStepSenderT,
+    CompletedInstanceSenderT,
+    PersistenceManagerT,
+    OutboxT,
+    NotificationSenderT,
+>(
     next_step_sender: &mut NextStepSenderT,
+    completed_instance_sender: &mut CompletedInstanceSenderT,
     persistence_manager: &mut PersistenceManagerT,
+    outbox: Option<&OutboxT>,
+    notification_sender: Option<&NotificationSenderT>,
     step: FullyQualifiedStep<P>,
-) -> Result<(), CompletedStepWorkerError<P, NextStepSenderT>>
+) -> Result<
+    (),
+    CompletedStepWorkerError<
+        P,
+        NextStepSenderT,
+        CompletedInstanceSenderT,
+        PersistenceManagerT,
+        OutboxT,
+    >,
+>
 where
     P: Project,
     NextStepSenderT: NextStepSender<P>,
+    CompletedInstanceSenderT: CompletedInstanceSender<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    OutboxT: Outbox<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     tracing::debug!(
         "received completed step for instance: {}",
         step.instance.external_id
     );
+    let instance_id = step.instance.external_id;
+    let completed = NotificationKind::StepCompleted {
+        step_id: step.step_id,
+        step_type: step.step.step.step_type().into_owned(),
+    };
 
-    persistence_manager
-        .set_step_status(step.step_id, 4)
-        .await
-        .expect("Failed to set step status");
+    if let Some(outbox) = outbox {
+        let next_step = step.next_step.map(|next_step| FullyQualifiedStep {
+            instance: step.instance.clone(),
+            step_id: StepId::successor_of(step.step_id),
+            step: next_step,
+
+            retry_count: 0,
+            previous_step_id: Some(step.step_id),
+            next_step: None,
+            trace_context: current_trace_context(),
+        });
+        let changes = vec![
+            StateChange::SetStepStatus {
+                step_id: step.step_id,
+                status: StepStatus::Completed,
+            },
+            StateChange::InsertStepOutput {
+                step_id: step.step_id,
+                output: next_step
+                    .as_ref()
+                    .map(|next_step| next_step.step.step.clone()),
+            },
+        ];
+        let messages = vec![match next_step {
+            Some(next_step) => OutboxMessage::NextStep(next_step),
+            None => OutboxMessage::CompletedInstance(WorkflowInstance {
+                trace_context: current_trace_context(),
+                ..step.instance.clone()
+            }),
+        }];
+        with_backoff!(
+            "commit to outbox",
+            outbox.commit(changes.clone(), messages.clone())
+        )
+        .map_err(CompletedStepWorkerError::OutboxError)?;
+        notify(
+            "completed_step_worker",
+            notification_sender,
+            instance_id,
+            completed,
+        )
+        .await;
+        return Ok(());
+    }
+
+    with_backoff!(
+        "set step status",
+        persistence_manager.set_step_status(step.step_id, StepStatus::Completed)
+    )
+    .map_err(CompletedStepWorkerError::DatabaseError)?;
 
     let next_step = step.next_step;
 
     if let Some(next_step) = next_step {
-        persistence_manager
-            .insert_step_output(step.step_id, Some(&next_step.step))
-            .await
-            .expect("TODO: handle error");
-
-        next_step_sender
-            .send(FullyQualifiedStep {
-                instance: step.instance,
-                step_id: StepId::new(),
-                step: next_step,
-                
-                retry_count: 0,
-                previous_step_id: Some(step.step_id),
-                next_step: None,
-            })
-            .await
+        with_backoff!(
+            "insert step output",
+            persistence_manager.insert_step_output(step.step_id, Some(&next_step.step))
+        )
+        .map_err(CompletedStepWorkerError::DatabaseError)?;
+
+        let next_step = FullyQualifiedStep {
+            instance: step.instance,
+            step_id: StepId::successor_of(step.step_id),
+            step: next_step,
+
+            retry_count: 0,
+            previous_step_id: Some(step.step_id),
+            next_step: None,
+            trace_context: current_trace_context(),
+        };
+        with_backoff!("send next step", next_step_sender.send(next_step.clone()))
             .map_err(CompletedStepWorkerError::SendNextStepError)?;
     } else {
-        // TODO: push to instance completed queue ?
         tracing::debug!("Instance {} completed", step.instance.external_id);
 
-        persistence_manager
-            .insert_step_output(step.step_id, None)
-            .await
-            .expect("TODO: handle error");
+        with_backoff!(
+            "insert step output",
+            persistence_manager.insert_step_output(step.step_id, None)
+        )
+        .map_err(CompletedStepWorkerError::DatabaseError)?;
+
+        with_backoff!(
+            "send completed instance",
+            completed_instance_sender.send(WorkflowInstance {
+                trace_context: current_trace_context(),
+                ..step.instance.clone()
+            })
+        )
+        .map_err(CompletedStepWorkerError::SendCompletedInstanceError)?;
     }
+    notify(
+        "completed_step_worker",
+        notification_sender,
+        instance_id,
+        completed,
+    )
+    .await;
 
     Ok(())
 }
@@ -1,68 +1,140 @@
-use adapter_types::{
-    dependencies::control_server::ControlServerDependencies,
-    senders::{EventSender, NewInstanceSender},
-};
+use std::sync::Arc;
+
+use adapter_types::{dependencies::ControlServerDependencyProvider, health::Health};
 use aide::{
     axum::{ApiRouter, IntoApiResponse, routing::get_with},
-    openapi::{Info, OpenApi},
+    openapi::{Info, OpenApi, Server},
     scalar::Scalar,
 };
-use axum::{Extension, Json, ServiceExt, extract::Request};
-use control_server::{ProjectWorkflowControl, init_app_state};
+use anyhow::{Context, ensure};
+use axum::{
+    Extension, Json, Router, ServiceExt,
+    extract::{DefaultBodyLimit, Request},
+    middleware,
+};
+use axum_server::tls_rustls::RustlsConfig;
+use control_server::{
+    ControlServerConfig, Dependencies, ProjectWorkflowControl,
+    auth::{self, Auth},
+    dead_letter_router, health_router, init_app_state, metrics_router, trace_request,
+    worker_router,
+};
+use metrics_exporter_prometheus::PrometheusHandle;
 use surgeflow_types::Project;
 use tokio::net::TcpListener;
 use tower::Layer;
-use tower_http::normalize_path::NormalizePathLayer;
+use tower_http::{limit::RequestBodyLimitLayer, normalize_path::NormalizePathLayer};
 
-pub async fn main<P, EventSenderT, NewInstanceSenderT>(
-    dependencies: ControlServerDependencies<P, EventSenderT, NewInstanceSenderT>,
+pub async fn main<P, D>(
+    dependencies: Dependencies<P, D>,
+    config: ControlServerConfig,
+    health: Arc<Health>,
+    metrics: Option<PrometheusHandle>,
 ) -> anyhow::Result<()>
 where
     P::Workflow: ProjectWorkflowControl<P>,
     P: Project,
-    EventSenderT: EventSender<P> + std::marker::Send + std::marker::Sync + 'static,
-    NewInstanceSenderT: NewInstanceSender<P> + std::marker::Send + std::marker::Sync + 'static,
+    D: ControlServerDependencyProvider<P> + 'static,
 {
-    let app_state = init_app_state(dependencies).await?;
-    let router = P::Workflow::control_router()
+    let auth = Arc::new(Auth::new(config.auth.clone())?);
+    let app_state = init_app_state::<P, D>(dependencies, health, metrics).await?;
+    let router = P::Workflow::control_router::<D>()
         .await?
+        .merge(dead_letter_router::<P, D>())
+        .merge(worker_router::<P, D>())
+        // the docs, added by `serve`, stay open
+        .layer(middleware::from_fn_with_state(auth, auth::authenticate))
+        // probes and metric scrapers reach these without credentials
+        .merge(health_router::<P, D>())
+        .merge(metrics_router::<P, D>())
+        // outermost, so that authentication runs in the request's span too
+        .layer(middleware::from_fn(trace_request))
         .with_state(app_state.clone());
 
-    serve(router).await
+    serve(router, config).await
 }
 
-async fn serve(router: ApiRouter) -> anyhow::Result<()> {
+async fn serve(router: ApiRouter, config: ControlServerConfig) -> anyhow::Result<()> {
+    let base_path = config.base_path.trim_end_matches('/');
+    ensure!(
+        base_path.is_empty() || base_path.starts_with('/'),
+        "the control server base path must start with `/`, got {:?}",
+        config.base_path
+    );
+
     let router = ApiRouter::new().merge(router);
 
     let mut api = base_open_api();
 
-    let router = if cfg!(debug_assertions) {
+    let router = if config.expose_docs {
+        if !base_path.is_empty() {
+            api.servers.push(Server {
+                url: base_path.to_string(),
+                ..Server::default()
+            });
+        }
         let router = router
             .api_route(
                 "/openapi.json",
                 get_with(serve_api, |op| op.summary("OpenAPI Spec").hidden(false)),
             )
-            .route("/docs", Scalar::new("/openapi.json").axum_route());
+            .route(
+                "/docs",
+                Scalar::new(format!("{base_path}/openapi.json")).axum_route(),
+            );
         router.finish_api(&mut api).layer(Extension(api))
     } else {
         router.into()
     };
+    let router = if base_path.is_empty() {
+        router
+    } else {
+        Router::new().nest(base_path, router)
+    };
+    // `RequestBodyLimitLayer` also bounds bodies read outside of extractors, like the ones
+    // buffered to check request signatures
+    let router = router
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(config.max_body_size));
     let router = NormalizePathLayer::trim_trailing_slash().layer(router);
+    // records the URI the client sent as `OriginalUri` before the path is normalized or the base
+    // path stripped, so that signatures are checked against it
+    let router = Router::new().fallback_service(router);
     let router = ServiceExt::<Request>::into_make_service(router);
 
-    let listener = TcpListener::bind(&format!("0.0.0.0:{}", 8080)).await?;
-    axum::serve(listener, router).await?;
+    match config.tls {
+        None => {
+            let listener = TcpListener::bind(config.bind).await?;
+            axum::serve(listener, router).await?;
+        }
+        Some(tls) => {
+            let rustls = RustlsConfig::from_pem_file(&tls.cert, &tls.key)
+                .await
+                .with_context(|| {
+                    format!(
+                        "couldn't load TLS certificate {} and key {}",
+                        tls.cert.display(),
+                        tls.key.display()
+                    )
+                })?;
+            axum_server::bind_rustls(config.bind, rustls)
+                .serve(router)
+                .await?;
+        }
+    }
     Ok(())
 }
 
 pub fn base_open_api() -> OpenApi {
-    OpenApi {
+    let mut api = OpenApi {
         info: Info {
             description: Some("API".to_string()),
             ..Info::default()
         },
         ..OpenApi::default()
-    }
+    };
+    auth::add_security_schemes(&mut api);
+    api
 }
 
 async fn serve_api(Extension(api): Extension<OpenApi>) -> impl IntoApiResponse {
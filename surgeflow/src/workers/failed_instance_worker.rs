@@ -1,58 +1,168 @@
+use std::sync::Arc;
+
 use adapter_types::{
+    dead_letters::{DeadLetterManager, Queue},
     dependencies::failed_instance_worker::FailedInstanceWorkerDependencies,
-    receivers::FailedInstanceReceiver,
+    health::Health,
+    managers::PersistenceManager,
+    notifications::{NotificationKind, NotificationSender},
+    receivers::{DeliveryHandle, FailedInstanceReceiver},
+};
+use surgeflow_types::{__WorkflowStatic, InstanceStatus, Project, WorkflowInstance};
+use tracing::Instrument;
+
+use super::{
+    failure_policy::{Settlement, record_error, settlement, with_backoff},
+    health::register_health_checks,
+    instrumentation::{received, record_instance_finished, stage_span},
+    notifications::notify,
 };
-use surgeflow_types::{Project, WorkflowInstance};
 
-async fn process<P: Project>(instance: WorkflowInstance<P>) -> anyhow::Result<()> {
+async fn process<P, PersistenceManagerT, NotificationSenderT>(
+    persistence_manager: &mut PersistenceManagerT,
+    notification_sender: Option<&NotificationSenderT>,
+    instance: WorkflowInstance<P>,
+) -> anyhow::Result<()>
+where
+    P: Project,
+    PersistenceManagerT: PersistenceManager<P>,
+    NotificationSenderT: NotificationSender<P>,
+{
     tracing::debug!("Failed instance: {:?}", instance);
 
+    with_backoff!(
+        "set instance status",
+        persistence_manager.set_instance_status(instance.external_id, InstanceStatus::Failed)
+    )?;
+    record_instance_finished(instance.workflow.name(), "failed");
+    notify(
+        "failed_instance_worker",
+        notification_sender,
+        instance.external_id,
+        NotificationKind::InstanceFailed,
+    )
+    .await;
+
     Ok(())
 }
 
-pub async fn main<P, FailedInstanceReceiverT>(
-    dependencies: FailedInstanceWorkerDependencies<P, FailedInstanceReceiverT>,
+pub async fn main<
+    P,
+    FailedInstanceReceiverT,
+    PersistenceManagerT,
+    DeadLetterManagerT,
+    NotificationSenderT,
+>(
+    dependencies: FailedInstanceWorkerDependencies<
+        P,
+        FailedInstanceReceiverT,
+        PersistenceManagerT,
+        DeadLetterManagerT,
+        NotificationSenderT,
+    >,
+    health: Arc<Health>,
 ) -> anyhow::Result<()>
 where
     P: Project,
     FailedInstanceReceiverT: FailedInstanceReceiver<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     let failed_instance_receiver = dependencies.failed_instance_receiver;
+    let persistence_manager = dependencies.persistence_manager;
+    let dead_letter_manager = dependencies.dead_letter_manager;
+    let notification_sender = dependencies.notification_sender;
+
+    let liveness = health.register_worker("failed_instance_worker");
+    register_health_checks!(
+        health,
+        "failed_instance_worker",
+        failed_instance_receiver,
+        persistence_manager
+    );
 
     loop {
-        if let Err(err) =
-            receive_and_process::<P, FailedInstanceReceiverT>(&failed_instance_receiver).await
+        liveness.beat();
+        if let Err(err) = receive_and_process::<
+            P,
+            FailedInstanceReceiverT,
+            PersistenceManagerT,
+            DeadLetterManagerT,
+            NotificationSenderT,
+        >(
+            &failed_instance_receiver,
+            &persistence_manager,
+            &dead_letter_manager,
+            notification_sender.as_ref(),
+        )
+        .await
         {
             tracing::error!("Error processing failed instance: {:?}", err);
         }
     }
 }
 
-async fn receive_and_process<P, FailedInstanceReceiverT>(
+async fn receive_and_process<
+    P,
+    FailedInstanceReceiverT,
+    PersistenceManagerT,
+    DeadLetterManagerT,
+    NotificationSenderT,
+>(
     failed_instance_receiver: &FailedInstanceReceiverT,
+    persistence_manager: &PersistenceManagerT,
+    dead_letter_manager: &DeadLetterManagerT,
+    notification_sender: Option<&NotificationSenderT>,
 ) -> anyhow::Result<()>
 where
     P: Project,
     FailedInstanceReceiverT: FailedInstanceReceiver<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     let mut failed_instance_receiver = failed_instance_receiver.clone();
 
     let (step, handle) = failed_instance_receiver.receive().await?;
+    let redelivery_count = handle.redelivery_count();
+    let in_flight = received("failed_instance_worker", &handle);
+    let span = stage_span("failed_instance_worker", step.trace_context.as_ref());
+    let persistence_manager = persistence_manager.clone();
+    let dead_letter_manager = dead_letter_manager.clone();
+    let notification_sender = notification_sender.cloned();
 
-    tokio::spawn(async move {
-        if let Err(err) = process::<P>(step).await {
-            tracing::error!("Error processing workflow instance: {:?}", err);
-        }
+    let task = async move {
+        let _in_flight = in_flight;
+        let result = process::<P, _, _>(
+            &mut persistence_manager.clone(),
+            notification_sender.as_ref(),
+            step.clone(),
+        )
+        .await;
 
-        tracing::debug!("acknowledging failed instance");
-        failed_instance_receiver
-            .accept(handle)
-            .await
-            .inspect_err(|e| {
-                tracing::error!("Failed to acknowledge failed instance: {:?}", e);
-            })
-            .unwrap();
-        tracing::debug!("acknowledged failed instance");
-    });
+        let settlement = settlement::<P, _, _>(
+            "failed_instance_worker",
+            Queue::FailedInstance,
+            &dead_letter_manager,
+            &step,
+            redelivery_count,
+            result,
+        )
+        .await;
+        let stage = settlement.stage();
+        tracing::debug!("settling failed instance ({stage})");
+        let settled = match settlement {
+            Settlement::Accept => failed_instance_receiver.accept(handle).await,
+            Settlement::Reject(delay) => failed_instance_receiver.reject(handle, Some(delay)).await,
+        };
+        if let Err(err) = settled {
+            tracing::error!("Failed to settle failed instance ({stage}): {:?}", err);
+            record_error("failed_instance_worker", stage);
+            return;
+        }
+        tracing::debug!("settled failed instance ({stage})");
+    };
+    tokio::spawn(task.instrument(span));
     Ok(())
 }
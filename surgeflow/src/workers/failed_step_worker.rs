@@ -1,38 +1,89 @@
+use std::sync::Arc;
+
 use adapter_types::{
-    dependencies::failed_step_worker::FailedStepWorkerDependencies, managers::PersistenceManager,
-    receivers::FailedStepReceiver, senders::FailedInstanceSender,
+    dead_letters::{DeadLetterManager, Queue},
+    dependencies::failed_step_worker::FailedStepWorkerDependencies,
+    health::Health,
+    managers::PersistenceManager,
+    notifications::{NotificationKind, NotificationSender},
+    outbox::{Outbox, OutboxMessage, StateChange},
+    receivers::{DeliveryHandle, FailedStepReceiver},
+    senders::FailedInstanceSender,
+    telemetry::current_trace_context,
 };
 use derive_more::Debug;
-use surgeflow_types::{FullyQualifiedStep, Project};
+use surgeflow_types::{__Step, FullyQualifiedStep, Project, StepStatus, WorkflowInstance};
+use tracing::Instrument;
+
+use super::{
+    failure_policy::{Settlement, WorkerError, record_error, settlement, with_backoff},
+    health::register_health_checks,
+    instrumentation::{received, stage_span},
+    notifications::notify,
+};
 
-pub async fn main<P, FailedStepReceiverT, FailedInstanceSenderT, PersistenceManagerT>(
+pub async fn main<
+    P,
+    FailedStepReceiverT,
+    FailedInstanceSenderT,
+    PersistenceManagerT,
+    DeadLetterManagerT,
+    OutboxT,
+    NotificationSenderT,
+>(
     dependencies: FailedStepWorkerDependencies<
         P,
         FailedStepReceiverT,
         FailedInstanceSenderT,
         PersistenceManagerT,
+        DeadLetterManagerT,
+        OutboxT,
+        NotificationSenderT,
     >,
+    health: Arc<Health>,
 ) -> anyhow::Result<()>
 where
     P: Project,
     FailedStepReceiverT: FailedStepReceiver<P>,
     FailedInstanceSenderT: FailedInstanceSender<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    OutboxT: Outbox<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     let failed_step_receiver = dependencies.failed_step_receiver;
     let failed_instance_sender = dependencies.failed_instance_sender;
     let persistence_manager = dependencies.persistence_manager;
+    let dead_letter_manager = dependencies.dead_letter_manager;
+    let outbox = dependencies.outbox;
+    let notification_sender = dependencies.notification_sender;
+
+    let liveness = health.register_worker("failed_step_worker");
+    register_health_checks!(
+        health,
+        "failed_step_worker",
+        failed_step_receiver,
+        failed_instance_sender,
+        persistence_manager
+    );
 
     loop {
+        liveness.beat();
         if let Err(err) = receive_and_process::<
             P,
             FailedStepReceiverT,
             FailedInstanceSenderT,
             PersistenceManagerT,
+            DeadLetterManagerT,
+            OutboxT,
+            NotificationSenderT,
         >(
             &failed_step_receiver,
             &failed_instance_sender,
             &persistence_manager,
+            &dead_letter_manager,
+            outbox.as_ref(),
+            notification_sender.as_ref(),
         )
         .await
         {
@@ -41,84 +92,179 @@ where
     }
 }
 
-async fn receive_and_process<P, FailedStepReceiverT, FailedInstanceSenderT, PersistenceManagerT>(
+async fn receive_and_process<
+    P,
+    FailedStepReceiverT,
+    FailedInstanceSenderT,
+    PersistenceManagerT,
+    DeadLetterManagerT,
+    OutboxT,
+    NotificationSenderT,
+>(
     failed_step_receiver: &FailedStepReceiverT,
     failed_instance_sender: &FailedInstanceSenderT,
     persistence_manager: &PersistenceManagerT,
+    dead_letter_manager: &DeadLetterManagerT,
+    outbox: Option<&OutboxT>,
+    notification_sender: Option<&NotificationSenderT>,
 ) -> anyhow::Result<()>
 where
     P: Project,
     FailedStepReceiverT: FailedStepReceiver<P>,
     FailedInstanceSenderT: FailedInstanceSender<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    OutboxT: Outbox<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     let mut failed_step_receiver = failed_step_receiver.clone();
 
     let (step, handle) = failed_step_receiver.receive().await?;
+    let redelivery_count = handle.redelivery_count();
+    let in_flight = received("failed_step_worker", &handle);
+    let span = stage_span("failed_step_worker", step.trace_context.as_ref());
     let failed_instance_sender = failed_instance_sender.clone();
     let persistence_manager = persistence_manager.clone();
+    let dead_letter_manager = dead_letter_manager.clone();
+    let outbox = outbox.cloned();
+    let notification_sender = notification_sender.cloned();
+
+    let task = async move {
+        let _in_flight = in_flight;
+        let result =
+            process::<P, FailedInstanceSenderT, PersistenceManagerT, OutboxT, NotificationSenderT>(
+                failed_instance_sender,
+                persistence_manager,
+                outbox.as_ref(),
+                notification_sender.as_ref(),
+                step.clone(),
+            )
+            .await;
 
-    tokio::spawn(async move {
-        if let Err(err) = process::<P, FailedInstanceSenderT, PersistenceManagerT>(
-            failed_instance_sender,
-            persistence_manager,
-            step,
+        let settlement = settlement::<P, _, _>(
+            "failed_step_worker",
+            Queue::FailedStep,
+            &dead_letter_manager,
+            &step,
+            redelivery_count,
+            result,
         )
-        .await
-        {
-            tracing::error!("Error processing failed step: {:?}", err);
+        .await;
+        let stage = settlement.stage();
+        tracing::debug!("settling failed step for instance ({stage})");
+        let settled = match settlement {
+            Settlement::Accept => failed_step_receiver.accept(handle).await,
+            Settlement::Reject(delay) => failed_step_receiver.reject(handle, Some(delay)).await,
+        };
+        if let Err(err) = settled {
+            tracing::error!(
+                "Failed to settle failed step for instance ({stage}): {:?}",
+                err
+            );
+            record_error("failed_step_worker", stage);
+            return;
         }
-
-        tracing::debug!("acknowledging failed step for instance");
-        failed_step_receiver
-            .accept(handle)
-            .await
-            .inspect_err(|e| {
-                tracing::error!("Failed to acknowledge failed step: {:?}", e);
-            })
-            .unwrap();
-        tracing::debug!("acknowledged failed step for instance");
-    });
+        tracing::debug!("settled failed step for instance ({stage})");
+    };
+    tokio::spawn(task.instrument(span));
     Ok(())
 }
 
 #[derive(thiserror::Error, Debug)]
-enum FailedStepWorkerError<P, FailedInstanceSenderT, PersistenceManagerT>
+#[allow(clippy::enum_variant_names)]
+enum FailedStepWorkerError<P, FailedInstanceSenderT, PersistenceManagerT, OutboxT>
 where
     P: Project,
     FailedInstanceSenderT: FailedInstanceSender<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    OutboxT: Outbox<P>,
 {
     #[error("Database error occurred")]
     PersistenceManagerError(#[source] PersistenceManagerT::Error),
     #[error("Failed to send instance: {0}")]
     SendError(#[source] FailedInstanceSenderT::Error),
+    #[error("Failed to commit to outbox")]
+    OutboxError(#[source] OutboxT::Error),
 }
 
-async fn process<P, FailedInstanceSenderT, PersistenceManagerT>(
+impl<P, FailedInstanceSenderT, PersistenceManagerT, OutboxT> WorkerError
+    for FailedStepWorkerError<P, FailedInstanceSenderT, PersistenceManagerT, OutboxT>
+where
+    P: Project,
+    FailedInstanceSenderT: FailedInstanceSender<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+    OutboxT: Outbox<P>,
+{
+}
+
+async fn process<P, FailedInstanceSenderT, PersistenceManagerT, OutboxT, NotificationSenderT>(
     failed_instance_sender: FailedInstanceSenderT,
     persistence_manager: PersistenceManagerT,
+    outbox: Option<&OutboxT>,
+    notification_sender: Option<&NotificationSenderT>,
     step: FullyQualifiedStep<P>,
-) -> Result<(), FailedStepWorkerError<P, FailedInstanceSenderT, PersistenceManagerT>>
+) -> Result<(), FailedStepWorkerError<P, FailedInstanceSenderT, PersistenceManagerT, OutboxT>>
 where
     P: Project,
     FailedInstanceSenderT: FailedInstanceSender<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    OutboxT: Outbox<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     tracing::debug!(
         "received failed step for instance: {}",
         step.instance.external_id
     );
+    let failed = NotificationKind::StepFailed {
+        step_id: step.step_id,
+        step_type: step.step.step.step_type().into_owned(),
+    };
 
-    persistence_manager
-        .set_step_status(step.step_id, 5)
-        .await
-        .map_err(FailedStepWorkerError::PersistenceManagerError)?;
+    if let Some(outbox) = outbox {
+        let changes = vec![StateChange::SetStepStatus {
+            step_id: step.step_id,
+            status: StepStatus::Failed,
+        }];
+        let messages = vec![OutboxMessage::FailedInstance(WorkflowInstance {
+            trace_context: current_trace_context(),
+            ..step.instance.clone()
+        })];
+        with_backoff!(
+            "commit to outbox",
+            outbox.commit(changes.clone(), messages.clone())
+        )
+        .map_err(FailedStepWorkerError::OutboxError)?;
+        notify(
+            "failed_step_worker",
+            notification_sender,
+            step.instance.external_id,
+            failed,
+        )
+        .await;
+        return Ok(());
+    }
 
-    failed_instance_sender
-        .send(step.instance)
-        .await
-        .map_err(FailedStepWorkerError::SendError)?;
+    with_backoff!(
+        "set step status",
+        persistence_manager.set_step_status(step.step_id, StepStatus::Failed)
+    )
+    .map_err(FailedStepWorkerError::PersistenceManagerError)?;
+
+    with_backoff!(
+        "send failed instance",
+        failed_instance_sender.send(WorkflowInstance {
+            trace_context: current_trace_context(),
+            ..step.instance.clone()
+        })
+    )
+    .map_err(FailedStepWorkerError::SendError)?;
+    notify(
+        "failed_step_worker",
+        notification_sender,
+        step.instance.external_id,
+        failed,
+    )
+    .await;
 
     Ok(())
 }
@@ -0,0 +1,361 @@
+//! Shared failure handling for the queue workers: infrastructure calls are retried with
+//! backoff, messages whose processing failed are handed back to the queue (or dead-lettered
+//! once they keep failing), and every failure is counted instead of panicking the spawned
+//! task.
+//!
+//! The limits and delays below are fixed, not configurable: an infrastructure call is tried
+//! [`MAX_ATTEMPTS`] times over about 1.5 seconds, and a message is delivered
+//! [`MAX_DELIVERIES`] times, the redeliveries 1, 2, 4 and 8 seconds apart, before it is
+//! dead-lettered.
+// the reaper and the outbox relay don't receive messages, so they settle none
+#![cfg_attr(
+    not(any(
+        feature = "active_step_worker",
+        feature = "new_instance_worker",
+        feature = "next_step_worker",
+        feature = "new_event_worker",
+        feature = "completed_step_worker",
+        feature = "failed_step_worker",
+        feature = "failed_instance_worker",
+        feature = "completed_instance_worker",
+    )),
+    allow(dead_code)
+)]
+
+use std::{fmt, time::Duration};
+
+use adapter_types::dead_letters::{DeadLetterManager, Queue};
+use serde::Serialize;
+use surgeflow_types::Project;
+
+/// Attempts of an infrastructure call before [`with_backoff!`] gives up.
+pub(crate) const MAX_ATTEMPTS: u32 = 5;
+/// Deliveries after which a message that still fails is moved to its dead-letter queue.
+const MAX_DELIVERIES: u32 = 5;
+/// Delay before the second attempt of an infrastructure call, doubling with each one after.
+const BASE_DELAY: Duration = Duration::from_millis(100);
+const MAX_DELAY: Duration = Duration::from_secs(5);
+/// Delay before the first redelivery of a rejected message, doubling with each one after.
+const BASE_REDELIVERY_DELAY: Duration = Duration::from_secs(1);
+const MAX_REDELIVERY_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// Retries an infrastructure call (persistence, queue send, ...) with exponential backoff.
+/// The call expression is re-evaluated on every attempt; the last error is returned once
+/// `MAX_ATTEMPTS` is reached.
+macro_rules! with_backoff {
+    ($operation:expr, $call:expr) => {{
+        let mut attempt = 1;
+        loop {
+            match $call.await {
+                Ok(value) => break Ok(value),
+                Err(err) if attempt < $crate::workers::failure_policy::MAX_ATTEMPTS => {
+                    $crate::workers::failure_policy::backoff($operation, attempt, &err).await;
+                    attempt += 1;
+                }
+                Err(err) => break Err(err),
+            }
+        }
+    }};
+}
+pub(crate) use with_backoff;
+
+pub(crate) async fn backoff(operation: &'static str, attempt: u32, err: &impl fmt::Display) {
+    let delay = BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt - 1))
+        .min(MAX_DELAY);
+    tracing::warn!(
+        "{operation} failed (attempt {attempt}/{MAX_ATTEMPTS}), retrying in {delay:?}: {err}"
+    );
+    metrics::counter!("surgeflow_infrastructure_retries_total", "operation" => operation)
+        .increment(1);
+    tokio::time::sleep(delay).await;
+}
+
+/// `stage` is one of `process`, `dead_letter`, `accept` or `reject`.
+pub(crate) fn record_error(worker: &'static str, stage: &'static str) {
+    metrics::counter!("surgeflow_worker_errors_total", "worker" => worker, "stage" => stage)
+        .increment(1);
+}
+
+/// Delay before a rejected message is delivered again, growing with each redelivery so a
+/// persistent infrastructure outage doesn't turn into a hot loop.
+pub(crate) fn redelivery_delay(redelivery_count: u32) -> Duration {
+    BASE_REDELIVERY_DELAY
+        .saturating_mul(2u32.saturating_pow(redelivery_count))
+        .min(MAX_REDELIVERY_DELAY)
+}
+
+pub(crate) trait WorkerError: fmt::Debug {
+    /// Infrastructure failures are worth redelivering, a malformed message is not.
+    fn is_transient(&self) -> bool {
+        true
+    }
+}
+
+impl WorkerError for anyhow::Error {}
+
+pub(crate) enum Settlement {
+    Accept,
+    Reject(Duration),
+}
+
+impl Settlement {
+    pub(crate) fn stage(&self) -> &'static str {
+        match self {
+            Settlement::Accept => "accept",
+            Settlement::Reject(_) => "reject",
+        }
+    }
+}
+
+/// Decides how a received message is settled given the outcome of processing it.
+/// Messages that failed permanently, or too many times, are dead-lettered and then accepted;
+/// if dead-lettering itself fails the message is rejected so it isn't lost.
+pub(crate) async fn settlement<P, DeadLetterManagerT, M>(
+    worker: &'static str,
+    queue: Queue,
+    dead_letter_manager: &DeadLetterManagerT,
+    message: &M,
+    redelivery_count: u32,
+    result: Result<(), impl WorkerError>,
+) -> Settlement
+where
+    P: Project,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    M: Serialize,
+{
+    let err = match result {
+        Ok(()) => return Settlement::Accept,
+        Err(err) => err,
+    };
+    record_error(worker, "process");
+
+    let delivery_count = redelivery_count + 1;
+    if err.is_transient() && delivery_count < MAX_DELIVERIES {
+        tracing::error!("{worker} failed to process message, rejecting: {:?}", err);
+        return Settlement::Reject(redelivery_delay(redelivery_count));
+    }
+
+    tracing::error!(
+        "{worker} failed to process message, dead-lettering: {:?}",
+        err
+    );
+    let dead_lettered = match serde_json::to_value(message) {
+        Ok(payload) => match dead_letter_manager
+            .dead_letter(queue, payload, format!("{err:?}"), delivery_count)
+            .await
+        {
+            Ok(id) => {
+                tracing::warn!("dead-lettered message {id} from {queue} queue");
+                true
+            }
+            Err(e) => {
+                tracing::error!("Failed to dead-letter message: {:?}", e);
+                false
+            }
+        },
+        Err(e) => {
+            tracing::error!("Failed to serialize message for dead-lettering: {:?}", e);
+            false
+        }
+    };
+    if dead_lettered {
+        Settlement::Accept
+    } else {
+        record_error(worker, "dead_letter");
+        Settlement::Reject(redelivery_delay(redelivery_count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        sync::{Arc, Mutex},
+    };
+
+    use adapter_types::dead_letters::{DeadLetter, DeadLetterId};
+    use surgeflow_types::testing::TestProject;
+
+    use super::*;
+
+    /// Records what it dead-letters, or fails to.
+    #[derive(Clone, Default)]
+    struct DeadLetters {
+        fail: bool,
+        dead_lettered: Arc<Mutex<Vec<(Queue, u32)>>>,
+    }
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("dead-letter queue unavailable")]
+    struct Unavailable;
+
+    impl DeadLetterManager<TestProject> for DeadLetters {
+        type Error = Unavailable;
+
+        async fn dead_letter(
+            &self,
+            queue: Queue,
+            _: serde_json::Value,
+            _: String,
+            delivery_count: u32,
+        ) -> Result<DeadLetterId, Unavailable> {
+            if self.fail {
+                return Err(Unavailable);
+            }
+            self.dead_lettered
+                .lock()
+                .unwrap()
+                .push((queue, delivery_count));
+            Ok(DeadLetterId::new())
+        }
+
+        async fn list(
+            &self,
+            _: Queue,
+            _: Option<DeadLetterId>,
+            _: u32,
+        ) -> Result<Vec<DeadLetter>, Unavailable> {
+            Ok(Vec::new())
+        }
+
+        async fn get(&self, _: Queue, _: DeadLetterId) -> Result<Option<DeadLetter>, Unavailable> {
+            Ok(None)
+        }
+
+        async fn delete(&self, _: Queue, _: DeadLetterId) -> Result<bool, Unavailable> {
+            Ok(false)
+        }
+
+        async fn redrive(&self, _: Queue, _: DeadLetterId) -> Result<bool, Unavailable> {
+            Ok(false)
+        }
+    }
+
+    #[derive(Debug)]
+    struct Failure {
+        transient: bool,
+    }
+
+    impl WorkerError for Failure {
+        fn is_transient(&self) -> bool {
+            self.transient
+        }
+    }
+
+    async fn settle(
+        dead_letters: &DeadLetters,
+        redelivery_count: u32,
+        result: Result<(), Failure>,
+    ) -> Settlement {
+        settlement::<TestProject, _, _>(
+            "test_worker",
+            Queue::NextStep,
+            dead_letters,
+            &"message",
+            redelivery_count,
+            result,
+        )
+        .await
+    }
+
+    #[test]
+    fn redelivery_delay_doubles_up_to_the_maximum() {
+        assert_eq!(redelivery_delay(0), BASE_REDELIVERY_DELAY);
+        assert_eq!(redelivery_delay(1), BASE_REDELIVERY_DELAY * 2);
+        assert_eq!(redelivery_delay(3), BASE_REDELIVERY_DELAY * 8);
+        assert_eq!(redelivery_delay(20), MAX_REDELIVERY_DELAY);
+        assert_eq!(redelivery_delay(u32::MAX), MAX_REDELIVERY_DELAY);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn with_backoff_retries_until_the_call_succeeds() {
+        let mut calls = 0;
+        let result: Result<u32, &str> = with_backoff!("test", async {
+            calls += 1;
+            if calls < 3 {
+                Err("unavailable")
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result, Ok(3));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn with_backoff_gives_up_after_max_attempts() {
+        let started = tokio::time::Instant::now();
+        let mut calls = 0;
+        let result: Result<Infallible, &str> = with_backoff!("test", async {
+            calls += 1;
+            Err("unavailable")
+        });
+        assert_eq!(result, Err("unavailable"));
+        assert_eq!(calls, MAX_ATTEMPTS);
+        // 100ms + 200ms + 400ms + 800ms between the five attempts
+        assert_eq!(started.elapsed(), Duration::from_millis(1500));
+    }
+
+    #[tokio::test]
+    async fn processed_messages_are_accepted() {
+        let dead_letters = DeadLetters::default();
+        let settlement = settle(&dead_letters, 0, Ok(())).await;
+        assert!(matches!(settlement, Settlement::Accept));
+        assert!(dead_letters.dead_lettered.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_rejected_with_a_growing_delay() {
+        let dead_letters = DeadLetters::default();
+        for redelivery_count in 0..MAX_DELIVERIES - 1 {
+            let settlement = settle(
+                &dead_letters,
+                redelivery_count,
+                Err(Failure { transient: true }),
+            )
+            .await;
+            assert!(matches!(
+                settlement,
+                Settlement::Reject(delay) if delay == redelivery_delay(redelivery_count)
+            ));
+        }
+        assert!(dead_letters.dead_lettered.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn the_last_delivery_is_dead_lettered() {
+        let dead_letters = DeadLetters::default();
+        let settlement = settle(
+            &dead_letters,
+            MAX_DELIVERIES - 1,
+            Err(Failure { transient: true }),
+        )
+        .await;
+        assert!(matches!(settlement, Settlement::Accept));
+        assert_eq!(
+            *dead_letters.dead_lettered.lock().unwrap(),
+            [(Queue::NextStep, MAX_DELIVERIES)]
+        );
+    }
+
+    #[tokio::test]
+    async fn permanent_failures_are_dead_lettered_right_away() {
+        let dead_letters = DeadLetters::default();
+        let settlement = settle(&dead_letters, 0, Err(Failure { transient: false })).await;
+        assert!(matches!(settlement, Settlement::Accept));
+        assert_eq!(
+            *dead_letters.dead_lettered.lock().unwrap(),
+            [(Queue::NextStep, 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn messages_that_cant_be_dead_lettered_are_rejected() {
+        let dead_letters = DeadLetters {
+            fail: true,
+            ..DeadLetters::default()
+        };
+        let settlement = settle(&dead_letters, 0, Err(Failure { transient: false })).await;
+        assert!(matches!(settlement, Settlement::Reject(_)));
+    }
+}
@@ -0,0 +1,16 @@
+/// Registers the health check of each dependency with a [`Health`](adapter_types::health::Health),
+/// named after its variable.
+macro_rules! register_health_checks {
+    ($health:expr, $worker:literal, $($dependency:ident),+ $(,)?) => {
+        $(
+            $health.register_check(
+                $worker,
+                stringify!($dependency),
+                $dependency.clone(),
+                |dependency| async move { dependency.health_check().await },
+            );
+        )+
+    };
+}
+
+pub(crate) use register_health_checks;
@@ -0,0 +1,138 @@
+//! Prometheus metrics of the workers, exposed by the control server on `/metrics`. Every worker
+//! records the messages it receives and processes; the step and instance metrics are labelled
+//! with the workflow name.
+//!
+//! Every message is also processed in a span, a child of the span that sent it, so an
+//! instance's journey through the workers shows up as one trace.
+
+use std::time::{Duration, Instant};
+
+use adapter_types::{receivers::DeliveryHandle, telemetry::set_parent};
+use chrono::Utc;
+use surgeflow_types::{StepId, TraceContext};
+use tracing::{Span, field::Empty};
+
+use super::process_registry::{in_flight_finished, in_flight_started};
+
+/// The span `worker` processes a message in, continuing the trace of the message's sender.
+pub(crate) fn stage_span(worker: &'static str, trace_context: Option<&TraceContext>) -> Span {
+    let span = tracing::info_span!(
+        "worker_stage",
+        otel.name = worker,
+        otel.kind = "consumer",
+        worker,
+    );
+    set_parent(&span, trace_context);
+    span
+}
+
+/// The span a step's `run` executes in. [`record_step_error`] marks it failed.
+#[cfg_attr(not(feature = "active_step_worker"), allow(dead_code))]
+pub(crate) fn step_run_span(
+    workflow: &'static str,
+    step_type: &str,
+    step_id: StepId,
+    attempt: u32,
+) -> Span {
+    tracing::info_span!(
+        "step_run",
+        otel.name = format!("{workflow} {step_type}"),
+        otel.status_code = Empty,
+        otel.status_description = Empty,
+        workflow,
+        step_type,
+        step_id = ?step_id,
+        attempt,
+    )
+}
+
+#[cfg_attr(not(feature = "active_step_worker"), allow(dead_code))]
+pub(crate) fn record_step_error(span: &Span, error: &str) {
+    span.record("otel.status_code", "ERROR");
+    span.record("otel.status_description", error);
+}
+
+/// Counts a received message and how long it waited in its queue, and tracks it as in flight,
+/// in the metrics and in the process's heartbeats, until the returned guard is dropped.
+pub(crate) fn received(worker: &'static str, handle: &impl DeliveryHandle) -> InFlight {
+    metrics::counter!("surgeflow_messages_received_total", "worker" => worker).increment(1);
+    if let Some(enqueued_at) = handle.enqueued_at() {
+        let lag = (Utc::now() - enqueued_at).to_std().unwrap_or_default();
+        metrics::histogram!("surgeflow_queue_lag_seconds", "worker" => worker)
+            .record(lag.as_secs_f64());
+    }
+    metrics::gauge!("surgeflow_tasks_in_flight", "worker" => worker).increment(1.0);
+    in_flight_started(worker);
+    InFlight {
+        worker,
+        started: Instant::now(),
+    }
+}
+
+/// A message being processed. Dropping it, including when the task processing it panics,
+/// records the processing time.
+pub(crate) struct InFlight {
+    worker: &'static str,
+    started: Instant,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        metrics::gauge!("surgeflow_tasks_in_flight", "worker" => self.worker).decrement(1.0);
+        in_flight_finished(self.worker);
+        metrics::histogram!("surgeflow_message_processing_seconds", "worker" => self.worker)
+            .record(self.started.elapsed().as_secs_f64());
+    }
+}
+
+/// `outcome` is `completed` or `failed`.
+#[cfg_attr(not(feature = "active_step_worker"), allow(dead_code))]
+pub(crate) fn record_step_run(
+    workflow: &'static str,
+    step_type: &str,
+    outcome: &'static str,
+    duration: Duration,
+) {
+    metrics::histogram!(
+        "surgeflow_step_run_seconds",
+        "workflow" => workflow,
+        "step_type" => step_type.to_owned(),
+        "outcome" => outcome
+    )
+    .record(duration.as_secs_f64());
+}
+
+/// Counts a failed attempt, as a retry if the step runs again or as a failure if it ran out of
+/// retries.
+#[cfg_attr(not(feature = "active_step_worker"), allow(dead_code))]
+pub(crate) fn record_step_attempt_failed(workflow: &'static str, step_type: &str, retrying: bool) {
+    let name = if retrying {
+        "surgeflow_step_retries_total"
+    } else {
+        "surgeflow_step_failures_total"
+    };
+    metrics::counter!(name, "workflow" => workflow, "step_type" => step_type.to_owned())
+        .increment(1);
+}
+
+#[cfg_attr(not(feature = "new_instance_worker"), allow(dead_code))]
+pub(crate) fn record_instance_started(workflow: &'static str) {
+    metrics::counter!("surgeflow_instances_started_total", "workflow" => workflow).increment(1);
+}
+
+/// `status` is `completed` or `failed`.
+#[cfg_attr(
+    not(any(
+        feature = "completed_instance_worker",
+        feature = "failed_instance_worker"
+    )),
+    allow(dead_code)
+)]
+pub(crate) fn record_instance_finished(workflow: &'static str, status: &'static str) {
+    metrics::counter!(
+        "surgeflow_instances_finished_total",
+        "workflow" => workflow,
+        "status" => status
+    )
+    .increment(1);
+}
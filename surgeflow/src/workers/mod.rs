@@ -21,3 +21,63 @@ pub mod new_event_worker;
 pub mod new_instance_worker;
 #[cfg(feature = "next_step_worker")]
 pub mod next_step_worker;
+#[cfg(feature = "outbox_relay_worker")]
+pub mod outbox_relay_worker;
+#[cfg(feature = "reaper_worker")]
+pub mod reaper_worker;
+
+#[cfg(any(feature = "active_step_worker", feature = "reaper_worker"))]
+pub(crate) mod attempts;
+
+pub(crate) mod probes;
+pub(crate) mod process_registry;
+
+#[cfg(any(
+    feature = "active_step_worker",
+    feature = "next_step_worker",
+    feature = "completed_step_worker",
+    feature = "failed_step_worker",
+    feature = "failed_instance_worker",
+    feature = "completed_instance_worker",
+))]
+pub(crate) mod notifications;
+
+#[cfg(any(
+    feature = "active_step_worker",
+    feature = "new_instance_worker",
+    feature = "next_step_worker",
+    feature = "new_event_worker",
+    feature = "completed_step_worker",
+    feature = "failed_step_worker",
+    feature = "failed_instance_worker",
+    feature = "completed_instance_worker",
+    feature = "reaper_worker",
+    feature = "outbox_relay_worker",
+))]
+pub(crate) mod failure_policy;
+
+#[cfg(any(
+    feature = "active_step_worker",
+    feature = "new_instance_worker",
+    feature = "next_step_worker",
+    feature = "new_event_worker",
+    feature = "completed_step_worker",
+    feature = "failed_step_worker",
+    feature = "failed_instance_worker",
+    feature = "completed_instance_worker",
+    feature = "reaper_worker",
+    feature = "outbox_relay_worker",
+))]
+pub(crate) mod health;
+
+#[cfg(any(
+    feature = "active_step_worker",
+    feature = "new_instance_worker",
+    feature = "next_step_worker",
+    feature = "new_event_worker",
+    feature = "completed_step_worker",
+    feature = "failed_step_worker",
+    feature = "failed_instance_worker",
+    feature = "completed_instance_worker",
+))]
+pub(crate) mod instrumentation;
@@ -1,39 +1,76 @@
-use std::sync::WaitTimeoutResult;
+use std::sync::Arc;
 
 use adapter_types::{
+    dead_letters::{DeadLetterManager, Queue},
     dependencies::new_event_worker::NewEventWorkerDependencies,
-    managers::StepsAwaitingEventManager, receivers::EventReceiver, senders::ActiveStepSender,
+    health::Health,
+    managers::StepsAwaitingEventManager,
+    receivers::{DeliveryHandle, EventReceiver},
+    senders::ActiveStepSender,
+    telemetry::current_trace_context,
 };
-use surgeflow_types::{
-    __Event, __Step, __Workflow, FullyQualifiedStep, InstanceEvent, Project, RawStep, Workflow,
+use derive_more::Debug;
+use surgeflow_types::{__Step, FullyQualifiedStep, InstanceEvent, Project, RawStep};
+use tracing::Instrument;
+
+use super::{
+    failure_policy::{Settlement, WorkerError, record_error, settlement, with_backoff},
+    health::register_health_checks,
+    instrumentation::{received, stage_span},
 };
 
-pub async fn main<P, ActiveStepSenderT, EventReceiverT, StepsAwaitingEventManagerT>(
+pub async fn main<
+    P,
+    ActiveStepSenderT,
+    EventReceiverT,
+    StepsAwaitingEventManagerT,
+    DeadLetterManagerT,
+>(
     dependencies: NewEventWorkerDependencies<
         P,
         ActiveStepSenderT,
         EventReceiverT,
         StepsAwaitingEventManagerT,
+        DeadLetterManagerT,
     >,
+    health: Arc<Health>,
 ) -> anyhow::Result<()>
 where
     P: Project,
     ActiveStepSenderT: ActiveStepSender<P>,
     EventReceiverT: EventReceiver<P>,
     StepsAwaitingEventManagerT: StepsAwaitingEventManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
 {
     let active_step_sender = dependencies.active_step_sender;
     let event_receiver = dependencies.event_receiver;
     let steps_awaiting_event = dependencies.steps_awaiting_event_manager;
+    let dead_letter_manager = dependencies.dead_letter_manager;
+
+    let liveness = health.register_worker("new_event_worker");
+    register_health_checks!(
+        health,
+        "new_event_worker",
+        active_step_sender,
+        event_receiver,
+        steps_awaiting_event
+    );
 
     loop {
+        liveness.beat();
         tracing::info!("Waiting for new event...");
         if let Err(err) = receive_and_process::<
             P,
             ActiveStepSenderT,
             EventReceiverT,
             StepsAwaitingEventManagerT,
-        >(&active_step_sender, &event_receiver, &steps_awaiting_event)
+            DeadLetterManagerT,
+        >(
+            &active_step_sender,
+            &event_receiver,
+            &steps_awaiting_event,
+            &dead_letter_manager,
+        )
         .await
         {
             tracing::error!("Error processing new event: {:?}", err);
@@ -41,79 +78,136 @@ where
     }
 }
 
-async fn receive_and_process<P, ActiveStepSenderT, EventReceiverT, StepsAwaitingEventManagerT>(
+async fn receive_and_process<
+    P,
+    ActiveStepSenderT,
+    EventReceiverT,
+    StepsAwaitingEventManagerT,
+    DeadLetterManagerT,
+>(
     active_step_sender: &ActiveStepSenderT,
     event_receiver: &EventReceiverT,
     steps_awaiting_event: &StepsAwaitingEventManagerT,
+    dead_letter_manager: &DeadLetterManagerT,
 ) -> anyhow::Result<()>
 where
     P: Project,
     ActiveStepSenderT: ActiveStepSender<P>,
     EventReceiverT: EventReceiver<P>,
     StepsAwaitingEventManagerT: StepsAwaitingEventManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
 {
     let mut event_receiver = event_receiver.clone();
 
     let (instance_event, handle) = event_receiver.receive().await?;
+    let redelivery_count = handle.redelivery_count();
+    let in_flight = received("new_event_worker", &handle);
+    let span = stage_span("new_event_worker", instance_event.trace_context.as_ref());
     let active_step_sender = active_step_sender.clone();
     let steps_awaiting_event = steps_awaiting_event.clone();
+    let dead_letter_manager = dead_letter_manager.clone();
 
-    tokio::spawn(async move {
-        if let Err(err) = process::<P, ActiveStepSenderT, StepsAwaitingEventManagerT>(
-            instance_event,
+    let task = async move {
+        let _in_flight = in_flight;
+        let result = process::<P, ActiveStepSenderT, StepsAwaitingEventManagerT>(
+            instance_event.clone(),
             &mut active_step_sender.clone(),
             &mut steps_awaiting_event.clone(),
         )
-        .await
-        {
-            tracing::error!("Error processing new event: {:?}", err);
-        }
+        .await;
 
-        tracing::debug!("acknowledging new event");
-        event_receiver
-            .accept(handle)
-            .await
-            .inspect_err(|e| {
-                tracing::error!("Failed to acknowledge new event: {:?}", e);
-            })
-            .unwrap();
-        tracing::debug!("acknowledged new event");
-    });
+        let settlement = settlement::<P, _, _>(
+            "new_event_worker",
+            Queue::Event,
+            &dead_letter_manager,
+            &instance_event,
+            redelivery_count,
+            result,
+        )
+        .await;
+        let stage = settlement.stage();
+        tracing::debug!("settling new event ({stage})");
+        let settled = match settlement {
+            Settlement::Accept => event_receiver.accept(handle).await,
+            Settlement::Reject(delay) => event_receiver.reject(handle, Some(delay)).await,
+        };
+        if let Err(err) = settled {
+            tracing::error!("Failed to settle new event ({stage}): {:?}", err);
+            record_error("new_event_worker", stage);
+            return;
+        }
+        tracing::debug!("settled new event ({stage})");
+    };
+    tokio::spawn(task.instrument(span));
     Ok(())
 }
 
+#[derive(thiserror::Error, Debug)]
+enum NewEventWorkerError<P, ActiveStepSenderT, StepsAwaitingEventManagerT>
+where
+    P: Project,
+    ActiveStepSenderT: ActiveStepSender<P>,
+    StepsAwaitingEventManagerT: StepsAwaitingEventManager<P>,
+{
+    #[error("Failed to access steps awaiting event")]
+    AwaitEventError(#[source] StepsAwaitingEventManagerT::Error),
+    #[error("Failed to send active step")]
+    SendActiveStepError(#[source] ActiveStepSenderT::Error),
+}
+
+impl<P, ActiveStepSenderT, StepsAwaitingEventManagerT> WorkerError
+    for NewEventWorkerError<P, ActiveStepSenderT, StepsAwaitingEventManagerT>
+where
+    P: Project,
+    ActiveStepSenderT: ActiveStepSender<P>,
+    StepsAwaitingEventManagerT: StepsAwaitingEventManager<P>,
+{
+}
+
 async fn process<P, ActiveStepSenderT, StepsAwaitingEventManagerT>(
-    InstanceEvent { event, instance_id }: InstanceEvent<P>,
+    InstanceEvent {
+        event, instance_id, ..
+    }: InstanceEvent<P>,
     active_step_sender: &mut ActiveStepSenderT,
     steps_awaiting_event: &mut StepsAwaitingEventManagerT,
-) -> anyhow::Result<()>
+) -> Result<(), NewEventWorkerError<P, ActiveStepSenderT, StepsAwaitingEventManagerT>>
 where
     P: Project,
     ActiveStepSenderT: ActiveStepSender<P>,
     StepsAwaitingEventManagerT: StepsAwaitingEventManager<P>,
 {
-    let step = steps_awaiting_event.get_step(instance_id).await?;
+    let step = with_backoff!(
+        "get step awaiting event",
+        steps_awaiting_event.get_step(instance_id)
+    )
+    .map_err(NewEventWorkerError::AwaitEventError)?;
 
     let Some(step) = step else {
         tracing::debug!("No step awaiting event for instance {}", instance_id);
         return Ok(());
     };
 
-    if step.step.step.event_is_event(&event) {
-        steps_awaiting_event.delete_step(instance_id).await?;
-    } else {
+    if !step.step.step.event_is_event(&event) {
         return Ok(());
     }
     let raw_step = RawStep {
         event: Some(event),
         ..step.step
     };
-    active_step_sender
-        .send(FullyQualifiedStep {
-            step: raw_step,
-            ..step
-        })
-        .await?;
+    let step = FullyQualifiedStep {
+        step: raw_step,
+        trace_context: current_trace_context(),
+        ..step
+    };
+    // only stop awaiting once the step is on its way: a redelivery after a failed send must still
+    // find it, while a second send is deduplicated when the attempt starts
+    with_backoff!("send active step", active_step_sender.send(step.clone()))
+        .map_err(NewEventWorkerError::SendActiveStepError)?;
+    with_backoff!(
+        "delete step awaiting event",
+        steps_awaiting_event.delete_step(instance_id, step.step_id)
+    )
+    .map_err(NewEventWorkerError::AwaitEventError)?;
 
     Ok(())
 }
@@ -1,68 +1,160 @@
+use std::sync::Arc;
+
 use adapter_types::{
-    dependencies::new_instance_worker::NewInstanceWorkerDependencies, managers::PersistenceManager,
-    receivers::NewInstanceReceiver, senders::NextStepSender,
+    dead_letters::{DeadLetterManager, Queue},
+    dependencies::new_instance_worker::NewInstanceWorkerDependencies,
+    health::Health,
+    managers::PersistenceManager,
+    outbox::{Outbox, OutboxMessage, StateChange},
+    receivers::{DeliveryHandle, NewInstanceReceiver},
+    senders::NextStepSender,
+    telemetry::current_trace_context,
 };
+use derive_more::Debug;
 use surgeflow_types::__WorkflowStatic;
 use surgeflow_types::{FullyQualifiedStep, Project, StepId, WorkflowInstance};
+use tracing::Instrument;
+
+use super::{
+    failure_policy::{Settlement, WorkerError, record_error, settlement, with_backoff},
+    health::register_health_checks,
+    instrumentation::{received, record_instance_started, stage_span},
+};
+
+#[derive(thiserror::Error, Debug)]
+#[allow(clippy::enum_variant_names)]
+enum NewInstanceWorkerError<P, NextStepSenderT, PersistenceManagerT, OutboxT>
+where
+    P: Project,
+    NextStepSenderT: NextStepSender<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+    OutboxT: Outbox<P>,
+{
+    #[error("Database error occurred")]
+    DatabaseError(#[source] PersistenceManagerT::Error),
+    #[error("Failed to send next step")]
+    SendNextStepError(#[source] NextStepSenderT::Error),
+    #[error("Failed to commit to outbox")]
+    OutboxError(#[source] OutboxT::Error),
+}
+
+impl<P, NextStepSenderT, PersistenceManagerT, OutboxT> WorkerError
+    for NewInstanceWorkerError<P, NextStepSenderT, PersistenceManagerT, OutboxT>
+where
+    P: Project,
+    NextStepSenderT: NextStepSender<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+    OutboxT: Outbox<P>,
+{
+}
 
-async fn process<P, NextStepSenderT, PersistenceManagerT>(
+async fn process<P, NextStepSenderT, PersistenceManagerT, OutboxT>(
     next_step_sender: &mut NextStepSenderT,
     persistence_manager: &mut PersistenceManagerT,
+    outbox: Option<&OutboxT>,
     instance: WorkflowInstance<P>,
-) -> anyhow::Result<()>
+) -> Result<(), NewInstanceWorkerError<P, NextStepSenderT, PersistenceManagerT, OutboxT>>
 where
     P: Project,
     NextStepSenderT: NextStepSender<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    OutboxT: Outbox<P>,
 {
-    let entrypoint = instance.workflow.entrypoint();
-
-    persistence_manager
-        .insert_instance(instance.clone())
-        .await
-        .expect("TODO: handle error inserting instance");
-
     let entrypoint = FullyQualifiedStep {
-        instance,
-        step: entrypoint,
+        step_id: StepId::entrypoint_of(instance.external_id),
+        step: instance.workflow.entrypoint(),
+        instance: instance.clone(),
         retry_count: 0,
-        step_id: StepId::new(),
-        
+
         previous_step_id: None,
         next_step: None,
+        trace_context: current_trace_context(),
     };
 
-    next_step_sender.send(entrypoint).await?;
+    let workflow = instance.workflow.name();
+    if let Some(outbox) = outbox {
+        let changes = vec![StateChange::InsertInstance(instance)];
+        let messages = vec![OutboxMessage::NextStep(entrypoint)];
+        with_backoff!(
+            "commit to outbox",
+            outbox.commit(changes.clone(), messages.clone())
+        )
+        .map_err(NewInstanceWorkerError::OutboxError)?;
+        record_instance_started(workflow);
+        return Ok(());
+    }
+
+    with_backoff!(
+        "insert instance",
+        persistence_manager.insert_instance(instance.clone())
+    )
+    .map_err(NewInstanceWorkerError::DatabaseError)?;
+
+    with_backoff!("send next step", next_step_sender.send(entrypoint.clone()))
+        .map_err(NewInstanceWorkerError::SendNextStepError)?;
+    record_instance_started(workflow);
 
     Ok(())
 }
 
-pub async fn main<P, NextStepSenderT, NewInstanceReceiverT, PersistenceManagerT>(
+pub async fn main<
+    P,
+    NextStepSenderT,
+    NewInstanceReceiverT,
+    PersistenceManagerT,
+    DeadLetterManagerT,
+    OutboxT,
+>(
     dependencies: NewInstanceWorkerDependencies<
         P,
         NextStepSenderT,
         NewInstanceReceiverT,
         PersistenceManagerT,
+        DeadLetterManagerT,
+        OutboxT,
     >,
+    health: Arc<Health>,
 ) -> anyhow::Result<()>
 where
     P: Project,
     NextStepSenderT: NextStepSender<P>,
     NewInstanceReceiverT: NewInstanceReceiver<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    OutboxT: Outbox<P>,
 {
     let instance_receiver = dependencies.new_instance_receiver;
     let next_step_sender = dependencies.next_step_sender;
     let persistence_manager = dependencies.persistence_manager;
+    let dead_letter_manager = dependencies.dead_letter_manager;
+    let outbox = dependencies.outbox;
+
+    let liveness = health.register_worker("new_instance_worker");
+    register_health_checks!(
+        health,
+        "new_instance_worker",
+        instance_receiver,
+        next_step_sender,
+        persistence_manager
+    );
 
     loop {
+        liveness.beat();
         tracing::info!("Waiting for new instance...");
         if let Err(err) = receive_and_process::<
             P,
             NextStepSenderT,
             NewInstanceReceiverT,
             PersistenceManagerT,
-        >(&instance_receiver, &next_step_sender, &persistence_manager)
+            DeadLetterManagerT,
+            OutboxT,
+        >(
+            &instance_receiver,
+            &next_step_sender,
+            &persistence_manager,
+            &dead_letter_manager,
+            outbox.as_ref(),
+        )
         .await
         {
             tracing::error!("Error processing new instance: {:?}", err);
@@ -70,43 +162,71 @@ where
     }
 }
 
-async fn receive_and_process<P, NextStepSenderT, NewInstanceReceiverT, PersistenceManagerT>(
+async fn receive_and_process<
+    P,
+    NextStepSenderT,
+    NewInstanceReceiverT,
+    PersistenceManagerT,
+    DeadLetterManagerT,
+    OutboxT,
+>(
     instance_receiver: &NewInstanceReceiverT,
     next_step_sender: &NextStepSenderT,
     persistence_manager: &PersistenceManagerT,
+    dead_letter_manager: &DeadLetterManagerT,
+    outbox: Option<&OutboxT>,
 ) -> anyhow::Result<()>
 where
     P: Project,
     NextStepSenderT: NextStepSender<P>,
     NewInstanceReceiverT: NewInstanceReceiver<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    OutboxT: Outbox<P>,
 {
     let mut instance_receiver = instance_receiver.clone();
 
     let (step, handle) = instance_receiver.receive().await?;
+    let redelivery_count = handle.redelivery_count();
+    let in_flight = received("new_instance_worker", &handle);
+    let span = stage_span("new_instance_worker", step.trace_context.as_ref());
     let next_step_sender = next_step_sender.clone();
     let persistence_manager = persistence_manager.clone();
+    let dead_letter_manager = dead_letter_manager.clone();
+    let outbox = outbox.cloned();
 
-    tokio::spawn(async move {
-        if let Err(err) = process(
+    let task = async move {
+        let _in_flight = in_flight;
+        let result = process(
             &mut next_step_sender.clone(),
             &mut persistence_manager.clone(),
-            step,
+            outbox.as_ref(),
+            step.clone(),
         )
-        .await
-        {
-            tracing::error!("Error processing workflow instance: {:?}", err);
-        }
+        .await;
 
-        tracing::debug!("acknowledging new instance");
-        instance_receiver
-            .accept(handle)
-            .await
-            .inspect_err(|e| {
-                tracing::error!("Failed to acknowledge new instance: {:?}", e);
-            })
-            .unwrap();
-        tracing::debug!("acknowledged new instance");
-    });
+        let settlement = settlement::<P, _, _>(
+            "new_instance_worker",
+            Queue::NewInstance,
+            &dead_letter_manager,
+            &step,
+            redelivery_count,
+            result,
+        )
+        .await;
+        let stage = settlement.stage();
+        tracing::debug!("settling new instance ({stage})");
+        let settled = match settlement {
+            Settlement::Accept => instance_receiver.accept(handle).await,
+            Settlement::Reject(delay) => instance_receiver.reject(handle, Some(delay)).await,
+        };
+        if let Err(err) = settled {
+            tracing::error!("Failed to settle new instance ({stage}): {:?}", err);
+            record_error("new_instance_worker", stage);
+            return;
+        }
+        tracing::debug!("settled new instance ({stage})");
+    };
+    tokio::spawn(task.instrument(span));
     Ok(())
 }
@@ -1,11 +1,25 @@
+use std::sync::Arc;
+
 use adapter_types::{
+    dead_letters::{DeadLetterManager, Queue},
     dependencies::next_step_worker::NextStepWorkerDependencies,
+    health::Health,
     managers::{PersistenceManager, StepsAwaitingEventManager},
-    receivers::NextStepReceiver,
+    notifications::{NotificationKind, NotificationSender},
+    receivers::{DeliveryHandle, NextStepReceiver},
     senders::ActiveStepSender,
+    telemetry::current_trace_context,
 };
 use derive_more::Debug;
-use surgeflow_types::{__Step, FullyQualifiedStep, Immediate, Project};
+use surgeflow_types::{__Step, FullyQualifiedStep, Project, StepStatus};
+use tracing::Instrument;
+
+use super::{
+    failure_policy::{Settlement, WorkerError, record_error, settlement, with_backoff},
+    health::register_health_checks,
+    instrumentation::{received, stage_span},
+    notifications::notify,
+};
 
 pub async fn main<
     P,
@@ -13,6 +27,8 @@ pub async fn main<
     ActiveStepSenderT,
     StepsAwaitingEventManagerT,
     PersistenceManagerT,
+    DeadLetterManagerT,
+    NotificationSenderT,
 >(
     dependencies: NextStepWorkerDependencies<
         P,
@@ -20,7 +36,10 @@ pub async fn main<
         ActiveStepSenderT,
         StepsAwaitingEventManagerT,
         PersistenceManagerT,
+        DeadLetterManagerT,
+        NotificationSenderT,
     >,
+    health: Arc<Health>,
 ) -> anyhow::Result<()>
 where
     P: Project,
@@ -28,13 +47,28 @@ where
     ActiveStepSenderT: ActiveStepSender<P>,
     StepsAwaitingEventManagerT: StepsAwaitingEventManager<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     let next_step_receiver = dependencies.next_step_receiver;
     let active_step_sender = dependencies.active_step_sender;
     let steps_awaiting_event_manager = dependencies.steps_awaiting_event_manager;
     let persistence_manager = dependencies.persistence_manager;
+    let dead_letter_manager = dependencies.dead_letter_manager;
+    let notification_sender = dependencies.notification_sender;
+
+    let liveness = health.register_worker("next_step_worker");
+    register_health_checks!(
+        health,
+        "next_step_worker",
+        next_step_receiver,
+        active_step_sender,
+        steps_awaiting_event_manager,
+        persistence_manager
+    );
 
     loop {
+        liveness.beat();
         tracing::info!("Waiting for new step...");
         if let Err(err) = receive_and_process::<
             P,
@@ -42,11 +76,15 @@ where
             ActiveStepSenderT,
             StepsAwaitingEventManagerT,
             PersistenceManagerT,
+            DeadLetterManagerT,
+            NotificationSenderT,
         >(
             &next_step_receiver,
             &active_step_sender,
             &steps_awaiting_event_manager,
             &persistence_manager,
+            &dead_letter_manager,
+            notification_sender.as_ref(),
         )
         .await
         {
@@ -61,11 +99,15 @@ async fn receive_and_process<
     ActiveStepSenderT,
     StepsAwaitingEventManagerT,
     PersistenceManagerT,
+    DeadLetterManagerT,
+    NotificationSenderT,
 >(
     next_step_receiver: &NextStepReceiverT,
     active_step_sender: &ActiveStepSenderT,
     steps_awaiting_event_manager: &StepsAwaitingEventManagerT,
     persistence_manager: &PersistenceManagerT,
+    dead_letter_manager: &DeadLetterManagerT,
+    notification_sender: Option<&NotificationSenderT>,
 ) -> anyhow::Result<()>
 where
     P: Project,
@@ -73,37 +115,64 @@ where
     ActiveStepSenderT: ActiveStepSender<P>,
     StepsAwaitingEventManagerT: StepsAwaitingEventManager<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    DeadLetterManagerT: DeadLetterManager<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     let mut next_step_receiver = next_step_receiver.clone();
 
     let (step, handle) = next_step_receiver.receive().await?;
+    let redelivery_count = handle.redelivery_count();
+    let in_flight = received("next_step_worker", &handle);
+    let span = stage_span("next_step_worker", step.trace_context.as_ref());
     let active_step_sender = active_step_sender.clone();
     let steps_awaiting_event_manager = steps_awaiting_event_manager.clone();
     let persistence_manager = persistence_manager.clone();
+    let dead_letter_manager = dead_letter_manager.clone();
+    let notification_sender = notification_sender.cloned();
 
-    tokio::spawn(async move {
-        if let Err(err) =
-            process::<P, ActiveStepSenderT, StepsAwaitingEventManagerT, PersistenceManagerT>(
-                &mut active_step_sender.clone(),
-                &mut steps_awaiting_event_manager.clone(),
-                &mut persistence_manager.clone(),
-                step,
-            )
-            .await
-        {
-            tracing::error!("Error processing next step: {:?}", err);
-        }
+    let task = async move {
+        let _in_flight = in_flight;
+        let result = process::<
+            P,
+            ActiveStepSenderT,
+            StepsAwaitingEventManagerT,
+            PersistenceManagerT,
+            NotificationSenderT,
+        >(
+            &mut active_step_sender.clone(),
+            &mut steps_awaiting_event_manager.clone(),
+            &mut persistence_manager.clone(),
+            notification_sender.as_ref(),
+            step.clone(),
+        )
+        .await;
 
-        tracing::debug!("acknowledging next step for instance");
-        next_step_receiver
-            .accept(handle)
-            .await
-            .inspect_err(|e| {
-                tracing::error!("Failed to acknowledge next step: {:?}", e);
-            })
-            .unwrap();
-        tracing::debug!("acknowledged next step for instance");
-    });
+        let settlement = settlement::<P, _, _>(
+            "next_step_worker",
+            Queue::NextStep,
+            &dead_letter_manager,
+            &step,
+            redelivery_count,
+            result,
+        )
+        .await;
+        let stage = settlement.stage();
+        tracing::debug!("settling next step for instance ({stage})");
+        let settled = match settlement {
+            Settlement::Accept => next_step_receiver.accept(handle).await,
+            Settlement::Reject(delay) => next_step_receiver.reject(handle, Some(delay)).await,
+        };
+        if let Err(err) = settled {
+            tracing::error!(
+                "Failed to settle next step for instance ({stage}): {:?}",
+                err
+            );
+            record_error("next_step_worker", stage);
+            return;
+        }
+        tracing::debug!("settled next step for instance ({stage})");
+    };
+    tokio::spawn(task.instrument(span));
     Ok(())
 }
 
@@ -123,10 +192,27 @@ where
     AwaitEventError(#[source] StepsAwaitingEventManagerT::Error),
 }
 
-async fn process<P, ActiveStepSenderT, StepsAwaitingEventManagerT, PersistenceManagerT>(
+impl<P, ActiveStepSenderT, StepsAwaitingEventManagerT, PersistenceManagerT> WorkerError
+    for NextStepWorkerError<P, ActiveStepSenderT, StepsAwaitingEventManagerT, PersistenceManagerT>
+where
+    P: Project,
+    ActiveStepSenderT: ActiveStepSender<P>,
+    StepsAwaitingEventManagerT: StepsAwaitingEventManager<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+{
+}
+
+async fn process<
+    P,
+    ActiveStepSenderT,
+    StepsAwaitingEventManagerT,
+    PersistenceManagerT,
+    NotificationSenderT,
+>(
     active_step_sender: &mut ActiveStepSenderT,
     steps_awaiting_event_manager: &mut StepsAwaitingEventManagerT,
     persistence_manager: &mut PersistenceManagerT,
+    notification_sender: Option<&NotificationSenderT>,
     mut step: FullyQualifiedStep<P>,
 ) -> Result<
     (),
@@ -137,30 +223,50 @@ where
     ActiveStepSenderT: ActiveStepSender<P>,
     StepsAwaitingEventManagerT: StepsAwaitingEventManager<P>,
     PersistenceManagerT: PersistenceManager<P>,
+    NotificationSenderT: NotificationSender<P>,
 {
     tracing::debug!(
         "received next step for instance: {}",
         step.instance.external_id
     );
 
-    persistence_manager
-        .insert_step(step.instance.external_id, step.step_id, &step.step.step)
-        .await
+    let status = with_backoff!("insert step", persistence_manager.insert_step(&step))
         .map_err(NextStepWorkerError::DatabaseError)?;
+    // a step that is no longer pending was already dispatched, this is a redelivery
+    if status != StepStatus::Pending {
+        tracing::debug!("Step {} is already {:?}, skipping", step.step_id, status);
+        return Ok(());
+    }
 
     // TODO(semantics): this requires step be muttable. Would shadowing be better here?
     step.step.event = step.step.step.init_event();
 
     if step.step.event.is_some() {
-        active_step_sender
-            .send(step)
-            .await
+        step.trace_context = current_trace_context();
+        with_backoff!("send active step", active_step_sender.send(step.clone()))
             .map_err(NextStepWorkerError::SendActiveStepError)?;
     } else {
-        steps_awaiting_event_manager
-            .put_step(step)
-            .await
-            .map_err(NextStepWorkerError::AwaitEventError)?;
+        with_backoff!(
+            "put step awaiting event",
+            steps_awaiting_event_manager.put_step(step.clone())
+        )
+        .map_err(NextStepWorkerError::AwaitEventError)?;
+        with_backoff!(
+            "set step status",
+            persistence_manager.set_step_status(step.step_id, StepStatus::AwaitingEvent)
+        )
+        .map_err(NextStepWorkerError::DatabaseError)?;
+        notify(
+            "next_step_worker",
+            notification_sender,
+            step.instance.external_id,
+            NotificationKind::StepWaitingForEvent {
+                step_id: step.step_id,
+                step_type: step.step.step.step_type().into_owned(),
+                event_type: step.step.step.event_type().into_owned(),
+            },
+        )
+        .await;
     }
 
     Ok(())
@@ -0,0 +1,29 @@
+use adapter_types::notifications::{InstanceNotification, NotificationKind, NotificationSender};
+use surgeflow_types::{Project, WorkflowInstanceId};
+
+use super::failure_policy::record_error;
+
+/// Publishes a notification if the adapter supports them. Notifications are best effort, so
+/// failing to send one is logged instead of failing the message being processed.
+pub(crate) async fn notify<P, NotificationSenderT>(
+    worker: &'static str,
+    notification_sender: Option<&NotificationSenderT>,
+    instance_id: WorkflowInstanceId,
+    kind: NotificationKind,
+) where
+    P: Project,
+    NotificationSenderT: NotificationSender<P>,
+{
+    let Some(notification_sender) = notification_sender else {
+        return;
+    };
+    let notification = InstanceNotification::new(instance_id, kind);
+    if let Err(err) = notification_sender.send(notification).await {
+        tracing::warn!(
+            "Failed to send notification for instance {}: {:?}",
+            instance_id,
+            err
+        );
+        record_error(worker, "notify");
+    }
+}
@@ -0,0 +1,107 @@
+use std::{sync::Arc, time::Duration};
+
+use adapter_types::{
+    dependencies::outbox_relay_worker::OutboxRelayWorkerDependencies,
+    health::Health,
+    outbox::{Outbox, OutboxMessage},
+    senders::{CompletedInstanceSender, FailedInstanceSender, NextStepSender},
+};
+use surgeflow_types::Project;
+use tokio::time::sleep;
+
+use super::{
+    failure_policy::{record_error, with_backoff},
+    health::register_health_checks,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const BATCH_SIZE: u32 = 100;
+
+pub async fn main<P, OutboxT, NextStepSenderT, CompletedInstanceSenderT, FailedInstanceSenderT>(
+    dependencies: OutboxRelayWorkerDependencies<
+        P,
+        OutboxT,
+        NextStepSenderT,
+        CompletedInstanceSenderT,
+        FailedInstanceSenderT,
+    >,
+    health: Arc<Health>,
+) -> anyhow::Result<()>
+where
+    P: Project,
+    OutboxT: Outbox<P>,
+    NextStepSenderT: NextStepSender<P>,
+    CompletedInstanceSenderT: CompletedInstanceSender<P>,
+    FailedInstanceSenderT: FailedInstanceSender<P>,
+{
+    let Some(outbox) = dependencies.outbox else {
+        tracing::info!("No outbox configured, outbox relay worker exiting");
+        return Ok(());
+    };
+    let mut next_step_sender = dependencies.next_step_sender;
+    let completed_instance_sender = dependencies.completed_instance_sender;
+    let failed_instance_sender = dependencies.failed_instance_sender;
+
+    let liveness = health.register_worker("outbox_relay_worker");
+    register_health_checks!(
+        health,
+        "outbox_relay_worker",
+        next_step_sender,
+        completed_instance_sender,
+        failed_instance_sender
+    );
+
+    loop {
+        liveness.beat();
+        let entries = match with_backoff!("read outbox", outbox.unpublished(BATCH_SIZE)) {
+            Ok(entries) => entries,
+            Err(err) => {
+                tracing::error!("Error reading outbox: {:?}", err);
+                record_error("outbox_relay_worker", "read");
+                sleep(POLL_INTERVAL).await;
+                continue;
+            }
+        };
+        if entries.is_empty() {
+            sleep(POLL_INTERVAL).await;
+            continue;
+        }
+
+        for entry in entries {
+            // messages are published at least once: a crash between sending and marking the
+            // entry as published sends it again, which the receiving workers deduplicate
+            let sent = match entry.message {
+                OutboxMessage::NextStep(step) => {
+                    with_backoff!("send next step", next_step_sender.send(step.clone()))
+                        .map_err(anyhow::Error::from)
+                }
+                OutboxMessage::CompletedInstance(instance) => with_backoff!(
+                    "send completed instance",
+                    completed_instance_sender.send(instance.clone())
+                )
+                .map_err(anyhow::Error::from),
+                OutboxMessage::FailedInstance(instance) => with_backoff!(
+                    "send failed instance",
+                    failed_instance_sender.send(instance.clone())
+                )
+                .map_err(anyhow::Error::from),
+            };
+            if let Err(err) = sent {
+                tracing::error!("Error publishing outbox entry {}: {:?}", entry.id, err);
+                record_error("outbox_relay_worker", "publish");
+                // keep the order, the rest of the batch is retried on the next poll
+                break;
+            }
+
+            if let Err(err) = with_backoff!("mark published", outbox.mark_published(entry.id)) {
+                tracing::error!(
+                    "Error marking outbox entry {} published: {:?}",
+                    entry.id,
+                    err
+                );
+                record_error("outbox_relay_worker", "mark_published");
+                break;
+            }
+        }
+    }
+}
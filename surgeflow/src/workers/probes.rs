@@ -0,0 +1,50 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use adapter_types::health::Health;
+use control_server::probe_router;
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use tokio::net::TcpListener;
+
+/// Buckets of the duration histograms, in seconds.
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0,
+];
+/// How often the recorder drains its histogram buffers.
+const METRICS_UPKEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Installs the global recorder the workers' metrics go to. If it can't be installed, recording
+/// metrics does nothing and `/metrics` responds with 501.
+pub(crate) fn install_metrics_recorder() -> Option<PrometheusHandle> {
+    let recorder = PrometheusBuilder::new()
+        .set_buckets(DURATION_BUCKETS)
+        .and_then(|builder| builder.install_recorder());
+    match recorder {
+        Ok(handle) => {
+            let upkeep = handle.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(METRICS_UPKEEP_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    upkeep.run_upkeep();
+                }
+            });
+            Some(handle)
+        }
+        Err(err) => {
+            tracing::warn!("couldn't install the metrics recorder, /metrics is disabled: {err}");
+            None
+        }
+    }
+}
+
+/// Serves the health and metrics endpoints on `bind`, for processes without the control server.
+pub(crate) async fn main(
+    bind: SocketAddr,
+    health: Arc<Health>,
+    metrics: Option<PrometheusHandle>,
+) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(bind).await?;
+    tracing::info!("Serving health and metrics endpoints on {bind}");
+    axum::serve(listener, probe_router(health, metrics)).await?;
+    Ok(())
+}
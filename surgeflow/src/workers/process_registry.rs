@@ -0,0 +1,142 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    sync::{Arc, Mutex},
+};
+
+use adapter_types::{
+    health::Health,
+    registry::{HEARTBEAT_INTERVAL, WorkerProcess, WorkerProcessId, WorkerRegistry},
+};
+use chrono::Utc;
+use surgeflow_types::{Project, TaskQueue};
+use tokio::time::interval;
+
+use crate::config::Worker;
+
+/// Messages each worker of this process is processing.
+static IN_FLIGHT: Mutex<BTreeMap<&'static str, u64>> = Mutex::new(BTreeMap::new());
+
+// only the queue workers receive messages
+#[cfg_attr(
+    not(any(
+        feature = "active_step_worker",
+        feature = "new_instance_worker",
+        feature = "next_step_worker",
+        feature = "new_event_worker",
+        feature = "completed_step_worker",
+        feature = "failed_step_worker",
+        feature = "failed_instance_worker",
+        feature = "completed_instance_worker",
+    )),
+    allow(dead_code)
+)]
+pub(crate) fn in_flight_started(worker: &'static str) {
+    *IN_FLIGHT
+        .lock()
+        .expect("in flight lock poisoned")
+        .entry(worker)
+        .or_default() += 1;
+}
+
+#[cfg_attr(
+    not(any(
+        feature = "active_step_worker",
+        feature = "new_instance_worker",
+        feature = "next_step_worker",
+        feature = "new_event_worker",
+        feature = "completed_step_worker",
+        feature = "failed_step_worker",
+        feature = "failed_instance_worker",
+        feature = "completed_instance_worker",
+    )),
+    allow(dead_code)
+)]
+pub(crate) fn in_flight_finished(worker: &'static str) {
+    // don't panic while unwinding from a panicking task
+    if let Ok(mut in_flight) = IN_FLIGHT.lock()
+        && let Some(count) = in_flight.get_mut(worker)
+    {
+        *count = count.saturating_sub(1);
+    }
+}
+
+/// This process, as first registered.
+pub(crate) fn this_process<P: Project>(
+    workers: &BTreeSet<Worker>,
+    task_queues: &[TaskQueue],
+) -> WorkerProcess {
+    let now = Utc::now();
+    WorkerProcess {
+        id: WorkerProcessId::new(),
+        hostname: env::var("HOSTNAME").ok(),
+        pid: std::process::id(),
+        version: P::VERSION.to_string(),
+        workers: workers
+            .iter()
+            .map(|worker| worker.name().to_string())
+            .collect(),
+        task_queues: if workers.contains(&Worker::ActiveStep) {
+            task_queues.to_vec()
+        } else {
+            Vec::new()
+        },
+        in_flight: BTreeMap::new(),
+        started_at: now,
+        last_heartbeat: now,
+    }
+}
+
+/// Heartbeats `process` until the process stops. A failed heartbeat is retried on the next
+/// one, as the registry only reports which processes are alive.
+pub(crate) async fn main<P, WorkerRegistryT>(
+    worker_registry: WorkerRegistryT,
+    mut process: WorkerProcess,
+    health: Arc<Health>,
+) -> anyhow::Result<()>
+where
+    P: Project,
+    WorkerRegistryT: WorkerRegistry<P>,
+{
+    let liveness = health.register_worker("worker_registry");
+    health.register_check(
+        "worker_registry",
+        "worker_registry",
+        worker_registry.clone(),
+        |worker_registry| async move { worker_registry.health_check().await },
+    );
+    tracing::info!("Registering worker process {}", process.id);
+
+    let mut heartbeats = interval(HEARTBEAT_INTERVAL);
+    loop {
+        heartbeats.tick().await;
+        liveness.beat();
+        process.last_heartbeat = Utc::now();
+        process.in_flight = IN_FLIGHT
+            .lock()
+            .expect("in flight lock poisoned")
+            .iter()
+            .map(|(worker, count)| (worker.to_string(), *count))
+            .collect();
+        if let Err(err) = worker_registry.heartbeat(&process).await {
+            tracing::error!(
+                "Failed to heartbeat worker process {}: {:?}",
+                process.id,
+                err
+            );
+        }
+    }
+}
+
+/// Removes this process from the registry, when it stops.
+pub(crate) async fn deregister<P, WorkerRegistryT>(
+    worker_registry: &WorkerRegistryT,
+    id: WorkerProcessId,
+) where
+    P: Project,
+    WorkerRegistryT: WorkerRegistry<P>,
+{
+    if let Err(err) = worker_registry.deregister(id).await {
+        tracing::error!("Failed to deregister worker process {id}: {:?}", err);
+    }
+}
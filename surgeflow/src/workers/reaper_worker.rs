@@ -0,0 +1,190 @@
+use std::sync::Arc;
+
+use adapter_types::{
+    dependencies::reaper_worker::ReaperWorkerDependencies,
+    health::Health,
+    managers::PersistenceManager,
+    senders::{ActiveStepSender, FailedStepSender, NextStepSender},
+};
+use surgeflow_types::{FullyQualifiedStep, Project};
+use tokio::time::sleep;
+
+use crate::config::ReaperConfig;
+
+use super::{
+    attempts::fail_attempt,
+    failure_policy::{record_error, with_backoff},
+    health::register_health_checks,
+};
+
+/// Why lost attempts failed, stored as their outcome.
+const LOST_ATTEMPT_ERROR: &str = "no heartbeat within the heartbeat timeout, attempt lost";
+
+pub async fn main<P, NextStepSenderT, ActiveStepSenderT, FailedStepSenderT, PersistenceManagerT>(
+    dependencies: ReaperWorkerDependencies<
+        P,
+        NextStepSenderT,
+        ActiveStepSenderT,
+        FailedStepSenderT,
+        PersistenceManagerT,
+    >,
+    config: ReaperConfig,
+    health: Arc<Health>,
+) -> anyhow::Result<()>
+where
+    P: Project,
+    NextStepSenderT: NextStepSender<P>,
+    ActiveStepSenderT: ActiveStepSender<P>,
+    FailedStepSenderT: FailedStepSender<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+{
+    let mut next_step_sender = dependencies.next_step_sender;
+    let mut active_step_sender = dependencies.active_step_sender;
+    let mut failed_step_sender = dependencies.failed_step_sender;
+    let mut persistence_manager = dependencies.persistence_manager;
+
+    let liveness = health.register_worker("reaper_worker");
+    register_health_checks!(
+        health,
+        "reaper_worker",
+        next_step_sender,
+        active_step_sender,
+        failed_step_sender,
+        persistence_manager
+    );
+
+    let stale_attempt_error = format!(
+        "still running after {:?}, attempt lost",
+        config.stale_attempt_threshold
+    );
+
+    loop {
+        liveness.beat();
+        sleep(config.sweep_interval).await;
+
+        // attempts whose worker stopped heartbeating, e.g. because it died
+        match with_backoff!(
+            "claim lost step attempts",
+            persistence_manager.claim_lost_step_attempts(
+                config.lost_attempt_grace,
+                config.reclaim_after,
+                config.batch_size,
+                LOST_ATTEMPT_ERROR
+            )
+        ) {
+            Ok(steps) => {
+                for step in steps {
+                    tracing::warn!("Step {} missed its heartbeat deadline", step.step_id);
+                    let result = recover_attempt(
+                        &mut active_step_sender,
+                        &mut failed_step_sender,
+                        &mut persistence_manager,
+                        step,
+                        LOST_ATTEMPT_ERROR,
+                    )
+                    .await;
+                    if let Err(err) = result {
+                        tracing::error!("Error reaping lost step attempt: {:?}", err);
+                        record_error("reaper_worker", "lost_attempt");
+                    }
+                }
+            }
+            Err(err) => {
+                tracing::error!("Error claiming lost step attempts: {:?}", err);
+                record_error("reaper_worker", "lost_attempt");
+            }
+        }
+
+        // attempts without a heartbeat timeout that have been running for too long. Steps with
+        // a heartbeat timeout are left to the sweep above, as they can run for as long as they
+        // heartbeat
+        match with_backoff!(
+            "claim stale step attempts",
+            persistence_manager.claim_stale_step_attempts(
+                config.stale_attempt_threshold,
+                config.reclaim_after,
+                config.batch_size,
+                &stale_attempt_error
+            )
+        ) {
+            Ok(steps) => {
+                for step in steps {
+                    tracing::warn!("Step {} has been running for too long", step.step_id);
+                    let result = recover_attempt(
+                        &mut active_step_sender,
+                        &mut failed_step_sender,
+                        &mut persistence_manager,
+                        step,
+                        &stale_attempt_error,
+                    )
+                    .await;
+                    if let Err(err) = result {
+                        tracing::error!("Error reaping stale step attempt: {:?}", err);
+                        record_error("reaper_worker", "stale_attempt");
+                    }
+                }
+            }
+            Err(err) => {
+                tracing::error!("Error claiming stale step attempts: {:?}", err);
+                record_error("reaper_worker", "stale_attempt");
+            }
+        }
+
+        // steps that were inserted but never made it to the active queue or the awaiting-event
+        // manager
+        match with_backoff!(
+            "claim stale pending steps",
+            persistence_manager
+                .claim_stale_pending_steps(config.stale_pending_threshold, config.batch_size)
+        ) {
+            Ok(steps) => {
+                for step in steps {
+                    tracing::warn!("Step {} has been pending for too long", step.step_id);
+                    // the next step worker dispatches it again, since it is still pending
+                    let result =
+                        with_backoff!("send next step", next_step_sender.send(step.clone()));
+                    if let Err(err) = result {
+                        tracing::error!("Error re-enqueuing stale pending step: {:?}", err);
+                        record_error("reaper_worker", "stale_pending");
+                    }
+                }
+            }
+            Err(err) => {
+                tracing::error!("Error claiming stale pending steps: {:?}", err);
+                record_error("reaper_worker", "stale_pending");
+            }
+        }
+    }
+}
+
+/// Retries or fails the step of a claimed attempt, then settles the claim. Until it is settled,
+/// the attempt is claimed again after [`ReaperConfig::reclaim_after`], so a step that couldn't be
+/// forwarded is retried by a later sweep instead of being stranded.
+async fn recover_attempt<P, ActiveStepSenderT, FailedStepSenderT, PersistenceManagerT>(
+    active_step_sender: &mut ActiveStepSenderT,
+    failed_step_sender: &mut FailedStepSenderT,
+    persistence_manager: &mut PersistenceManagerT,
+    step: FullyQualifiedStep<P>,
+    error: &str,
+) -> anyhow::Result<()>
+where
+    P: Project,
+    ActiveStepSenderT: ActiveStepSender<P>,
+    FailedStepSenderT: FailedStepSender<P>,
+    PersistenceManagerT: PersistenceManager<P>,
+{
+    let (step_id, retry_count) = (step.step_id, step.retry_count);
+    fail_attempt(
+        active_step_sender,
+        failed_step_sender,
+        persistence_manager,
+        step,
+        error,
+    )
+    .await?;
+    with_backoff!(
+        "settle claimed step attempt",
+        persistence_manager.settle_claimed_step_attempt(step_id, retry_count)
+    )?;
+    Ok(())
+}
@@ -2,10 +2,17 @@ use bon::builder;
 use derive_more::{Debug, Display, From, Into};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::fmt::{self};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
 use uuid::Uuid;
 
+#[cfg(feature = "testing")]
+pub mod testing;
+
 #[derive(
     Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, From, Into, JsonSchema,
 )]
@@ -50,6 +57,18 @@ impl StepId {
     pub fn new() -> Self {
         Self(Uuid::new_v4())
     }
+
+    /// The id of an instance's first step. Deterministic, so a redelivered instance creation
+    /// yields the same step.
+    pub fn entrypoint_of(instance_id: WorkflowInstanceId) -> Self {
+        Self(Uuid::new_v5(&instance_id.0, b"entrypoint"))
+    }
+
+    /// The id of the step that follows `previous`. Deterministic, so a redelivered completion
+    /// yields the same successor instead of forking the instance.
+    pub fn successor_of(previous: StepId) -> Self {
+        Self(Uuid::new_v5(&previous.0, b"next"))
+    }
 }
 
 impl Default for StepId {
@@ -58,6 +77,76 @@ impl Default for StepId {
     }
 }
 
+/// An instance id chosen by the client, such as `order-1234`. Unlike a [`WorkflowInstanceId`],
+/// later instances of the same workflow can take it over, as the workflow's [`IdReusePolicy`]
+/// allows.
+#[derive(
+    Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, From, Into, JsonSchema, Display,
+)]
+#[serde(transparent)]
+pub struct BusinessId(String);
+
+impl BusinessId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// The queue a step runs from. Active step workers subscribe to a set of task queues, so steps
+/// with special needs, like a GPU or a rate limited API, can run on their own pool of workers.
+#[derive(
+    Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, From, Into, JsonSchema, Display,
+)]
+#[serde(transparent)]
+pub struct TaskQueue(String);
+
+impl TaskQueue {
+    /// Where steps run unless they choose a task queue.
+    pub const DEFAULT: &str = "default";
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for TaskQueue {
+    fn default() -> Self {
+        Self(Self::DEFAULT.to_string())
+    }
+}
+
+impl From<&str> for TaskQueue {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+/// When a new instance may take over the [`BusinessId`] of an earlier instance of the same
+/// workflow. The id of a running instance is never taken over.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum IdReusePolicy {
+    /// Once the earlier instance completed or failed.
+    #[default]
+    RejectWhileRunning,
+    /// Once the earlier instance completed, but not if it failed.
+    AllowAfterCompletion,
+    /// Once the earlier instance failed, to run it again, but not if it completed.
+    AllowAfterFailureOnly,
+}
+
+impl IdReusePolicy {
+    /// Whether the id of an instance with `status` can be taken over.
+    pub fn allows_reuse(self, status: InstanceStatus) -> bool {
+        match (self, status) {
+            (_, InstanceStatus::Running) => false,
+            (Self::RejectWhileRunning, _) => true,
+            (Self::AllowAfterCompletion, status) => status == InstanceStatus::Completed,
+            (Self::AllowAfterFailureOnly, status) => status == InstanceStatus::Failed,
+        }
+    }
+}
+
 #[derive(
     Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, From, Into, JsonSchema, Display,
 )]
@@ -68,6 +157,10 @@ pub struct WorkflowId(i32);
 pub trait Project: Sized + Send + Sync + 'static + Clone {
     type Workflow: __Workflow<Self>;
 
+    /// The version of the project's code, reported by the worker processes running it. Usually
+    /// `env!("CARGO_PKG_VERSION")`.
+    const VERSION: &'static str = "unknown";
+
     ///
     fn workflow_for_step(
         &self,
@@ -128,6 +221,8 @@ where
         + TryFrom<<P::Workflow as __Workflow<P>>::Step>;
     const NAME: &'static str;
     const WORKFLOW_STATIC: <Self as __Workflow<P>>::WorkflowStatic;
+    /// When instances created with a [`BusinessId`] may take over the id of an earlier one.
+    const ID_REUSE_POLICY: IdReusePolicy = IdReusePolicy::RejectWhileRunning;
 
     fn entrypoint() -> RawStep<P, P::Workflow>;
 }
@@ -255,9 +350,24 @@ where
         &self,
         wf: W,
         event: <Self as __Step<P, W>>::Event,
+        ctx: StepContext,
     ) -> impl Future<Output = Result<Option<RawStep<P, W>>, <Self as __Step<P, W>>::Error>> + Send;
 
     fn event_is_event(&self, event: &Self::Event) -> bool;
+
+    /// The name of this step's type, as reported by the control server. Step enums must
+    /// override this to name the step they hold, or every step of a workflow reports the enum:
+    /// the `workflow` macro generates a `step_type` on the enums it builds for this.
+    fn step_type(&self) -> Cow<'static, str> {
+        Cow::Borrowed(std::any::type_name::<Self>())
+    }
+
+    /// The name of the event type this step waits for, as reported by the control server. Step
+    /// enums must override this like [`step_type`](Self::step_type), with the generated
+    /// `event_type`.
+    fn event_type(&self) -> Cow<'static, str> {
+        <Self::Event as JsonSchema>::schema_name()
+    }
 }
 
 pub trait Step<P: Project, W: __Workflow<P>>:
@@ -284,6 +394,7 @@ where
         &self,
         wf: W,
         event: <Self as __Step<P, W>>::Event,
+        ctx: StepContext,
     ) -> impl Future<Output = Result<Option<RawStep<P, W>>, <Self as __Step<P, W>>::Error>> + Send;
 
     fn init_event(&self) -> Option<<Self as Step<P, W>>::Event> {
@@ -316,29 +427,204 @@ impl<P: Project, W: __Workflow<P>, E: Event<P, W>> __Event<P, W> for E {
 
 ////////////////////////////////////////////////
 
+/// Progress a step can report along with a heartbeat.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct HeartbeatDetails {
+    /// Fraction of the work done, from 0 to 1.
+    pub progress: Option<f64>,
+    pub message: Option<String>,
+}
+
+/// Receives the heartbeats of a running step. Implemented by the runtime.
+pub trait HeartbeatSink: Send + Sync + 'static {
+    fn heartbeat(&self, details: HeartbeatDetails);
+}
+
+/// User-defined key/value metadata of an instance.
+pub type Tags = BTreeMap<String, String>;
+
+/// Changes to the tags of an instance. `None` removes the tag.
+pub type TagUpdates = BTreeMap<String, Option<String>>;
+
+/// The context a step attempt runs in.
+#[derive(Debug, Clone)]
+pub struct StepContext {
+    pub instance_id: WorkflowInstanceId,
+    pub step_id: StepId,
+    /// Starts at 1.
+    pub attempt: u32,
+    #[debug(skip)]
+    heartbeat_sink: Arc<dyn HeartbeatSink>,
+    tag_updates: Arc<Mutex<TagUpdates>>,
+}
+
+impl StepContext {
+    pub fn new(
+        instance_id: WorkflowInstanceId,
+        step_id: StepId,
+        attempt: u32,
+        heartbeat_sink: Arc<dyn HeartbeatSink>,
+    ) -> Self {
+        Self {
+            instance_id,
+            step_id,
+            attempt,
+            heartbeat_sink,
+            tag_updates: Arc::default(),
+        }
+    }
+
+    /// Tells the runtime the step is still making progress. A step with a heartbeat timeout
+    /// has to call this more often than the timeout, or its attempt is considered lost.
+    pub fn heartbeat(&self) {
+        self.heartbeat_with(HeartbeatDetails::default());
+    }
+
+    pub fn heartbeat_with(&self, details: HeartbeatDetails) {
+        self.heartbeat_sink.heartbeat(details);
+    }
+
+    /// Sets a tag on the instance. Tag changes are persisted once the attempt succeeds.
+    pub fn set_tag(&self, key: impl Into<String>, value: impl Into<String>) {
+        self.update_tag(key.into(), Some(value.into()));
+    }
+
+    /// Removes a tag from the instance. Tag changes are persisted once the attempt succeeds.
+    pub fn remove_tag(&self, key: impl Into<String>) {
+        self.update_tag(key.into(), None);
+    }
+
+    fn update_tag(&self, key: String, value: Option<String>) {
+        self.tag_updates
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(key, value);
+    }
+
+    /// The tag changes made through this context and its clones so far.
+    pub fn tag_updates(&self) -> TagUpdates {
+        self.tag_updates
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+////////////////////////////////////////////////
+
 #[builder]
 pub fn next_step<P: Project, W: __Workflow<P>>(
     #[builder(into, start_fn)] step: W::Step,
     max_retries: u32,
+    heartbeat_timeout: Option<Duration>,
+    #[builder(default, into)] task_queue: TaskQueue,
     #[builder(into)] event: Option<<W::Step as __Step<P, W>>::Event>,
 ) -> RawStep<P, W> {
     RawStep {
         step,
-        settings: StepSettings { max_retries },
+        settings: StepSettings {
+            max_retries,
+            heartbeat_timeout,
+            task_queue,
+        },
         event,
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct StepSettings {
     pub max_retries: u32,
+    /// How long an attempt may go without a heartbeat before it is considered lost and retried.
+    /// `None` disables heartbeat checking.
+    #[serde(default)]
+    pub heartbeat_timeout: Option<Duration>,
+    /// Only active step workers subscribed to this queue run the step.
+    #[serde(default)]
+    pub task_queue: TaskQueue,
     // TODO
     // pub delay: Option<Duration>,
     // TODO
     // backoff: u32,
 }
 
+/// Where a step is in its lifecycle, as tracked by persistence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum StepStatus {
+    /// Inserted, and either queued to run or about to be.
+    Pending,
+    /// Waiting for an event before it can run.
+    AwaitingEvent,
+    Running,
+    Completed,
+    Failed,
+}
+
+/// Stable numeric codes, for adapters that store the status as an integer.
+impl From<StepStatus> for i32 {
+    fn from(status: StepStatus) -> Self {
+        match status {
+            StepStatus::Pending => 1,
+            StepStatus::AwaitingEvent => 2,
+            StepStatus::Running => 3,
+            StepStatus::Completed => 4,
+            StepStatus::Failed => 5,
+        }
+    }
+}
+
+impl TryFrom<i32> for StepStatus {
+    type Error = i32;
+
+    fn try_from(code: i32) -> Result<Self, Self::Error> {
+        match code {
+            1 => Ok(StepStatus::Pending),
+            2 => Ok(StepStatus::AwaitingEvent),
+            3 => Ok(StepStatus::Running),
+            4 => Ok(StepStatus::Completed),
+            5 => Ok(StepStatus::Failed),
+            code => Err(code),
+        }
+    }
+}
+
+/// Where an instance is in its lifecycle. Instances are running until the completed or failed
+/// instance worker records otherwise: an instance completes once a step completes without a
+/// successor, and fails once one of its steps runs out of retries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum InstanceStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+/// Stable numeric codes, for adapters that store the status as an integer.
+impl From<InstanceStatus> for i32 {
+    fn from(status: InstanceStatus) -> Self {
+        match status {
+            InstanceStatus::Running => 1,
+            InstanceStatus::Completed => 2,
+            InstanceStatus::Failed => 3,
+        }
+    }
+}
+
+impl TryFrom<i32> for InstanceStatus {
+    type Error = i32;
+
+    fn try_from(code: i32) -> Result<Self, Self::Error> {
+        match code {
+            1 => Ok(InstanceStatus::Running),
+            2 => Ok(InstanceStatus::Completed),
+            3 => Ok(InstanceStatus::Failed),
+            code => Err(code),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(bound = "")]
 pub struct FullyQualifiedStep<P: Project> {
     pub instance: WorkflowInstance<P>,
     pub step_id: StepId,
@@ -346,6 +632,9 @@ pub struct FullyQualifiedStep<P: Project> {
     pub retry_count: u32,
     pub previous_step_id: Option<StepId>,
     pub next_step: Option<RawStep<P, P::Workflow>>,
+    /// The span that sent this step.
+    #[serde(default)]
+    pub trace_context: Option<TraceContext>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -367,17 +656,41 @@ impl<P: Project, W: __Workflow<P>> Event<P, W> for Immediate {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct InstanceEvent<P: Project> {
     #[serde(bound = "")]
     pub event: <<P::Workflow as __Workflow<P>>::Step as __Step<P, P::Workflow>>::Event,
     pub instance_id: WorkflowInstanceId,
+    /// The span that sent this event, usually the control server request.
+    #[serde(default)]
+    pub trace_context: Option<TraceContext>,
 }
 
 #[derive(Debug, Deserialize, Serialize, JsonSchema, Clone)]
+#[serde(bound = "")]
 pub struct WorkflowInstance<P: Project> {
     pub external_id: WorkflowInstanceId,
     pub workflow: <P::Workflow as __Workflow<P>>::WorkflowStatic,
+    /// The tags the instance was created with. Steps can change them later, so the current
+    /// tags are the ones persisted.
+    #[serde(default)]
+    pub tags: Tags,
+    /// Set if the client chose an id for the instance.
+    #[serde(default)]
+    pub business_id: Option<BusinessId>,
+    /// The span that sent this instance: the control server request that created it, then the
+    /// worker that completed or failed it.
+    #[serde(default)]
+    pub trace_context: Option<TraceContext>,
+}
+
+/// A [W3C trace context](https://www.w3.org/TR/trace-context/), carried in queue messages so the
+/// spans of every worker that handles an instance join one trace.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct TraceContext {
+    pub traceparent: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tracestate: Option<String>,
 }
 #[derive(Debug, Deserialize, Serialize, JsonSchema, Clone, From, Into, PartialEq, Eq)]
 #[serde(transparent)]
@@ -408,3 +721,50 @@ impl From<&str> for WorkflowName {
 
 // #[concrete(IAmGeneric)]
 // mod IamConcreteB { type A = crate::B; }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn step_ids_are_deterministic() {
+        let instance_id = WorkflowInstanceId::new();
+        let entrypoint = StepId::entrypoint_of(instance_id);
+        assert_eq!(entrypoint, StepId::entrypoint_of(instance_id));
+        assert_eq!(
+            StepId::successor_of(entrypoint),
+            StepId::successor_of(entrypoint)
+        );
+    }
+
+    #[test]
+    fn step_ids_differ_between_instances_and_steps() {
+        let entrypoint = StepId::entrypoint_of(WorkflowInstanceId::new());
+        let successor = StepId::successor_of(entrypoint);
+        assert_ne!(entrypoint, StepId::entrypoint_of(WorkflowInstanceId::new()));
+        assert_ne!(successor, entrypoint);
+        assert_ne!(StepId::successor_of(successor), successor);
+        assert_ne!(StepId::successor_of(successor), entrypoint);
+    }
+
+    #[test]
+    fn running_instances_never_give_up_their_id() {
+        for policy in [
+            IdReusePolicy::RejectWhileRunning,
+            IdReusePolicy::AllowAfterCompletion,
+            IdReusePolicy::AllowAfterFailureOnly,
+        ] {
+            assert!(!policy.allows_reuse(InstanceStatus::Running));
+        }
+    }
+
+    #[test]
+    fn finished_instances_give_up_their_id_as_the_policy_allows() {
+        assert!(IdReusePolicy::RejectWhileRunning.allows_reuse(InstanceStatus::Completed));
+        assert!(IdReusePolicy::RejectWhileRunning.allows_reuse(InstanceStatus::Failed));
+        assert!(IdReusePolicy::AllowAfterCompletion.allows_reuse(InstanceStatus::Completed));
+        assert!(!IdReusePolicy::AllowAfterCompletion.allows_reuse(InstanceStatus::Failed));
+        assert!(!IdReusePolicy::AllowAfterFailureOnly.allows_reuse(InstanceStatus::Completed));
+        assert!(IdReusePolicy::AllowAfterFailureOnly.allows_reuse(InstanceStatus::Failed));
+    }
+}
@@ -0,0 +1,87 @@
+//! A project with a single workflow of a single step, for the tests of the crates built on these
+//! types. Enabled by the `testing` feature.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{__Step, Immediate, Project, RawStep, StepContext, StepSettings, TryFromRef, Workflow};
+
+#[derive(Debug, Clone)]
+pub struct TestProject;
+
+impl Project for TestProject {
+    type Workflow = TestWorkflow;
+
+    fn workflow_for_step(&self, _: &TestStep) -> TestWorkflow {
+        TestWorkflow
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TestWorkflow;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+pub struct TestWorkflowStatic;
+
+impl Workflow<TestProject> for TestWorkflow {
+    type WorkflowStatic = TestWorkflowStatic;
+    type Step = TestStep;
+    const NAME: &'static str = "test";
+    const WORKFLOW_STATIC: TestWorkflowStatic = TestWorkflowStatic;
+
+    fn entrypoint() -> RawStep<TestProject, TestWorkflow> {
+        TestStep(0).raw()
+    }
+}
+
+/// Completes the instance when run. The number tells steps apart.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestStep(pub u32);
+
+impl TestStep {
+    pub fn raw(self) -> RawStep<TestProject, TestWorkflow> {
+        RawStep {
+            step: self,
+            event: None,
+            settings: StepSettings {
+                max_retries: 0,
+                heartbeat_timeout: None,
+                task_queue: Default::default(),
+            },
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("test step failed")]
+pub struct TestStepError;
+
+impl __Step<TestProject, TestWorkflow> for TestStep {
+    type Event = Immediate;
+    type Error = TestStepError;
+
+    fn init_event(&self) -> Option<Immediate> {
+        Some(Immediate)
+    }
+
+    async fn run(
+        &self,
+        _: TestWorkflow,
+        _: Immediate,
+        _: StepContext,
+    ) -> Result<Option<RawStep<TestProject, TestWorkflow>>, TestStepError> {
+        Ok(None)
+    }
+
+    fn event_is_event(&self, _: &Immediate) -> bool {
+        true
+    }
+}
+
+impl TryFromRef<Immediate> for Immediate {
+    type Error = std::convert::Infallible;
+
+    fn try_from_ref(value: &Immediate) -> Result<&Self, Self::Error> {
+        Ok(value)
+    }
+}